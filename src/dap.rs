@@ -0,0 +1,1109 @@
+use crate::json::Json;
+use crate::{config_for, HISTORY};
+use emulator::{
+    assemble_file, compile_octo_file, disassemble_instruction, Command, Condition, Config,
+    Debugger, EmulatorError, Framebuffer, Op, Program, Stop, Syntax, System, Video, Watch,
+    Watchpoint,
+};
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// the variablesReference of each scope
+const REGISTERS: i64 = 1;
+const TIMERS: i64 = 2;
+const STACK: i64 = 3;
+
+// chip8 dap, a debug adapter protocol server on stdin and stdout
+pub(crate) fn dap() -> Result<(), EmulatorError> {
+    let mut conn = Connection {
+        messages: read_messages(),
+        seq: 0,
+    };
+
+    // nothing can be done before there is a program to debug
+    while let Ok(request) = conn.messages.recv() {
+        match request.get("command").as_str().unwrap_or("") {
+            "initialize" => conn.respond(
+                &request,
+                Json::object([
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsSetVariable", true.into()),
+                    ("supportsReadMemoryRequest", true.into()),
+                    ("supportsDisassembleRequest", true.into()),
+                    ("supportsInstructionBreakpoints", true.into()),
+                    ("supportsConditionalBreakpoints", true.into()),
+                    ("supportsDataBreakpoints", true.into()),
+                    ("supportsDataBreakpointBytes", true.into()),
+                    ("supportsSteppingGranularity", true.into()),
+                    ("supportsTerminateRequest", true.into()),
+                    ("supportsStepBack", true.into()),
+                ]),
+            ),
+            "launch" => match Launch::parse(request.get("arguments")) {
+                Ok(launch) if launch.headless => {
+                    let system = System::create(Framebuffer::create(), launch.config);
+                    return Session::start(system, launch, conn, &request);
+                }
+                Ok(launch) => return windowed(launch, conn, &request),
+                Err(e) => conn.fail(&request, &e),
+            },
+            "disconnect" | "terminate" => {
+                conn.respond(&request, Json::Null);
+                return Ok(());
+            }
+            _ => conn.fail(&request, "launch a program first"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "sdl")]
+fn windowed(launch: Launch, conn: Connection, request: &Json) -> Result<(), EmulatorError> {
+    let display = emulator::Display::create("Chip-8".to_string(), 10);
+    Session::start(
+        System::create(display, launch.config),
+        launch,
+        conn,
+        request,
+    )
+}
+
+// without SDL there is no window to show, the program always runs headless
+#[cfg(not(feature = "sdl"))]
+fn windowed(launch: Launch, conn: Connection, request: &Json) -> Result<(), EmulatorError> {
+    let system = System::create(Framebuffer::create(), launch.config);
+    Session::start(system, launch, conn, request)
+}
+
+// read messages on their own thread so the program can keep running while
+// waiting for the next request. The channel closes at the end of input.
+fn read_messages() -> Receiver<Json> {
+    let (send, recv) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+
+        loop {
+            // headers up to a blank line, only Content-Length matters
+            let mut len = None;
+            loop {
+                let mut line = String::new();
+                match stdin.read_line(&mut line) {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) => {}
+                }
+
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("Content-Length:") {
+                    len = value.trim().parse().ok();
+                }
+            }
+
+            let mut body = vec![0; len.unwrap_or(0)];
+            if stdin.read_exact(&mut body).is_err() {
+                return;
+            }
+
+            let message = String::from_utf8(body)
+                .map_err(|_| "a message isn't utf-8".to_string())
+                .and_then(|body| Json::parse(&body));
+            match message {
+                Ok(message) => {
+                    if send.send(message).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => eprintln!("bad message: {}", e),
+            }
+        }
+    });
+
+    recv
+}
+
+// standard base64, for the data of readMemory
+fn base64(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(DIGITS[(n >> (18 - 6 * i)) as usize & 0x3F] as char),
+                false => out.push('='),
+            }
+        }
+    }
+
+    out
+}
+
+// a number given as text, hex with 0x in front and decimal otherwise
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// a breakpoint at addr, with its condition checked if it has one
+fn breakpoint_at(
+    addr: Result<u16, String>,
+    breakpoint: &Json,
+) -> Result<(u16, Option<String>), String> {
+    let addr = addr?;
+    match breakpoint.get("condition").as_str().map(str::trim) {
+        Some(text) if !text.is_empty() => {
+            condition_at(addr, text).map_err(|e| e.to_string())?;
+            Ok((addr, Some(text.to_string())))
+        }
+        _ => Ok((addr, None)),
+    }
+}
+
+// a condition that is true when the PC gets to addr and text is true
+fn condition_at(addr: u16, text: &str) -> Result<Condition, EmulatorError> {
+    Condition::parse(text)?;
+    Condition::parse(&format!("pc == {:#X} && ({})", addr, text))
+}
+
+// memory can be watched, registers can't. The name is an address, and bytes
+// how many bytes from it to watch.
+fn data_breakpoint_info(args: &Json) -> Json {
+    let addr = match args.get("variablesReference") {
+        Json::Null => args
+            .get("name")
+            .as_str()
+            .and_then(parse_number)
+            .and_then(|addr| u16::try_from(addr).ok()),
+        _ => None,
+    };
+    let len = args.get("bytes").as_i64().unwrap_or(1).clamp(1, 0xFFFF);
+
+    match addr {
+        Some(addr) => Json::object([
+            ("dataId", format!("0x{:03X}/{}", addr, len).into()),
+            (
+                "description",
+                format!("{} bytes at 0x{:03X}", len, addr).into(),
+            ),
+            (
+                "accessTypes",
+                vec!["read".into(), "write".into(), "readWrite".into()].into(),
+            ),
+        ]),
+        None => Json::object([
+            ("dataId", Json::Null),
+            ("description", "only memory addresses can be watched".into()),
+        ]),
+    }
+}
+
+// the address and length from a dataId like 0x300/2
+fn parse_data_id(id: &str) -> Option<(u16, u16)> {
+    let (addr, len) = id.split_once('/')?;
+    Some((
+        u16::try_from(parse_number(addr)?).ok()?,
+        u16::try_from(parse_number(len)?)
+            .ok()
+            .filter(|len| *len > 0)?,
+    ))
+}
+
+fn address(addr: u16) -> Json {
+    format!("0x{:03X}", addr).into()
+}
+
+// the DAP side of stdin and stdout
+struct Connection {
+    messages: Receiver<Json>,
+    // sequence number of the last message sent
+    seq: i64,
+}
+
+impl Connection {
+    fn send(&mut self, mut fields: Vec<(String, Json)>) {
+        self.seq += 1;
+        fields.insert(0, ("seq".to_string(), self.seq.into()));
+        let body = Json::Object(fields).to_string();
+
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body).ok();
+        stdout.flush().ok();
+    }
+
+    fn reply(&mut self, request: &Json, success: bool, message: Option<&str>, body: Json) {
+        let mut fields = vec![
+            ("type".to_string(), "response".into()),
+            ("request_seq".to_string(), request.get("seq").clone()),
+            ("success".to_string(), success.into()),
+            ("command".to_string(), request.get("command").clone()),
+        ];
+        if let Some(message) = message {
+            fields.push(("message".to_string(), message.into()));
+        }
+        if body != Json::Null {
+            fields.push(("body".to_string(), body));
+        }
+        self.send(fields);
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) {
+        self.reply(request, true, None, body);
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) {
+        self.reply(request, false, Some(message), Json::Null);
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        let mut fields = vec![
+            ("type".to_string(), "event".into()),
+            ("event".to_string(), event.into()),
+        ];
+        if body != Json::Null {
+            fields.push(("body".to_string(), body));
+        }
+        self.send(fields);
+    }
+}
+
+// the arguments of the launch request
+struct Launch {
+    config: Config,
+    rom: Vec<u8>,
+    // where each address came from, for programs launched from source
+    program: Option<Program>,
+    headless: bool,
+    stop_on_entry: bool,
+    // instructions that can be stepped back through
+    history: usize,
+}
+
+impl Launch {
+    // program is a rom, Octo source ending in .8o or assembly ending in .asm.
+    // mode is chip8, superchip or xochip, by default Octo runs as XO-CHIP and
+    // everything else as Chip-8.
+    fn parse(args: &Json) -> Result<Launch, String> {
+        let path = args
+            .get("program")
+            .as_str()
+            .ok_or("launch needs a program")?;
+        let path = fs::canonicalize(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut config = match args.get("mode").as_str() {
+            Some("chip8") => Config::chip8(),
+            Some("superchip") => Config::superchip(),
+            Some("xochip") => Config::xochip(),
+            Some(mode) => return Err(format!("unknown mode {}", mode)),
+            None => config_for(&path),
+        };
+        if let Some(seed) = args.get("seed").as_i64() {
+            config.seed = seed as u64;
+        }
+
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let program = match extension {
+            Some("8o") => Some(compile_octo_file(&path, config.mode)),
+            Some("asm") => Some(assemble_file(&path)),
+            _ => None,
+        }
+        .transpose()
+        .map_err(|e| e.to_string())?;
+
+        let rom = match &program {
+            Some(program) => program.rom.clone(),
+            None => fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        };
+
+        Ok(Launch {
+            config,
+            rom,
+            program: program.map(canonical_lines),
+            headless: args.get("headless").as_bool().unwrap_or(false),
+            stop_on_entry: args.get("stopOnEntry").as_bool().unwrap_or(false),
+            history: args
+                .get("history")
+                .as_i64()
+                .map_or(HISTORY, |depth| depth.max(0) as usize),
+        })
+    }
+}
+
+// make the file of every source line canonical, so they compare equal to the
+// paths an editor sends
+fn canonical_lines(mut program: Program) -> Program {
+    let mut paths: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+    for line in &mut program.lines {
+        let path = paths
+            .entry(line.file.clone())
+            .or_insert_with(|| fs::canonicalize(&line.file).unwrap_or(line.file.clone()));
+        line.file = path.clone();
+    }
+
+    program
+}
+
+// when a running program stops by itself
+enum Until {
+    // at a breakpoint, or when the editor pauses it
+    Break,
+    // when the call stack gets shallower than depth
+    Return {
+        depth: usize,
+    },
+    // when the PC leaves line of file, at depth or shallower if a depth is
+    // given
+    Line {
+        file: PathBuf,
+        line: usize,
+        depth: Option<usize>,
+    },
+}
+
+struct Session<V: Video> {
+    system: System<V>,
+    debugger: Debugger,
+    conn: Connection,
+    program: Option<Program>,
+    stop_on_entry: bool,
+    // the breakpoints set in each source file, with their conditions
+    source_breakpoints: HashMap<PathBuf, Vec<(u16, Option<String>)>>,
+    // the breakpoints set in the disassembly
+    instruction_breakpoints: Vec<(u16, Option<String>)>,
+    // the debugger's ids for the conditional breakpoints and the data
+    // breakpoints, which are watchpoints
+    conditions: Vec<u32>,
+    watchpoints: Vec<u32>,
+    // how the program is running, None while it is stopped
+    running: Option<Until>,
+}
+
+impl<V: Video> Session<V> {
+    // load the program and handle requests until the editor disconnects
+    fn start(
+        mut system: System<V>,
+        launch: Launch,
+        mut conn: Connection,
+        request: &Json,
+    ) -> Result<(), EmulatorError> {
+        if let Err(e) = system.load_rom_data(launch.rom) {
+            conn.fail(request, &e.to_string());
+            return Err(e);
+        }
+        system.set_history(launch.history);
+        system.render();
+
+        conn.respond(request, Json::Null);
+        conn.event("initialized", Json::Null);
+
+        let mut session = Session {
+            system,
+            debugger: Debugger::new(),
+            conn,
+            program: launch.program,
+            stop_on_entry: launch.stop_on_entry,
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            conditions: Vec::new(),
+            watchpoints: Vec::new(),
+            running: None,
+        };
+        session.run()
+    }
+
+    fn run(&mut self) -> Result<(), EmulatorError> {
+        let mut next_frame = Instant::now();
+
+        loop {
+            if let Some(Command::Quit) = self.system.poll_input()? {
+                self.conn.event("terminated", Json::Null);
+                break;
+            }
+
+            // a frame runs every 1/60s while running, requests are handled
+            // while waiting for the next one
+            let now = Instant::now();
+            if now >= next_frame {
+                next_frame = (next_frame + FRAME).max(now);
+
+                if let Some(until) = &self.running {
+                    let program = &self.program;
+                    let stop = self
+                        .debugger
+                        .run_frame_until(&mut self.system, |system| done(until, program, system));
+                    self.system.render();
+                    self.stopped(stop);
+                }
+            }
+
+            let request = match self
+                .conn
+                .messages
+                .recv_timeout(next_frame.saturating_duration_since(Instant::now()))
+            {
+                Ok(request) => request,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            if !self.request(&request) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // tell the editor why the program stopped, if it did
+    fn stopped(&mut self, stop: Result<Option<Stop>, EmulatorError>) {
+        let reason = match &stop {
+            Ok(None) => return,
+            Ok(Some(Stop::Breakpoint(_) | Stop::Condition { .. })) => "breakpoint",
+            Ok(Some(Stop::Watchpoint { .. })) => "data breakpoint",
+            Ok(Some(Stop::Done | Stop::HistoryStart)) => "step",
+            Ok(Some(Stop::Exited)) => {
+                self.running = None;
+                self.conn
+                    .event("exited", Json::object([("exitCode", 0i64.into())]));
+                self.conn.event("terminated", Json::Null);
+                return;
+            }
+            Err(_) => "exception",
+        };
+
+        self.running = None;
+        let mut body = vec![
+            ("reason".to_string(), reason.into()),
+            ("threadId".to_string(), 1i64.into()),
+            ("allThreadsStopped".to_string(), true.into()),
+        ];
+        match stop {
+            Err(e) => body.push(("text".to_string(), e.to_string().into())),
+            Ok(Some(Stop::HistoryStart)) => {
+                body.push(("description".to_string(), "start of the history".into()))
+            }
+            Ok(_) => {}
+        }
+        self.conn.event("stopped", Json::Object(body));
+    }
+
+    // handle one request, returns false when the editor is done
+    fn request(&mut self, request: &Json) -> bool {
+        let args = request.get("arguments");
+
+        let body = match request.get("command").as_str().unwrap_or("") {
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(Json::Null),
+            "dataBreakpointInfo" => Ok(data_breakpoint_info(args)),
+            "setDataBreakpoints" => Ok(self.set_data_breakpoints(args)),
+            "configurationDone" => {
+                self.conn.respond(request, Json::Null);
+                match self.stop_on_entry {
+                    true => self.conn.event(
+                        "stopped",
+                        Json::object([
+                            ("reason", "entry".into()),
+                            ("threadId", 1i64.into()),
+                            ("allThreadsStopped", true.into()),
+                        ]),
+                    ),
+                    false => self.running = Some(Until::Break),
+                }
+                return true;
+            }
+            "threads" => Ok(Json::object([(
+                "threads",
+                vec![Json::object([
+                    ("id", 1i64.into()),
+                    ("name", "CHIP-8".into()),
+                ])]
+                .into(),
+            )])),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(Json::object([(
+                "scopes",
+                vec![
+                    scope("Registers", REGISTERS),
+                    scope("Timers", TIMERS),
+                    scope("Stack", STACK),
+                ]
+                .into(),
+            )])),
+            "variables" => Ok(self.variables(args.get("variablesReference").as_i64())),
+            "setVariable" => self.set_variable(args),
+            "continue" => {
+                self.running = Some(Until::Break);
+                Ok(Json::object([("allThreadsContinued", true.into())]))
+            }
+            command @ ("next" | "stepIn" | "stepOut") => {
+                self.conn.respond(request, Json::Null);
+                let instruction = args.get("granularity").as_str() == Some("instruction");
+                self.step(command, instruction);
+                return true;
+            }
+            "stepBack" => {
+                self.conn.respond(request, Json::Null);
+                let instruction = args.get("granularity").as_str() == Some("instruction");
+                self.step_back(instruction);
+                return true;
+            }
+            "reverseContinue" => {
+                self.conn.respond(request, Json::Null);
+                self.running = None;
+                let stop = self.debugger.reverse_continue(&mut self.system);
+                self.system.render();
+                self.stopped(Ok(Some(stop)));
+                return true;
+            }
+            "pause" => {
+                self.conn.respond(request, Json::Null);
+                if self.running.take().is_some() {
+                    self.conn.event(
+                        "stopped",
+                        Json::object([
+                            ("reason", "pause".into()),
+                            ("threadId", 1i64.into()),
+                            ("allThreadsStopped", true.into()),
+                        ]),
+                    );
+                }
+                return true;
+            }
+            "readMemory" => self.read_memory(args),
+            "disassemble" => self.disassemble(args),
+            "disconnect" | "terminate" => {
+                self.conn.respond(request, Json::Null);
+                return false;
+            }
+            command => Err(format!("{} isn't supported", command)),
+        };
+
+        match body {
+            Ok(body) => self.conn.respond(request, body),
+            Err(e) => self.conn.fail(request, &e),
+        }
+        true
+    }
+
+    // the source line the instruction at addr came from
+    fn source_line(&self, addr: u16) -> Option<(&Path, usize)> {
+        source_line(&self.program, addr)
+    }
+
+    // next, stepIn or stepOut. Programs launched from source step a line at a
+    // time, roms and steps asked for with instruction granularity an
+    // instruction at a time.
+    fn step(&mut self, command: &str, instruction: bool) {
+        let pc = self.system.registers().pc;
+        let depth = self.system.call_stack().len();
+
+        let line = match instruction {
+            true => None,
+            false => self
+                .source_line(pc)
+                .map(|(file, line)| (file.to_path_buf(), line)),
+        };
+
+        let until = match (command, line) {
+            ("stepOut", _) => Until::Return { depth },
+            ("next", Some((file, line))) => Until::Line {
+                file,
+                line,
+                depth: Some(depth),
+            },
+            ("stepIn", Some((file, line))) => Until::Line {
+                file,
+                line,
+                depth: None,
+            },
+            // stepping over a call runs until it returns
+            ("next", None) if matches!(Op::decode(self.opcode(pc)), Ok(Op::Call(_))) => {
+                Until::Return { depth: depth + 1 }
+            }
+            _ => {
+                let stop = self.debugger.step(&mut self.system).map(|events| {
+                    Some(match events.exited {
+                        true => Stop::Exited,
+                        false => Stop::Done,
+                    })
+                });
+                self.system.render();
+                self.stopped(stop);
+                return;
+            }
+        };
+
+        self.running = Some(until);
+    }
+
+    // undo the last instruction, or by line everything back to the start of
+    // the line that ran before the current one
+    fn step_back(&mut self, instruction: bool) {
+        let line = |session: &Self, pc: u16| {
+            session
+                .source_line(pc)
+                .map(|(file, line)| (file.to_path_buf(), line))
+        };
+        let start = line(self, self.system.registers().pc);
+
+        let stop = loop {
+            if !self.debugger.step_back(&mut self.system) {
+                break Stop::HistoryStart;
+            }
+
+            let now = line(self, self.system.registers().pc);
+            if instruction || start.is_none() || (now.is_some() && now != start) {
+                break Stop::Done;
+            }
+        };
+
+        // by line, carry on back to the first instruction of the line reached
+        if !instruction && stop == Stop::Done {
+            let now = line(self, self.system.registers().pc);
+            while now.is_some() && self.system.last_pc().and_then(|pc| line(self, pc)) == now {
+                self.debugger.step_back(&mut self.system);
+            }
+        }
+
+        self.system.render();
+        self.stopped(Ok(Some(stop)));
+    }
+
+    fn opcode(&self, addr: u16) -> u16 {
+        let ram = self.system.memory();
+        let byte = |addr: usize| ram.get(addr).copied().unwrap_or(0);
+        u16::from_be_bytes([byte(addr as usize), byte(addr as usize + 1)])
+    }
+
+    // the breakpoints of every source file and the disassembly. A
+    // conditional breakpoint is a debugger condition that also checks the PC.
+    fn sync_breakpoints(&mut self) {
+        let all: Vec<(u16, Option<String>)> = self
+            .source_breakpoints
+            .values()
+            .flatten()
+            .chain(&self.instruction_breakpoints)
+            .cloned()
+            .collect();
+
+        let old: Vec<u16> = self.debugger.breakpoints().collect();
+        for addr in old {
+            self.debugger.remove_breakpoint(addr);
+        }
+        for id in self.conditions.drain(..) {
+            self.debugger.remove_condition(id);
+        }
+
+        let mut plain = BTreeSet::new();
+        for (addr, condition) in all {
+            match condition.and_then(|text| condition_at(addr, &text).ok()) {
+                Some(condition) => self.conditions.push(self.debugger.add_condition(condition)),
+                None => {
+                    plain.insert(addr);
+                }
+            }
+        }
+        for addr in plain {
+            self.debugger.add_breakpoint(addr);
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let path = args
+            .get("source")
+            .get("path")
+            .as_str()
+            .ok_or("setBreakpoints needs a source path")?;
+        let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+
+        let mut addrs = Vec::new();
+        let mut replies = Vec::new();
+
+        for breakpoint in args.get("breakpoints").as_array() {
+            let line = breakpoint.get("line").as_i64().unwrap_or(0);
+            let addr = self
+                .program
+                .as_ref()
+                .and_then(|program| program.addr_of(&path, line as usize))
+                .ok_or_else(|| "no code on this line".to_string());
+
+            replies.push(match breakpoint_at(addr, breakpoint) {
+                Ok((addr, condition)) => {
+                    addrs.push((addr, condition));
+                    Json::object([
+                        ("verified", true.into()),
+                        ("line", line.into()),
+                        ("instructionReference", address(addr)),
+                    ])
+                }
+                Err(e) => Json::object([
+                    ("verified", false.into()),
+                    ("line", line.into()),
+                    ("message", e.into()),
+                ]),
+            });
+        }
+
+        self.source_breakpoints.insert(path, addrs);
+        self.sync_breakpoints();
+        Ok(Json::object([("breakpoints", replies.into())]))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let mut addrs = Vec::new();
+        let mut replies = Vec::new();
+
+        for breakpoint in args.get("breakpoints").as_array() {
+            let addr = breakpoint
+                .get("instructionReference")
+                .as_str()
+                .and_then(parse_number)
+                .map(|addr| addr + breakpoint.get("offset").as_i64().unwrap_or(0))
+                .and_then(|addr| u16::try_from(addr).ok())
+                .ok_or_else(|| "not an address".to_string());
+
+            replies.push(match breakpoint_at(addr, breakpoint) {
+                Ok((addr, condition)) => {
+                    addrs.push((addr, condition));
+                    Json::object([
+                        ("verified", true.into()),
+                        ("instructionReference", address(addr)),
+                    ])
+                }
+                Err(e) => Json::object([("verified", false.into()), ("message", e.into())]),
+            });
+        }
+
+        self.instruction_breakpoints = addrs;
+        self.sync_breakpoints();
+        Ok(Json::object([("breakpoints", replies.into())]))
+    }
+
+    // data breakpoints replace all the watchpoints each time
+    fn set_data_breakpoints(&mut self, args: &Json) -> Json {
+        for id in self.watchpoints.drain(..) {
+            self.debugger.remove_watchpoint(id);
+        }
+
+        let mut replies = Vec::new();
+        for breakpoint in args.get("breakpoints").as_array() {
+            let watch = match breakpoint.get("accessType").as_str() {
+                Some("read") => Watch::Read,
+                Some("readWrite") => Watch::Access,
+                _ => Watch::Write,
+            };
+            let w = breakpoint
+                .get("dataId")
+                .as_str()
+                .and_then(parse_data_id)
+                .map(|(addr, len)| Watchpoint { addr, len, watch });
+
+            replies.push(match w {
+                Some(w) => {
+                    self.watchpoints.push(self.debugger.add_watchpoint(w));
+                    Json::object([("verified", true.into())])
+                }
+                None => Json::object([
+                    ("verified", false.into()),
+                    ("message", "not a memory address".into()),
+                ]),
+            });
+        }
+
+        Json::object([("breakpoints", replies.into())])
+    }
+
+    // a stack frame for the PC and one for the call of every subroutine on
+    // the stack
+    fn stack_trace(&self) -> Json {
+        let pc = self.system.registers().pc;
+        let calls = self
+            .system
+            .call_stack()
+            .iter()
+            .rev()
+            .map(|ret| ret.wrapping_sub(2));
+
+        let frames: Vec<Json> = std::iter::once(pc)
+            .chain(calls)
+            .enumerate()
+            .map(|(id, addr)| {
+                let mut frame = vec![
+                    ("id".to_string(), id.into()),
+                    ("name".to_string(), format!("0x{:03X}", addr).into()),
+                    ("instructionPointerReference".to_string(), address(addr)),
+                ];
+
+                match self.source_line(addr) {
+                    Some((file, line)) => frame.extend([
+                        ("source".to_string(), source(file)),
+                        ("line".to_string(), line.into()),
+                        ("column".to_string(), 1i64.into()),
+                    ]),
+                    None => frame.extend([
+                        ("line".to_string(), 0i64.into()),
+                        ("column".to_string(), 0i64.into()),
+                    ]),
+                }
+                Json::Object(frame)
+            })
+            .collect();
+
+        Json::object([
+            ("totalFrames", frames.len().into()),
+            ("stackFrames", frames.into()),
+        ])
+    }
+
+    fn variables(&self, reference: Option<i64>) -> Json {
+        let r = self.system.registers();
+
+        let variables = match reference {
+            Some(REGISTERS) => {
+                let mut variables: Vec<Json> =
+                    r.v.iter()
+                        .enumerate()
+                        .map(|(x, v)| variable(&format!("V{:X}", x), format!("0x{:02X}", v), None))
+                        .collect();
+                variables.extend([
+                    variable("I", format!("0x{:03X}", r.i), Some(r.i)),
+                    variable("PC", format!("0x{:03X}", r.pc), Some(r.pc)),
+                    variable("SP", r.sp.to_string(), None),
+                ]);
+                variables
+            }
+            Some(TIMERS) => vec![
+                variable("DT", r.dt.to_string(), None),
+                variable("ST", r.st.to_string(), None),
+            ],
+            // the return address of every subroutine being run, the most
+            // recent call first
+            Some(STACK) => self
+                .system
+                .call_stack()
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(n, addr)| {
+                    variable(
+                        &format!("#{}", n + 1),
+                        format!("0x{:03X}", addr),
+                        Some(*addr),
+                    )
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Json::object([("variables", variables.into())])
+    }
+
+    fn set_variable(&mut self, args: &Json) -> Result<Json, String> {
+        let name = args.get("name").as_str().unwrap_or("");
+        let text = args.get("value").as_str().unwrap_or("");
+        let value = parse_number(text).ok_or_else(|| format!("{} isn't a number", text))?;
+
+        let byte = || u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", text));
+        let word = || u16::try_from(value).map_err(|_| format!("{} doesn't fit in 16 bits", text));
+
+        let mut r = self.system.registers();
+        let shown = match (args.get("variablesReference").as_i64(), name) {
+            (Some(REGISTERS), "I") => {
+                r.i = word()?;
+                format!("0x{:03X}", r.i)
+            }
+            (Some(REGISTERS), "PC") => {
+                r.pc = word()?;
+                format!("0x{:03X}", r.pc)
+            }
+            (Some(REGISTERS), "SP") => {
+                r.sp = byte()?;
+                r.sp.to_string()
+            }
+            (Some(TIMERS), "DT") => {
+                r.dt = byte()?;
+                r.dt.to_string()
+            }
+            (Some(TIMERS), "ST") => {
+                r.st = byte()?;
+                r.st.to_string()
+            }
+            (Some(REGISTERS), _) => {
+                let x = name
+                    .strip_prefix('V')
+                    .and_then(|x| usize::from_str_radix(x, 16).ok())
+                    .filter(|x| *x < 16)
+                    .ok_or_else(|| format!("there is no register {}", name))?;
+                r.v[x] = byte()?;
+                format!("0x{:02X}", r.v[x])
+            }
+            _ => return Err(format!("{} can't be changed", name)),
+        };
+
+        self.system.set_registers(r);
+        Ok(Json::object([("value", shown.into())]))
+    }
+
+    fn read_memory(&self, args: &Json) -> Result<Json, String> {
+        let start = args
+            .get("memoryReference")
+            .as_str()
+            .and_then(parse_number)
+            .ok_or("readMemory needs a memory reference")?
+            .saturating_add(args.get("offset").as_i64().unwrap_or(0));
+        let count = args.get("count").as_i64().unwrap_or(0).max(0);
+
+        // the numbers come from the client, they can be anything
+        let ram = self.system.memory();
+        let first = start.clamp(0, ram.len() as i64) as usize;
+        let last = start.saturating_add(count).clamp(0, ram.len() as i64) as usize;
+        let bytes = &ram[first..last.max(first)];
+
+        Ok(Json::object([
+            ("address", format!("0x{:03X}", start.max(0)).into()),
+            ("data", base64(bytes).into()),
+            ("unreadableBytes", (count - bytes.len() as i64).into()),
+        ]))
+    }
+
+    // instructions from the memory reference on, in Cowgod syntax.
+    // Instructions are counted as 2 bytes to find where to start.
+    fn disassemble(&self, args: &Json) -> Result<Json, String> {
+        let mut addr = args
+            .get("memoryReference")
+            .as_str()
+            .and_then(parse_number)
+            .ok_or("disassemble needs a memory reference")?
+            .saturating_add(args.get("offset").as_i64().unwrap_or(0))
+            .saturating_add(
+                args.get("instructionOffset")
+                    .as_i64()
+                    .unwrap_or(0)
+                    .saturating_mul(2),
+            );
+        let count = args.get("instructionCount").as_i64().unwrap_or(0);
+        let size = self.system.memory().len() as i64;
+
+        let mut instructions = Vec::new();
+        for _ in 0..count {
+            if addr < 0 || addr >= size - 1 {
+                instructions.push(Json::object([
+                    ("address", format!("0x{:03X}", addr.max(0)).into()),
+                    ("instruction", "".into()),
+                    ("presentationHint", "invalid".into()),
+                ]));
+                addr = addr.saturating_add(2);
+                continue;
+            }
+
+            let word = self.opcode(addr as u16);
+            let next = self.opcode((addr as u16).wrapping_add(2));
+            let len = Op::decode(word).map_or(2, |op| op.size());
+            let bytes = match len {
+                4 => format!("{:04X} {:04X}", word, next),
+                _ => format!("{:04X}", word),
+            };
+            let text = disassemble_instruction(word, next, Syntax::Cowgod)
+                .unwrap_or_else(|| format!("dw 0x{:04X}", word));
+
+            let mut instruction = vec![
+                ("address".to_string(), address(addr as u16)),
+                ("instructionBytes".to_string(), bytes.into()),
+                ("instruction".to_string(), text.into()),
+            ];
+            if let Some((file, line)) = self.source_line(addr as u16) {
+                instruction.extend([
+                    ("location".to_string(), source(file)),
+                    ("line".to_string(), line.into()),
+                ]);
+            }
+
+            instructions.push(Json::Object(instruction));
+            addr += len as i64;
+        }
+
+        Ok(Json::object([("instructions", instructions.into())]))
+    }
+}
+
+// the source line the instruction at addr came from, nothing below 0x200 does
+fn source_line(program: &Option<Program>, addr: u16) -> Option<(&Path, usize)> {
+    match addr {
+        0x200.. => program
+            .as_ref()?
+            .line_of(addr)
+            .map(|line| (line.file.as_path(), line.line)),
+        _ => None,
+    }
+}
+
+// a running program has gone far enough
+fn done<V: Video>(until: &Until, program: &Option<Program>, system: &System<V>) -> bool {
+    let depth = system.call_stack().len();
+
+    match until {
+        Until::Break => false,
+        Until::Return { depth: from } => depth < *from,
+        Until::Line {
+            file,
+            line,
+            depth: from,
+        } => {
+            let moved = source_line(program, system.registers().pc) != Some((file, *line));
+            match from {
+                Some(from) => (moved && depth <= *from) || depth < *from,
+                None => moved,
+            }
+        }
+    }
+}
+
+fn scope(name: &str, reference: i64) -> Json {
+    Json::object([
+        ("name", name.into()),
+        ("variablesReference", reference.into()),
+        ("expensive", false.into()),
+    ])
+}
+
+// a register or stack entry, addresses can be opened in the memory view
+fn variable(name: &str, value: String, addr: Option<u16>) -> Json {
+    let mut fields = vec![
+        ("name".to_string(), name.into()),
+        ("value".to_string(), value.into()),
+        ("variablesReference".to_string(), 0i64.into()),
+    ];
+    if let Some(addr) = addr {
+        fields.push(("memoryReference".to_string(), address(addr)));
+    }
+    Json::Object(fields)
+}
+
+fn source(file: &Path) -> Json {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    Json::object([
+        ("name", name.as_ref().into()),
+        ("path", file.to_string_lossy().as_ref().into()),
+    ])
+}
@@ -0,0 +1,649 @@
+use crate::{clock_seed, config_for, exit_usage, HISTORY, USAGE};
+use emulator::{
+    disassemble_instruction, Command, Condition, Config, Debugger, EmulatorError, Framebuffer, Op,
+    RngKind, Stop, Syntax, System, Video, Watch, Watchpoint,
+};
+
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+const HELP: &str = "commands:
+  step [n]             run n instructions, 1 if not given (s)
+  back [n]             undo the last n instructions run, 1 if not given (rs)
+  continue             run until a breakpoint or pause (c)
+  rcontinue            run backwards until a breakpoint, watchpoint or
+                       condition, or the start of the history (rc)
+  pause                stop running (p)
+  break [addr]         set a breakpoint, or list breakpoints, watchpoints and
+                       conditions without addr (b)
+  break [addr] if <condition>
+                       stop when condition is true after an instruction, with
+                       addr only when the PC gets there
+  watch [read|write|access] <addr> [len]
+                       stop after an instruction writes (or reads, or does
+                       either) any of len bytes from addr, 1 if not given
+  delete <addr|#n>     remove a breakpoint, or watchpoint or condition n (d)
+  regs                 show the registers and timers (r)
+  stack                show the call stack (bt)
+  x <addr> [len]       show len bytes of memory, 64 if not given
+  who <addr>           show the last instruction to write addr
+  dis [addr] [count]   disassemble count instructions, around the PC if no
+                       addr is given
+  set <reg> <value>    change v0-vf, i, pc, sp, dt or st
+  poke <addr> <bytes>  write bytes to memory
+  press <key>          press a key (0-f) on the keypad
+  release <key>        release a key
+  screen               print the screen
+  quit                 quit the debugger (q)
+addresses and values are hex, counts are decimal. Conditions are C
+expressions over v0-vf, i, pc, sp, dt, st and [addr] for a byte of memory,
+like pc == 0x2A4 && v3 > 10, where numbers are decimal unless written 0x";
+
+// command line options for debugging a rom
+struct Options {
+    rom: PathBuf,
+    headless: bool,
+    history: usize,
+    seed: Option<u64>,
+    rng: RngKind,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom: PathBuf::new(),
+        headless: false,
+        history: HISTORY,
+        seed: None,
+        rng: RngKind::Xorshift,
+    };
+
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => options.headless = true,
+            "--history" => {
+                let depth = args.next().ok_or("--history needs a value")?;
+                options.history = depth
+                    .parse()
+                    .map_err(|_| format!("invalid history depth {}", depth))?;
+            }
+            "--seed" => {
+                let seed = args.next().ok_or("--seed needs a value")?;
+                options.seed = Some(seed.parse().map_err(|_| format!("invalid seed {}", seed))?);
+            }
+            "--rng" => {
+                options.rng = match args.next().as_deref() {
+                    Some("xorshift") => RngKind::Xorshift,
+                    Some("vip") => RngKind::CosmacVip,
+                    _ => return Err("--rng needs to be xorshift or vip".to_string()),
+                }
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => options.rom = PathBuf::from(arg),
+        }
+    }
+
+    match options.rom.as_os_str().is_empty() {
+        true => Err("debug needs a rom".to_string()),
+        false => Ok(options),
+    }
+}
+
+// chip8 debug [--headless] [--history <n>] [--seed <n>] [--rng <xorshift|vip>] <rom>
+pub(crate) fn debug(args: &[String]) -> Result<(), EmulatorError> {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => exit_usage(&e),
+    };
+
+    let mut config = config_for(&options.rom);
+    config.rng = options.rng;
+    config.seed = options.seed.unwrap_or_else(clock_seed);
+
+    match options.headless {
+        true => Session::start(System::create(Framebuffer::create(), config), &options),
+        false => windowed(config, &options),
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn windowed(config: Config, options: &Options) -> Result<(), EmulatorError> {
+    let display = emulator::Display::create("Chip-8 debugger".to_string(), 10);
+    Session::start(System::create(display, config), options)
+}
+
+// without SDL there is no window to show, the debugger always runs headless
+#[cfg(not(feature = "sdl"))]
+fn windowed(config: Config, options: &Options) -> Result<(), EmulatorError> {
+    Session::start(System::create(Framebuffer::create(), config), options)
+}
+
+// read stdin on its own thread so the window stays responsive and the program
+// keeps running while waiting for a command. The channel closes at the end of
+// input.
+fn read_lines() -> Receiver<String> {
+    let (send, recv) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if send.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    recv
+}
+
+fn prompt() {
+    print!("(chip8) ");
+    io::stdout().flush().ok();
+}
+
+// a hex number, with or without 0x in front
+fn parse_hex(text: &str) -> Result<u16, String> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid hex number {}", text))
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+    u8::try_from(parse_hex(text)?).map_err(|_| format!("{} doesn't fit in a byte", text))
+}
+
+fn parse_count(text: &str) -> Result<usize, String> {
+    text.parse().map_err(|_| format!("invalid count {}", text))
+}
+
+struct Session<V: Video> {
+    system: System<V>,
+    debugger: Debugger,
+    // the program is running at 60 frames a second instead of paused
+    running: bool,
+}
+
+impl<V: Video> Session<V> {
+    // load the rom and take commands until the user quits. The program starts
+    // paused at its first instruction.
+    fn start(mut system: System<V>, options: &Options) -> Result<(), EmulatorError> {
+        system.load_rom(&options.rom)?;
+        system.set_history(options.history);
+        system.render();
+
+        let mut session = Session {
+            system,
+            debugger: Debugger::new(),
+            running: false,
+        };
+        session.run()
+    }
+
+    fn run(&mut self) -> Result<(), EmulatorError> {
+        let lines = read_lines();
+        let mut next_frame = Instant::now();
+
+        self.show_pc();
+        prompt();
+
+        loop {
+            if let Some(Command::Quit) = self.system.poll_input()? {
+                break;
+            }
+
+            // a frame runs every 1/60s while running, commands are handled
+            // while waiting for the next one
+            let now = Instant::now();
+            if now >= next_frame && self.running {
+                let stop = self.debugger.run_frame(&mut self.system);
+                self.system.render();
+
+                match stop {
+                    Ok(None | Some(Stop::Done)) => {}
+                    Ok(Some(stop)) => self.pause(&self.why(&stop)),
+                    Err(e) => self.pause(&e.to_string()),
+                }
+            }
+
+            if now >= next_frame {
+                next_frame = (next_frame + FRAME).max(now);
+            }
+
+            let line =
+                match lines.recv_timeout(next_frame.saturating_duration_since(Instant::now())) {
+                    Ok(line) => line,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+
+            match self.command(&line) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => println!("{}", e),
+            }
+
+            if !self.running {
+                prompt();
+            }
+        }
+
+        Ok(())
+    }
+
+    // stop running and say why
+    fn pause(&mut self, reason: &str) {
+        self.running = false;
+        println!("{}", reason);
+        self.show_pc();
+        prompt();
+    }
+
+    // what made the program stop
+    fn why(&self, stop: &Stop) -> String {
+        match *stop {
+            Stop::Breakpoint(addr) => format!("breakpoint at {:03X}", addr),
+            Stop::Watchpoint { id, access, pc } => {
+                let verb = if access.write { "wrote" } else { "read" };
+                let bytes = if access.len == 1 { "byte" } else { "bytes" };
+                format!(
+                    "watchpoint #{}: {} at {:03X} {} {} {} at {:03X}",
+                    id,
+                    self.instruction_text(pc),
+                    pc,
+                    verb,
+                    access.len,
+                    bytes,
+                    access.addr
+                )
+            }
+            Stop::Condition { id, pc } => {
+                let condition = self
+                    .debugger
+                    .condition(id)
+                    .map(|condition| condition.to_string())
+                    .unwrap_or_default();
+                format!(
+                    "condition #{}: {} after {} at {:03X}",
+                    id,
+                    condition,
+                    self.instruction_text(pc),
+                    pc
+                )
+            }
+            Stop::HistoryStart => "at the start of the history".to_string(),
+            Stop::Exited => "the program exited".to_string(),
+            Stop::Done => "done".to_string(),
+        }
+    }
+
+    // run one command line, returns false to quit
+    fn command(&mut self, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Ok(true),
+        };
+
+        match (name, args) {
+            ("s" | "step", [] | [_]) => {
+                let count = args.first().map_or(Ok(1), |n| parse_count(n))?;
+                self.running = false;
+
+                for _ in 0..count {
+                    let events = self
+                        .debugger
+                        .step(&mut self.system)
+                        .map_err(|e| e.to_string())?;
+                    if events.exited {
+                        println!("the program exited");
+                        break;
+                    }
+                }
+
+                self.system.render();
+                self.show_pc();
+            }
+            ("rs" | "back", [] | [_]) => {
+                let count = args.first().map_or(Ok(1), |n| parse_count(n))?;
+                self.running = false;
+
+                for _ in 0..count {
+                    if !self.debugger.step_back(&mut self.system) {
+                        println!("{}", self.why(&Stop::HistoryStart));
+                        break;
+                    }
+                }
+
+                self.system.render();
+                self.show_pc();
+            }
+            ("c" | "continue", []) => self.running = true,
+            ("rc" | "rcontinue", []) => {
+                self.running = false;
+                let stop = self.debugger.reverse_continue(&mut self.system);
+                self.system.render();
+
+                println!("{}", self.why(&stop));
+                self.show_pc();
+            }
+            ("p" | "pause", []) => {
+                self.running = false;
+                self.show_pc();
+            }
+            ("b" | "break", []) => {
+                for addr in self.debugger.breakpoints() {
+                    println!("breakpoint at {:03X}", addr);
+                }
+                for (id, w) in self.debugger.watchpoints() {
+                    println!("#{} {}", id, describe_watchpoint(&w));
+                }
+                for (id, condition) in self.debugger.conditions() {
+                    println!("#{} if {}", id, condition);
+                }
+            }
+            ("b" | "break", ["if", ..]) | ("b" | "break", [_, "if", ..]) => {
+                let (_, text) = line.split_once(" if ").ok_or("if needs a condition")?;
+                let text = match args[0] {
+                    "if" => text.to_string(),
+                    addr => format!("pc == {:#X} && ({})", parse_hex(addr)?, text.trim()),
+                };
+
+                let condition = Condition::parse(&text).map_err(|e| e.to_string())?;
+                println!(
+                    "#{} if {}",
+                    self.debugger.add_condition(condition.clone()),
+                    condition
+                );
+            }
+            ("b" | "break", [addr]) => {
+                let addr = parse_hex(addr)?;
+                match self.debugger.add_breakpoint(addr) {
+                    true => println!("breakpoint at {:03X}", addr),
+                    false => println!("there already is a breakpoint at {:03X}", addr),
+                }
+            }
+            ("watch", [first, ..]) => {
+                let (watch, rest) = match *first {
+                    "read" => (Watch::Read, &args[1..]),
+                    "write" => (Watch::Write, &args[1..]),
+                    "access" => (Watch::Access, &args[1..]),
+                    _ => (Watch::Write, args),
+                };
+                let len = match rest {
+                    [_] => 1,
+                    [_, len] => parse_count(len)?,
+                    _ => return Err("watch needs an address and maybe a length".to_string()),
+                };
+                let len = u16::try_from(len)
+                    .ok()
+                    .filter(|len| *len > 0)
+                    .ok_or("a watchpoint needs 1 to FFFF bytes")?;
+
+                let w = Watchpoint {
+                    addr: parse_hex(rest[0])?,
+                    len,
+                    watch,
+                };
+                println!(
+                    "#{} {}",
+                    self.debugger.add_watchpoint(w),
+                    describe_watchpoint(&w)
+                );
+            }
+            ("d" | "delete", [id]) if id.starts_with('#') => {
+                let id: u32 = id[1..]
+                    .parse()
+                    .map_err(|_| format!("invalid number {}", id))?;
+                if !self.debugger.remove_watchpoint(id) && !self.debugger.remove_condition(id) {
+                    return Err(format!("no watchpoint or condition #{}", id));
+                }
+            }
+            ("d" | "delete", [addr]) => {
+                let addr = parse_hex(addr)?;
+                if !self.debugger.remove_breakpoint(addr) {
+                    return Err(format!("no breakpoint at {:03X}", addr));
+                }
+            }
+            ("r" | "regs", []) => self.show_registers(),
+            ("bt" | "stack", []) => self.show_stack(),
+            ("x", [addr] | [addr, _]) => {
+                let len = args.get(1).map_or(Ok(64), |n| parse_count(n))?;
+                self.show_memory(parse_hex(addr)?, len);
+            }
+            ("who", [addr]) => {
+                let addr = parse_hex(addr)?;
+                match self.system.last_write(addr) {
+                    Some(w) => {
+                        let ago = self.system.cycles() - w.cycle;
+                        let instructions = if ago == 1 {
+                            "instruction"
+                        } else {
+                            "instructions"
+                        };
+                        println!(
+                            "{:03X} was written by {} at {:03X}, {} {} ago: {:02X} -> {:02X}",
+                            addr,
+                            self.instruction_text(w.pc),
+                            w.pc,
+                            ago,
+                            instructions,
+                            w.old,
+                            w.new
+                        )
+                    }
+                    None => println!("nothing in the history wrote {:03X}", addr),
+                }
+            }
+            ("dis", []) => {
+                // start a few instructions back so the PC is in the middle
+                let pc = self.system.registers().pc;
+                self.disassemble(pc.saturating_sub(8), 10);
+            }
+            ("dis", [addr] | [addr, _]) => {
+                let count = args.get(1).map_or(Ok(10), |n| parse_count(n))?;
+                self.disassemble(parse_hex(addr)?, count);
+            }
+            ("set", [reg, value]) => self.set_register(reg, value)?,
+            ("poke", [addr, bytes @ ..]) if !bytes.is_empty() => {
+                let bytes = bytes
+                    .iter()
+                    .map(|byte| parse_byte(byte))
+                    .collect::<Result<Vec<u8>, String>>()?;
+                self.system
+                    .write_memory(parse_hex(addr)?, &bytes)
+                    .map_err(|e| e.to_string())?;
+            }
+            ("press" | "release", [key]) => {
+                let key = parse_byte(key)?;
+                if key > 0xF {
+                    return Err(format!("there is no key {:X}", key));
+                }
+
+                match name {
+                    "press" => self.system.keypad_mut().press(key),
+                    _ => self.system.keypad_mut().release(key),
+                }
+            }
+            ("screen", []) => self.show_screen(),
+            ("h" | "help", []) => println!("{}", HELP),
+            ("q" | "quit", []) => return Ok(false),
+            _ => {
+                return Err(format!(
+                    "don't know how to {}, type help for the commands",
+                    line.trim()
+                ))
+            }
+        }
+
+        Ok(true)
+    }
+
+    // the instruction at the PC, which runs next
+    fn show_pc(&self) {
+        let pc = self.system.registers().pc;
+        print!("{}", self.instruction_line(pc).0);
+    }
+
+    // the disassembly of the instruction at addr
+    fn instruction_text(&self, addr: u16) -> String {
+        let ram = self.system.memory();
+        let byte = |addr: u16| ram.get(addr as usize).copied().unwrap_or(0);
+        let opcode = u16::from_be_bytes([byte(addr), byte(addr.wrapping_add(1))]);
+        let next = u16::from_be_bytes([byte(addr.wrapping_add(2)), byte(addr.wrapping_add(3))]);
+
+        disassemble_instruction(opcode, next, Syntax::Cowgod)
+            .unwrap_or_else(|| "not an instruction".to_string())
+    }
+
+    // one line of a listing, => marks the PC and * a breakpoint. Returns the
+    // line and the length of the instruction.
+    fn instruction_line(&self, addr: u16) -> (String, u16) {
+        let ram = self.system.memory();
+        let word = |addr: u16| {
+            let byte = |addr: usize| ram.get(addr).copied().unwrap_or(0);
+            u16::from_be_bytes([byte(addr as usize), byte(addr as usize + 1)])
+        };
+
+        let opcode = word(addr);
+        let next = word(addr.wrapping_add(2));
+        let len = Op::decode(opcode).map_or(2, |op| op.size());
+
+        let marker = match (
+            addr == self.system.registers().pc,
+            self.debugger.breakpoints().any(|b| b == addr),
+        ) {
+            (true, _) => "=>",
+            (false, true) => " *",
+            (false, false) => "  ",
+        };
+        let hex = match len {
+            4 => format!("{:04X} {:04X}", opcode, next),
+            _ => format!("{:04X}", opcode),
+        };
+        (
+            format!(
+                "{} {:03X}  {:<9}  {}\n",
+                marker,
+                addr,
+                hex,
+                self.instruction_text(addr)
+            ),
+            len,
+        )
+    }
+
+    fn disassemble(&self, mut addr: u16, count: usize) {
+        for _ in 0..count {
+            if addr as usize >= self.system.memory().len() {
+                break;
+            }
+
+            let (line, len) = self.instruction_line(addr);
+            print!("{}", line);
+            addr = addr.wrapping_add(len);
+        }
+    }
+
+    fn show_registers(&self) {
+        let r = self.system.registers();
+
+        for (n, values) in r.v.chunks(8).enumerate() {
+            let regs: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| format!("V{:X} {:02X}", n * 8 + i, v))
+                .collect();
+            println!("{}", regs.join("  "));
+        }
+
+        println!(
+            "I {:04X}  PC {:03X}  SP {}  DT {:02X}  ST {:02X}",
+            r.i, r.pc, r.sp, r.dt, r.st
+        );
+        if let Some(x) = self.system.waiting_for_key() {
+            println!("waiting for a key to store in V{:X}", x);
+        }
+    }
+
+    // the PC and the address every subroutine on the stack returns to
+    fn show_stack(&self) {
+        println!("#0 {:03X}", self.system.registers().pc);
+        for (depth, addr) in self.system.call_stack().iter().rev().enumerate() {
+            println!("#{} {:03X}", depth + 1, addr);
+        }
+    }
+
+    // hex dump, 16 bytes a line
+    fn show_memory(&self, addr: u16, len: usize) {
+        let ram = self.system.memory();
+        let start = (addr as usize).min(ram.len());
+        let end = start.saturating_add(len).min(ram.len());
+
+        for (line, bytes) in ram[start..end].chunks(16).enumerate() {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            println!("{:03X}  {}", start + line * 16, hex.join(" "));
+        }
+    }
+
+    fn set_register(&mut self, reg: &str, value: &str) -> Result<(), String> {
+        let mut r = self.system.registers();
+        let reg = reg.to_ascii_lowercase();
+
+        match reg.as_str() {
+            "i" => r.i = parse_hex(value)?,
+            "pc" => r.pc = parse_hex(value)?,
+            "sp" => r.sp = parse_byte(value)?,
+            "dt" => r.dt = parse_byte(value)?,
+            "st" => r.st = parse_byte(value)?,
+            _ => {
+                let x = reg
+                    .strip_prefix('v')
+                    .filter(|x| x.len() == 1)
+                    .and_then(|x| u8::from_str_radix(x, 16).ok())
+                    .ok_or(format!("there is no register {}", reg))?;
+                r.v[x as usize] = parse_byte(value)?;
+            }
+        }
+
+        self.system.set_registers(r);
+        Ok(())
+    }
+
+    // the screen as text, # for lit pixels
+    fn show_screen(&self) {
+        let (width, _) = self.system.resolution();
+        for row in self.system.vram().chunks(width) {
+            let line: String = row
+                .iter()
+                .map(|pixel| if *pixel != 0 { '#' } else { '.' })
+                .collect();
+            println!("{}", line);
+        }
+    }
+}
+
+// e.g. "watch write 300-302"
+fn describe_watchpoint(w: &Watchpoint) -> String {
+    let kind = match w.watch {
+        Watch::Read => "read",
+        Watch::Write => "write",
+        Watch::Access => "access",
+    };
+
+    match w.len {
+        1 => format!("watch {} {:03X}", kind, w.addr),
+        len => format!(
+            "watch {} {:03X}-{:03X}",
+            kind,
+            w.addr,
+            w.addr as u32 + len as u32 - 1
+        ),
+    }
+}
@@ -0,0 +1,754 @@
+use crate::{clock_seed, config_for, exit_usage, HISTORY, USAGE};
+use emulator::{
+    Access, Command, Config, Debugger, EmulatorError, Framebuffer, Registers, RngKind, Stop,
+    System, Video, Watch, Watchpoint,
+};
+
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// the registers in the order gdb numbers them, with their size in bits. g and
+// G packets send them in this order, each in big-endian like the CHIP-8's
+// memory.
+const REGISTERS: [(&str, usize, &str); 21] = [
+    ("v0", 8, "uint8"),
+    ("v1", 8, "uint8"),
+    ("v2", 8, "uint8"),
+    ("v3", 8, "uint8"),
+    ("v4", 8, "uint8"),
+    ("v5", 8, "uint8"),
+    ("v6", 8, "uint8"),
+    ("v7", 8, "uint8"),
+    ("v8", 8, "uint8"),
+    ("v9", 8, "uint8"),
+    ("va", 8, "uint8"),
+    ("vb", 8, "uint8"),
+    ("vc", 8, "uint8"),
+    ("vd", 8, "uint8"),
+    ("ve", 8, "uint8"),
+    ("vf", 8, "uint8"),
+    ("i", 16, "data_ptr"),
+    ("pc", 16, "code_ptr"),
+    ("sp", 8, "uint8"),
+    ("dt", 8, "uint8"),
+    ("st", 8, "uint8"),
+];
+
+// the stop reply for running backwards into the start of the history
+const HISTORY_START: &str = "T05replaylog:begin;";
+
+// bytes taken by all the registers in a g or G packet
+const REGISTERS_SIZE: usize = 23;
+
+// command line options for serving a rom to gdb
+struct Options {
+    rom: PathBuf,
+    port: u16,
+    socket: Option<PathBuf>,
+    headless: bool,
+    history: usize,
+    seed: Option<u64>,
+    rng: RngKind,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom: PathBuf::new(),
+        port: 1234,
+        socket: None,
+        headless: false,
+        history: HISTORY,
+        seed: None,
+        rng: RngKind::Xorshift,
+    };
+
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                let port = args.next().ok_or("--port needs a value")?;
+                options.port = port.parse().map_err(|_| format!("invalid port {}", port))?;
+            }
+            "--socket" => options.socket = Some(args.next().ok_or("--socket needs a file")?.into()),
+            "--headless" => options.headless = true,
+            "--history" => {
+                let depth = args.next().ok_or("--history needs a value")?;
+                options.history = depth
+                    .parse()
+                    .map_err(|_| format!("invalid history depth {}", depth))?;
+            }
+            "--seed" => {
+                let seed = args.next().ok_or("--seed needs a value")?;
+                options.seed = Some(seed.parse().map_err(|_| format!("invalid seed {}", seed))?);
+            }
+            "--rng" => {
+                options.rng = match args.next().as_deref() {
+                    Some("xorshift") => RngKind::Xorshift,
+                    Some("vip") => RngKind::CosmacVip,
+                    _ => return Err("--rng needs to be xorshift or vip".to_string()),
+                }
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => options.rom = PathBuf::from(arg),
+        }
+    }
+
+    match options.rom.as_os_str().is_empty() {
+        true => Err("gdb needs a rom".to_string()),
+        false => Ok(options),
+    }
+}
+
+// chip8 gdb [--port <n> | --socket <file>] [--headless] [--history <n>] <rom>
+pub(crate) fn gdb(args: &[String]) -> Result<(), EmulatorError> {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => exit_usage(&e),
+    };
+
+    let mut config = config_for(&options.rom);
+    config.rng = options.rng;
+    config.seed = options.seed.unwrap_or_else(clock_seed);
+
+    let (reader, writer) = accept(&options)?;
+
+    match options.headless {
+        true => Server::start(
+            System::create(Framebuffer::create(), config),
+            &options,
+            reader,
+            writer,
+        ),
+        false => windowed(config, &options, reader, writer),
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn windowed(
+    config: Config,
+    options: &Options,
+    reader: Box<dyn Read + Send>,
+    writer: Box<dyn Write>,
+) -> Result<(), EmulatorError> {
+    let display = emulator::Display::create("Chip-8 gdb".to_string(), 10);
+    Server::start(System::create(display, config), options, reader, writer)
+}
+
+// without SDL there is no window to show, the server always runs headless
+#[cfg(not(feature = "sdl"))]
+fn windowed(
+    config: Config,
+    options: &Options,
+    reader: Box<dyn Read + Send>,
+    writer: Box<dyn Write>,
+) -> Result<(), EmulatorError> {
+    Server::start(
+        System::create(Framebuffer::create(), config),
+        options,
+        reader,
+        writer,
+    )
+}
+
+// wait for gdb to connect, on the unix socket if one was given and on the
+// localhost tcp port otherwise
+fn accept(options: &Options) -> io::Result<(Box<dyn Read + Send>, Box<dyn Write>)> {
+    #[cfg(unix)]
+    if let Some(path) = &options.socket {
+        use std::os::unix::net::UnixListener;
+
+        let listener = UnixListener::bind(path)?;
+        println!("waiting for gdb on {}", path.display());
+        let (stream, _) = listener.accept()?;
+        std::fs::remove_file(path)?;
+        return Ok((Box::new(stream.try_clone()?), Box::new(stream)));
+    }
+
+    #[cfg(not(unix))]
+    if options.socket.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "unix sockets aren't available on this platform",
+        ));
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", options.port))?;
+    println!("waiting for gdb on 127.0.0.1:{}", options.port);
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+}
+
+// what comes in from gdb
+enum Input {
+    // the data of a packet, with escapes undone
+    Packet(Vec<u8>),
+    // a packet whose checksum didn't match, gdb sends it again
+    Corrupt,
+    // ctrl-c, stop the running program
+    Interrupt,
+}
+
+// read packets on their own thread, so the program can run until gdb
+// interrupts it. The channel closes when gdb disconnects.
+fn read_packets(reader: Box<dyn Read + Send>) -> Receiver<Input> {
+    let (send, recv) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut bytes = BufReader::new(reader).bytes().map_while(Result::ok);
+
+        while let Some(byte) = bytes.next() {
+            let input = match byte {
+                0x03 => Input::Interrupt,
+                b'$' => {
+                    let mut data = Vec::new();
+                    let mut sum = 0u8;
+                    let mut escaped = false;
+
+                    for byte in bytes.by_ref() {
+                        if byte == b'#' && !escaped {
+                            break;
+                        }
+                        sum = sum.wrapping_add(byte);
+
+                        match (escaped, byte) {
+                            (true, _) => data.push(byte ^ 0x20),
+                            (false, b'}') => {}
+                            (false, _) => data.push(byte),
+                        }
+                        escaped = !escaped && byte == b'}';
+                    }
+
+                    let checksum: Vec<u8> = bytes.by_ref().take(2).collect();
+                    let checksum = std::str::from_utf8(&checksum)
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    match checksum == Some(sum) {
+                        true => Input::Packet(data),
+                        false => Input::Corrupt,
+                    }
+                }
+                // acks, and anything between packets
+                _ => continue,
+            };
+
+            if send.send(input).is_err() {
+                break;
+            }
+        }
+    });
+
+    recv
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex_bytes(text: &[u8]) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    // the digits are worked through as bytes, so anything that isn't ascii is
+    // just an invalid digit
+    let digit = |byte: u8| (byte as char).to_digit(16);
+    text.chunks(2)
+        .map(|pair| Some((digit(pair[0])? * 16 + digit(pair[1])?) as u8))
+        .collect()
+}
+
+fn parse_number(text: &[u8]) -> Option<usize> {
+    usize::from_str_radix(std::str::from_utf8(text).ok()?, 16).ok()
+}
+
+// "addr,len" as sent with m, M, X and qXfer
+fn parse_range(text: &[u8]) -> Option<(usize, usize)> {
+    let comma = text.iter().position(|byte| *byte == b',')?;
+    Some((
+        parse_number(&text[..comma])?,
+        parse_number(&text[comma + 1..])?,
+    ))
+}
+
+// the register set for gdb's qXfer:features:read
+fn target_xml() -> String {
+    let regs: String = REGISTERS
+        .iter()
+        .map(|(name, bits, kind)| {
+            format!(
+                "    <reg name=\"{}\" bitsize=\"{}\" type=\"{}\"/>\n",
+                name, bits, kind
+            )
+        })
+        .collect();
+
+    format!(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n  \
+         <feature name=\"org.chip8.cpu\">\n{}  </feature>\n\
+         </target>\n",
+        regs
+    )
+}
+
+// register n in hex, big-endian, None if there is no register n
+fn read_register(r: &Registers, n: usize) -> Option<String> {
+    match n {
+        0..=15 => Some(hex(&[r.v[n]])),
+        16 => Some(hex(&r.i.to_be_bytes())),
+        17 => Some(hex(&r.pc.to_be_bytes())),
+        18 => Some(hex(&[r.sp])),
+        19 => Some(hex(&[r.dt])),
+        20 => Some(hex(&[r.st])),
+        _ => None,
+    }
+}
+
+// set register n from its big-endian bytes, false if there is no register n
+// or the value is the wrong size
+fn write_register(r: &mut Registers, n: usize, bytes: &[u8]) -> bool {
+    let word = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+
+    match (n, bytes.len()) {
+        (0..=15, 1) => r.v[n] = bytes[0],
+        (16, 2) => r.i = word(bytes),
+        (17, 2) => r.pc = word(bytes),
+        (18, 1) => r.sp = bytes[0],
+        (19, 1) => r.dt = bytes[0],
+        (20, 1) => r.st = bytes[0],
+        _ => return false,
+    }
+    true
+}
+
+// the signal a stop is reported with
+fn signal(error: &EmulatorError) -> u8 {
+    match error {
+        // SIGILL
+        EmulatorError::InvalidOpcode { .. } => 4,
+        // SIGSEGV
+        EmulatorError::MemoryOutOfBounds { .. }
+        | EmulatorError::StackOverflow
+        | EmulatorError::StackUnderflow => 11,
+        // SIGTRAP
+        _ => 5,
+    }
+}
+
+struct Server<V: Video> {
+    system: System<V>,
+    debugger: Debugger,
+    out: Box<dyn Write>,
+    // packets are acknowledged until gdb asks for QStartNoAckMode
+    ack: bool,
+    // the program is running at 60 frames a second, a stop reply is due when
+    // it stops
+    running: bool,
+    // the reply to ?, why the program last stopped
+    stop: String,
+}
+
+impl<V: Video> Server<V> {
+    // load the rom and serve gdb until it detaches or kills the program, which
+    // starts stopped at its first instruction
+    fn start(
+        mut system: System<V>,
+        options: &Options,
+        reader: Box<dyn Read + Send>,
+        out: Box<dyn Write>,
+    ) -> Result<(), EmulatorError> {
+        system.load_rom(&options.rom)?;
+        system.set_history(options.history);
+        system.render();
+
+        let mut server = Server {
+            system,
+            debugger: Debugger::new(),
+            out,
+            ack: true,
+            running: false,
+            stop: "S05".to_string(),
+        };
+        server.run(read_packets(reader))
+    }
+
+    fn run(&mut self, packets: Receiver<Input>) -> Result<(), EmulatorError> {
+        let mut next_frame = Instant::now();
+
+        loop {
+            if let Some(Command::Quit) = self.system.poll_input()? {
+                break;
+            }
+
+            // a frame runs every 1/60s while running, packets are handled
+            // while waiting for the next one
+            let now = Instant::now();
+            if now >= next_frame && self.running {
+                let stop = self.debugger.run_frame(&mut self.system);
+                self.system.render();
+
+                match stop {
+                    Ok(None | Some(Stop::Done)) => {}
+                    Ok(Some(stop)) => self.report(stop)?,
+                    Err(e) => self.faulted(&e)?,
+                }
+            }
+
+            if now >= next_frame {
+                next_frame = (next_frame + FRAME).max(now);
+            }
+
+            let input =
+                match packets.recv_timeout(next_frame.saturating_duration_since(Instant::now())) {
+                    Ok(input) => input,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+
+            match input {
+                Input::Packet(data) => {
+                    if self.ack {
+                        self.out.write_all(b"+")?;
+                    }
+                    if !self.packet(&data)? {
+                        break;
+                    }
+                }
+                Input::Corrupt => self.out.write_all(b"-")?,
+                Input::Interrupt if self.running => self.stopped("S02")?,
+                Input::Interrupt => {}
+            }
+            self.out.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        // binary replies escape the characters that frame packets
+        let mut escaped = Vec::with_capacity(data.len());
+        for byte in data.bytes() {
+            match byte {
+                b'$' | b'#' | b'}' | b'*' => escaped.extend([b'}', byte ^ 0x20]),
+                _ => escaped.push(byte),
+            }
+        }
+
+        let sum = escaped
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        self.out.write_all(b"$")?;
+        self.out.write_all(&escaped)?;
+        write!(self.out, "#{:02x}", sum)?;
+        self.out.flush()
+    }
+
+    // the program stopped, tell gdb why
+    fn stopped(&mut self, reply: &str) -> io::Result<()> {
+        self.running = false;
+        self.stop = reply.to_string();
+        self.send(reply)
+    }
+
+    // tell gdb why the program stopped
+    fn report(&mut self, stop: Stop) -> io::Result<()> {
+        match stop {
+            Stop::Watchpoint { id, access, .. } => self.watched(id, access),
+            Stop::Exited => self.stopped("W00"),
+            Stop::HistoryStart => self.stopped(HISTORY_START),
+            _ => self.stopped("S05"),
+        }
+    }
+
+    // an instruction made an access watchpoint id watches for. gdb wants the
+    // address as one it is watching, so an access that started before the
+    // watched bytes is reported at the first of them.
+    fn watched(&mut self, id: u32, access: Access) -> io::Result<()> {
+        let w = match self.debugger.watchpoint(id) {
+            Some(w) => w,
+            None => return self.stopped("S05"),
+        };
+
+        let kind = match w.watch {
+            Watch::Write => "watch",
+            Watch::Read => "rwatch",
+            Watch::Access => "awatch",
+        };
+        self.stopped(&format!("T05{}:{:x};", kind, access.addr.max(w.addr)))
+    }
+
+    // the cpu couldn't run an instruction, stop with the signal a real cpu
+    // would raise
+    fn faulted(&mut self, error: &EmulatorError) -> io::Result<()> {
+        eprintln!("{}", error);
+        self.stopped(&format!("S{:02x}", signal(error)))
+    }
+
+    // run one instruction and report the stop
+    fn step(&mut self) -> io::Result<()> {
+        match self.debugger.step(&mut self.system) {
+            Ok(events) if events.exited => self.stopped("W00"),
+            Ok(_) => self.stopped("S05"),
+            Err(e) => self.faulted(&e),
+        }?;
+        self.system.render();
+        Ok(())
+    }
+
+    // undo one instruction and report the stop
+    fn step_back(&mut self) -> io::Result<()> {
+        match self.debugger.step_back(&mut self.system) {
+            true => self.stopped("S05")?,
+            false => self.stopped(HISTORY_START)?,
+        }
+        self.system.render();
+        Ok(())
+    }
+
+    // the reply to a monitor command, as text for gdb to print
+    fn monitor(&self, command: &str) -> String {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let addr = match words.as_slice() {
+            ["who", addr] => parse_number(addr.trim_start_matches("0x").as_bytes())
+                .and_then(|addr| u16::try_from(addr).ok()),
+            _ => {
+                return "monitor commands:\n  who <addr>  the last instruction to write addr\n"
+                    .to_string()
+            }
+        };
+
+        let addr = match addr {
+            Some(addr) => addr,
+            None => return format!("invalid address {}\n", words[1]),
+        };
+        match self.system.last_write(addr) {
+            Some(w) => {
+                let ago = self.system.cycles() - w.cycle;
+                let instructions = if ago == 1 {
+                    "instruction"
+                } else {
+                    "instructions"
+                };
+                format!(
+                    "{:#05x} was written by the instruction at {:#05x}, {} {} ago: {:#04x} -> {:#04x}\n",
+                    addr, w.pc, ago, instructions, w.old, w.new
+                )
+            }
+            None => format!("nothing in the history wrote {:#05x}\n", addr),
+        }
+    }
+
+    // handle one packet, returns false when gdb is done with the program
+    fn packet(&mut self, data: &[u8]) -> io::Result<bool> {
+        let (kind, args) = match data.split_first() {
+            Some((kind, args)) => (*kind, args),
+            None => return self.send("").map(|_| true),
+        };
+
+        match kind {
+            b'?' => self.send(&self.stop.clone())?,
+            b'g' => {
+                let r = self.system.registers();
+                let regs: String = (0..REGISTERS.len())
+                    .filter_map(|n| read_register(&r, n))
+                    .collect();
+                self.send(&regs)?;
+            }
+            b'G' => {
+                let mut r = self.system.registers();
+                let reply = match parse_hex_bytes(args) {
+                    Some(bytes) if bytes.len() == REGISTERS_SIZE => {
+                        let mut offset = 0;
+                        for (n, (_, bits, _)) in REGISTERS.iter().enumerate() {
+                            write_register(&mut r, n, &bytes[offset..offset + bits / 8]);
+                            offset += bits / 8;
+                        }
+                        self.system.set_registers(r);
+                        "OK"
+                    }
+                    _ => "E01",
+                };
+                self.send(reply)?;
+            }
+            b'p' => {
+                let r = self.system.registers();
+                let reply = parse_number(args).and_then(|n| read_register(&r, n));
+                self.send(reply.as_deref().unwrap_or("E01"))?;
+            }
+            b'P' => {
+                let mut r = self.system.registers();
+                let mut parts = args.splitn(2, |byte| *byte == b'=');
+                let n = parts.next().and_then(parse_number);
+                let bytes = parts.next().and_then(parse_hex_bytes);
+
+                let reply = match (n, bytes) {
+                    (Some(n), Some(bytes)) if write_register(&mut r, n, &bytes) => {
+                        self.system.set_registers(r);
+                        "OK"
+                    }
+                    _ => "E01",
+                };
+                self.send(reply)?;
+            }
+            b'm' => {
+                let ram = self.system.memory();
+                let reply = match parse_range(args) {
+                    Some((addr, len)) if addr < ram.len() => {
+                        hex(&ram[addr..addr.saturating_add(len).min(ram.len())])
+                    }
+                    _ => "E01".to_string(),
+                };
+                self.send(&reply)?;
+            }
+            b'M' | b'X' => {
+                let colon = args.iter().position(|byte| *byte == b':');
+                let write = colon.and_then(|colon| {
+                    let (addr, len) = parse_range(&args[..colon])?;
+                    let data = match kind {
+                        b'M' => parse_hex_bytes(&args[colon + 1..])?,
+                        _ => args[colon + 1..].to_vec(),
+                    };
+                    (data.len() == len).then_some((addr, data))
+                });
+
+                let reply = match write {
+                    Some((addr, data)) => match u16::try_from(addr) {
+                        Ok(addr) if self.system.write_memory(addr, &data).is_ok() => "OK",
+                        _ => "E01",
+                    },
+                    None => "E01",
+                };
+                self.send(reply)?;
+            }
+            // software and hardware breakpoints are the same thing here
+            b'Z' | b'z' if matches!(args.first(), Some(b'0' | b'1')) => {
+                let addr = args
+                    .get(2..)
+                    .and_then(parse_range)
+                    .and_then(|(addr, _)| u16::try_from(addr).ok());
+
+                match addr {
+                    Some(addr) => {
+                        match kind {
+                            b'Z' => self.debugger.add_breakpoint(addr),
+                            _ => self.debugger.remove_breakpoint(addr),
+                        };
+                        self.send("OK")?;
+                    }
+                    None => self.send("E01")?,
+                }
+            }
+            // write, read and access watchpoints, kind is the length
+            b'Z' | b'z' if matches!(args.first(), Some(b'2' | b'3' | b'4')) => {
+                let watch = match args[0] {
+                    b'2' => Watch::Write,
+                    b'3' => Watch::Read,
+                    _ => Watch::Access,
+                };
+                let w = args.get(2..).and_then(parse_range).and_then(|(addr, len)| {
+                    Some(Watchpoint {
+                        addr: u16::try_from(addr).ok()?,
+                        len: u16::try_from(len).ok().filter(|len| *len > 0)?,
+                        watch,
+                    })
+                });
+
+                let reply = match (kind, w) {
+                    (b'Z', Some(w)) => {
+                        self.debugger.add_watchpoint(w);
+                        "OK"
+                    }
+                    (_, Some(w)) => {
+                        let id = self.debugger.watchpoints().find(|(_, other)| *other == w);
+                        if let Some((id, _)) = id {
+                            self.debugger.remove_watchpoint(id);
+                        }
+                        "OK"
+                    }
+                    (_, None) => "E01",
+                };
+                self.send(reply)?;
+            }
+            b'c' | b's' => {
+                // continue or step from addr if one is given
+                if let Some(addr) = parse_number(args).and_then(|addr| u16::try_from(addr).ok()) {
+                    let mut r = self.system.registers();
+                    r.pc = addr;
+                    self.system.set_registers(r);
+                }
+
+                match kind {
+                    b'c' => self.running = true,
+                    _ => self.step()?,
+                }
+            }
+            b'k' => return Ok(false),
+            b'D' => {
+                self.send("OK")?;
+                return Ok(false);
+            }
+            // there is only one thread
+            b'H' | b'T' => self.send("OK")?,
+            _ => match data {
+                _ if data.starts_with(b"qSupported") => self.send(
+                    "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+;\
+                     ReverseStep+;ReverseContinue+",
+                )?,
+                b"vCont?" => self.send("vCont;c;s")?,
+                b"bs" => self.step_back()?,
+                b"bc" => {
+                    let stop = self.debugger.reverse_continue(&mut self.system);
+                    self.system.render();
+                    self.report(stop)?;
+                }
+                _ if data.starts_with(b"qRcmd,") => {
+                    let command = parse_hex_bytes(&data[b"qRcmd,".len()..]).unwrap_or_default();
+                    let reply = self.monitor(&String::from_utf8_lossy(&command));
+                    self.send(&format!("O{}", hex(reply.as_bytes())))?;
+                    self.send("OK")?;
+                }
+                b"vCont;c" | b"vCont;c:1" | b"vCont;c:-1" => self.running = true,
+                b"vCont;s" | b"vCont;s:1" | b"vCont;s:-1" => self.step()?,
+                b"qAttached" => self.send("1")?,
+                b"qC" => self.send("QC1")?,
+                b"qfThreadInfo" => self.send("m1")?,
+                b"qsThreadInfo" => self.send("l")?,
+                b"qSymbol::" => self.send("OK")?,
+                b"QStartNoAckMode" => {
+                    self.send("OK")?;
+                    self.ack = false;
+                }
+                _ if data.starts_with(b"qXfer:features:read:target.xml:") => {
+                    let range = &data[b"qXfer:features:read:target.xml:".len()..];
+                    let reply = match parse_range(range) {
+                        Some((offset, len)) => {
+                            let xml = target_xml();
+                            let start = offset.min(xml.len());
+                            let end = start.saturating_add(len).min(xml.len());
+                            let more = match end < xml.len() {
+                                true => "m",
+                                false => "l",
+                            };
+                            format!("{}{}", more, &xml[start..end])
+                        }
+                        None => "E01".to_string(),
+                    };
+                    self.send(&reply)?;
+                }
+                // an empty reply tells gdb the packet isn't supported
+                _ => self.send("")?,
+            },
+        }
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,698 @@
+use crate::opcode::{Operand, OPCODES};
+use crate::EmulatorError;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// where roms are loaded in memory, the first instruction assembles to here
+const START: usize = 0x200;
+
+// includes nested deeper than this are taken to be including themselves
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// A rom built from source, ready for System::load_rom once written to disk.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+    pub rom: Vec<u8>,
+    // the source line each instruction and piece of data came from, in
+    // address order
+    pub lines: Vec<SourceLine>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLine {
+    pub addr: u16,
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+impl Program {
+    // the source line of the instruction or data at addr
+    pub fn line_of(&self, addr: u16) -> Option<&SourceLine> {
+        self.lines.iter().rev().find(|line| line.addr <= addr)
+    }
+
+    // the address of the first instruction or data on a line of a file
+    pub fn addr_of(&self, file: &Path, line: usize) -> Option<u16> {
+        self.lines
+            .iter()
+            .find(|l| l.line == line && l.file == file)
+            .map(|l| l.addr)
+    }
+}
+
+// Assemble source written with the mnemonics of Cowgod's Chip-8 technical
+// reference. Included files are looked up from the current directory.
+//
+//   ; comments run to the end of the line
+//   speed = 3                ; a constant
+//   start:                   ; a label, the address of what follows
+//       LD V0, speed
+//       LD I, sprite
+//       DRW V0, V1, 5
+//       JP start
+//   sprite:
+//       db 0xF0, 0x90, 0xF0  ; bytes, dw stores big-endian words
+//   include "font.asm"       ; assemble another file in place
+//
+// Numbers are decimal, 0x hex or 0b binary, and operands can be expressions
+// using + - * / & | ^ << >> ~ and parentheses.
+pub fn assemble(source: &str) -> Result<Program, EmulatorError> {
+    let mut asm = Assembler::default();
+    asm.parse_source(PathBuf::from("<source>"), source, Path::new("."), 0)?;
+    asm.finish()
+}
+
+// Assemble a file, included files are looked up next to the file including
+// them.
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Program, EmulatorError> {
+    let mut asm = Assembler::default();
+    asm.parse_file(path.as_ref(), 0)?;
+    asm.finish()
+}
+
+// where something is in the source, lines and columns count from 1
+#[derive(Clone, Copy, Debug)]
+struct Pos {
+    file: usize,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Punct(&'static str),
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    Symbol(String, Pos),
+    Unary(&'static str, Box<Expr>),
+    // the position is the operator's
+    Binary(&'static str, Pos, Box<Expr>, Box<Expr>),
+}
+
+// an operand of an instruction
+#[derive(Clone, Debug)]
+enum Arg {
+    Register(u8),
+    Keyword(String),
+    Long(Expr),
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Instruction {
+        mnemonic: String,
+        args: Vec<(Arg, Pos)>,
+    },
+    Bytes(Vec<(Expr, Pos)>),
+    Words(Vec<(Expr, Pos)>),
+}
+
+// a statement that puts bytes in the rom at addr
+struct Statement {
+    pos: Pos,
+    addr: usize,
+    item: Item,
+}
+
+// constants and labels
+enum Symbol {
+    Constant(Expr),
+    Label(usize),
+}
+
+#[derive(Default)]
+struct Assembler {
+    files: Vec<PathBuf>,
+    statements: Vec<Statement>,
+    symbols: HashMap<String, (Symbol, Pos)>,
+    // address of the next statement
+    addr: usize,
+}
+
+impl Assembler {
+    fn error(&self, pos: Pos, message: String) -> EmulatorError {
+        EmulatorError::Assemble {
+            file: self.files[pos.file].display().to_string(),
+            line: pos.line,
+            column: pos.column,
+            message,
+        }
+    }
+
+    fn parse_file(&mut self, path: &Path, depth: usize) -> Result<(), EmulatorError> {
+        let source = fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        self.parse_source(path.to_path_buf(), &source, dir, depth)
+    }
+
+    // first pass: parse every line, give labels their addresses and collect
+    // the statements to encode once every symbol is known
+    fn parse_source(
+        &mut self,
+        name: PathBuf,
+        source: &str,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), EmulatorError> {
+        let file = self.files.len();
+        self.files.push(name);
+        self.addr = self.addr.max(START);
+
+        for (n, text) in source.lines().enumerate() {
+            let line_pos = Pos {
+                file,
+                line: n + 1,
+                column: 1,
+            };
+            let tokens = tokenize(text, line_pos).map_err(|(pos, e)| self.error(pos, e))?;
+            let end = Pos {
+                column: text.chars().count() + 1,
+                ..line_pos
+            };
+            let mut line = Line { tokens, at: 0, end };
+
+            // label:
+            if let (Some((Token::Ident(name), pos)), Some((Token::Punct(":"), _))) =
+                (line.peek(), line.peek_at(1))
+            {
+                let (name, pos) = (name.clone(), *pos);
+                line.at += 2;
+                self.define(name, Symbol::Label(self.addr), pos)?;
+            }
+
+            let (first, pos) = match line.next() {
+                Some((Token::Ident(first), pos)) => (first, pos),
+                Some((_, pos)) => return Err(self.error(pos, "expected an instruction".into())),
+                None => continue,
+            };
+
+            // name = value, or name equ value
+            let is_constant = match line.peek() {
+                Some((Token::Punct("="), _)) => true,
+                Some((Token::Ident(word), _)) => word.eq_ignore_ascii_case("equ"),
+                _ => false,
+            };
+            if is_constant {
+                line.at += 1;
+                let value = line.expr().map_err(|(pos, e)| self.error(pos, e))?;
+                self.end_of_line(&line)?;
+                self.define(first, Symbol::Constant(value), pos)?;
+                continue;
+            }
+
+            let item = match first.to_ascii_lowercase().as_str() {
+                "include" => {
+                    let path = match line.next() {
+                        Some((Token::Str(path), _)) => path,
+                        _ => return Err(self.error(pos, "include needs a file name".into())),
+                    };
+                    self.end_of_line(&line)?;
+
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(self.error(pos, "includes are nested too deeply".into()));
+                    }
+                    self.parse_file(&dir.join(&path), depth + 1)
+                        .map_err(|e| match e {
+                            EmulatorError::Io(io) => {
+                                self.error(pos, format!("can't include {}: {}", path, io))
+                            }
+                            e => e,
+                        })?;
+                    continue;
+                }
+                "db" => Item::Bytes(self.data(&mut line)?),
+                "dw" => Item::Words(self.data(&mut line)?),
+                _ => {
+                    let mut args = Vec::new();
+                    if line.peek().is_some() {
+                        loop {
+                            args.push(line.arg().map_err(|(pos, e)| self.error(pos, e))?);
+                            match line.next() {
+                                Some((Token::Punct(","), _)) => {}
+                                None => break,
+                                Some((_, pos)) => {
+                                    return Err(self.error(pos, "expected a comma".into()))
+                                }
+                            }
+                        }
+                    }
+                    Item::Instruction {
+                        mnemonic: first,
+                        args,
+                    }
+                }
+            };
+
+            let len = match &item {
+                Item::Instruction { args, .. } => {
+                    match args.iter().any(|(a, _)| matches!(a, Arg::Long(_))) {
+                        true => 4,
+                        false => 2,
+                    }
+                }
+                Item::Bytes(bytes) => bytes.len(),
+                Item::Words(words) => words.len() * 2,
+            };
+
+            self.statements.push(Statement {
+                pos,
+                addr: self.addr,
+                item,
+            });
+            self.addr += len;
+        }
+
+        Ok(())
+    }
+
+    // the comma separated values of db or dw. Strings stand for their bytes.
+    fn data(&self, line: &mut Line) -> Result<Vec<(Expr, Pos)>, EmulatorError> {
+        let mut values = Vec::new();
+
+        loop {
+            match line.peek() {
+                Some((Token::Str(text), pos)) => {
+                    let pos = *pos;
+                    values.extend(text.bytes().map(|b| (Expr::Number(b as i64), pos)));
+                    line.at += 1;
+                }
+                Some((_, pos)) => {
+                    let pos = *pos;
+                    values.push((line.expr().map_err(|(pos, e)| self.error(pos, e))?, pos));
+                }
+                None => return Err(self.error(line.end, "expected a value".into())),
+            }
+
+            match line.next() {
+                Some((Token::Punct(","), _)) => {}
+                None => return Ok(values),
+                Some((_, pos)) => return Err(self.error(pos, "expected a comma".into())),
+            }
+        }
+    }
+
+    fn end_of_line(&self, line: &Line) -> Result<(), EmulatorError> {
+        match line.peek() {
+            Some((_, pos)) => Err(self.error(*pos, "unexpected text at end of line".into())),
+            None => Ok(()),
+        }
+    }
+
+    fn define(&mut self, name: String, symbol: Symbol, pos: Pos) -> Result<(), EmulatorError> {
+        if let Some((_, first)) = self.symbols.get(&name) {
+            return Err(self.error(
+                pos,
+                format!("{} is already defined on line {}", name, first.line),
+            ));
+        }
+
+        self.symbols.insert(name, (symbol, pos));
+        Ok(())
+    }
+
+    // second pass: encode every statement now that all symbols are known
+    fn finish(self) -> Result<Program, EmulatorError> {
+        let mut program = Program::default();
+
+        for statement in &self.statements {
+            program.lines.push(SourceLine {
+                addr: statement.addr as u16,
+                file: self.files[statement.pos.file].clone(),
+                line: statement.pos.line,
+            });
+
+            let bytes = match &statement.item {
+                Item::Instruction { mnemonic, args } => {
+                    self.encode(mnemonic, args, statement.pos)?
+                }
+                Item::Bytes(values) => {
+                    let mut bytes = Vec::new();
+                    for (value, pos) in values {
+                        bytes.push(self.value(value, *pos, -0x80, 0xFF)? as u8);
+                    }
+                    bytes
+                }
+                Item::Words(values) => {
+                    let mut bytes = Vec::new();
+                    for (value, pos) in values {
+                        let word = self.value(value, *pos, -0x8000, 0xFFFF)? as u16;
+                        bytes.extend_from_slice(&word.to_be_bytes());
+                    }
+                    bytes
+                }
+            };
+
+            program.rom.extend_from_slice(&bytes);
+            if START + program.rom.len() > 0x10000 {
+                return Err(self.error(statement.pos, "program doesn't fit in memory".into()));
+            }
+        }
+
+        Ok(program)
+    }
+
+    // encode an instruction using the first entry of the opcode table with the
+    // same mnemonic and the same kinds of operands
+    fn encode(
+        &self,
+        mnemonic: &str,
+        args: &[(Arg, Pos)],
+        pos: Pos,
+    ) -> Result<Vec<u8>, EmulatorError> {
+        let mut args = args.to_vec();
+
+        // SHR Vx and SHL Vx are short for SHR Vx, Vx and SHL Vx, Vx
+        if matches!(mnemonic.to_ascii_uppercase().as_str(), "SHR" | "SHL") && args.len() == 1 {
+            args.push(args[0].clone());
+        }
+
+        let mut candidates = OPCODES
+            .iter()
+            .filter(|op| op.mnemonic.eq_ignore_ascii_case(mnemonic))
+            .peekable();
+        if candidates.peek().is_none() {
+            return Err(self.error(pos, format!("unknown instruction {}", mnemonic)));
+        }
+
+        let op = candidates
+            .find(|op| {
+                op.operands.len() == args.len()
+                    && op.operands.iter().zip(&args).all(|(operand, (arg, _))| {
+                        match (operand, arg) {
+                            (Operand::Vx | Operand::Vy, Arg::Register(_)) => true,
+                            (Operand::Fixed("V0"), Arg::Register(0)) => true,
+                            (Operand::Fixed(keyword), Arg::Keyword(word)) => {
+                                keyword.eq_ignore_ascii_case(word)
+                            }
+                            (Operand::Long, Arg::Long(_)) => true,
+                            (
+                                Operand::Nibble | Operand::Byte | Operand::Addr | Operand::Plane,
+                                Arg::Expr(_),
+                            ) => true,
+                            _ => false,
+                        }
+                    })
+            })
+            .ok_or_else(|| {
+                self.error(
+                    pos,
+                    format!("invalid operands for {}", mnemonic.to_uppercase()),
+                )
+            })?;
+
+        let mut word = op.pattern;
+        let mut long = None;
+
+        for (operand, (arg, pos)) in op.operands.iter().zip(&args) {
+            let value = match arg {
+                Arg::Register(r) => *r as i64,
+                Arg::Expr(e) => match operand {
+                    Operand::Byte => self.value(e, *pos, -0x80, 0xFF)?,
+                    Operand::Addr => self.value(e, *pos, 0, 0xFFF)?,
+                    _ => self.value(e, *pos, 0, 0xF)?,
+                },
+                Arg::Long(e) => {
+                    long = Some(self.value(e, *pos, 0, 0xFFFF)? as u16);
+                    continue;
+                }
+                Arg::Keyword(_) => continue,
+            };
+
+            word = operand.insert(word, value as u16);
+        }
+
+        let mut bytes = word.to_be_bytes().to_vec();
+        if let Some(long) = long {
+            bytes.extend_from_slice(&long.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+
+    // evaluate an expression that has to be between min and max
+    fn value(&self, expr: &Expr, pos: Pos, min: i64, max: i64) -> Result<i64, EmulatorError> {
+        let value = self.eval(expr, &mut Vec::new())?;
+
+        match (min..=max).contains(&value) {
+            true => Ok(value),
+            false => Err(self.error(
+                pos,
+                format!(
+                    "{} is out of range, it needs to be {} to {:#X}",
+                    value, min, max
+                ),
+            )),
+        }
+    }
+
+    // evaluate an expression, resolving is the constants being evaluated
+    // already so constants defined in terms of themselves are caught
+    fn eval(&self, expr: &Expr, resolving: &mut Vec<String>) -> Result<i64, EmulatorError> {
+        Ok(match expr {
+            Expr::Number(n) => *n,
+            Expr::Symbol(name, pos) => match self.symbols.get(name) {
+                Some((Symbol::Label(addr), _)) => *addr as i64,
+                Some((Symbol::Constant(value), _)) => {
+                    if resolving.contains(name) {
+                        return Err(
+                            self.error(*pos, format!("{} is defined in terms of itself", name))
+                        );
+                    }
+
+                    resolving.push(name.clone());
+                    let value = self.eval(value, resolving)?;
+                    resolving.pop();
+                    value
+                }
+                None => return Err(self.error(*pos, format!("{} isn't defined", name))),
+            },
+            Expr::Unary(op, a) => {
+                let a = self.eval(a, resolving)?;
+                match *op {
+                    "-" => a.wrapping_neg(),
+                    _ => !a,
+                }
+            }
+            Expr::Binary(op, pos, a, b) => {
+                let (a, b) = (self.eval(a, resolving)?, self.eval(b, resolving)?);
+                match *op {
+                    "+" => a.wrapping_add(b),
+                    "-" => a.wrapping_sub(b),
+                    "*" => a.wrapping_mul(b),
+                    "/" if b == 0 => return Err(self.error(*pos, "division by zero".to_string())),
+                    "/" => a.wrapping_div(b),
+                    "&" => a & b,
+                    "|" => a | b,
+                    "^" => a ^ b,
+                    "<<" => a.wrapping_shl(b as u32),
+                    _ => a.wrapping_shr(b as u32),
+                }
+            }
+        })
+    }
+}
+
+// binary operators from loosest to tightest binding
+const PRECEDENCE: &[&[&str]] = &[
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/"],
+];
+
+// keywords that stand for themselves as operands, see the opcode table
+const KEYWORDS: &[&str] = &["I", "DT", "ST", "K", "F", "HF", "B", "R", "PITCH"];
+
+// the tokens of one line being parsed
+struct Line {
+    tokens: Vec<(Token, Pos)>,
+    at: usize,
+    // the position just past the end of the line
+    end: Pos,
+}
+
+type ParseError = (Pos, String);
+
+impl Line {
+    fn peek(&self) -> Option<&(Token, Pos)> {
+        self.tokens.get(self.at)
+    }
+
+    fn peek_at(&self, n: usize) -> Option<&(Token, Pos)> {
+        self.tokens.get(self.at + n)
+    }
+
+    fn next(&mut self) -> Option<(Token, Pos)> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn arg(&mut self) -> Result<(Arg, Pos), ParseError> {
+        let (token, pos) = match self.peek() {
+            Some((token, pos)) => (token.clone(), *pos),
+            None => return Err((self.end, "expected an operand".into())),
+        };
+
+        let arg = match token {
+            Token::Punct("[") => {
+                self.at += 1;
+                match (self.next(), self.next()) {
+                    (Some((Token::Ident(i), _)), Some((Token::Punct("]"), _)))
+                        if i.eq_ignore_ascii_case("I") =>
+                    {
+                        Arg::Keyword("[I]".into())
+                    }
+                    _ => return Err((pos, "expected [I]".into())),
+                }
+            }
+            Token::Ident(word) => {
+                let upper = word.to_ascii_uppercase();
+                let register = upper
+                    .strip_prefix('V')
+                    .filter(|r| r.len() == 1)
+                    .and_then(|r| u8::from_str_radix(r, 16).ok());
+
+                if let Some(r) = register {
+                    self.at += 1;
+                    Arg::Register(r)
+                } else if KEYWORDS.contains(&upper.as_str()) {
+                    self.at += 1;
+                    Arg::Keyword(upper)
+                } else if upper == "LONG" {
+                    self.at += 1;
+                    Arg::Long(self.expr()?)
+                } else {
+                    Arg::Expr(self.expr()?)
+                }
+            }
+            _ => Arg::Expr(self.expr()?),
+        };
+
+        Ok((arg, pos))
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, ParseError> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        while let Some((Token::Punct(op), pos)) = self.peek() {
+            let (op, pos) = (*op, *pos);
+            if !PRECEDENCE[level].contains(&op) {
+                break;
+            }
+
+            self.at += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, pos, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        match self.next() {
+            Some((Token::Number(n), _)) => Ok(Expr::Number(n)),
+            Some((Token::Ident(name), pos)) => Ok(Expr::Symbol(name, pos)),
+            Some((Token::Punct(op @ ("-" | "~")), _)) => {
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            Some((Token::Punct("("), pos)) => {
+                let expr = self.expr()?;
+                match self.next() {
+                    Some((Token::Punct(")"), _)) => Ok(expr),
+                    _ => Err((pos, "missing )".into())),
+                }
+            }
+            Some((_, pos)) => Err((pos, "expected a value".into())),
+            None => Err((self.end, "expected a value".into())),
+        }
+    }
+}
+
+const PUNCTUATION: &[&str] = &[
+    "<<", ">>", ",", ":", "=", "+", "-", "*", "/", "&", "|", "^", "~", "(", ")", "[", "]",
+];
+
+// split a line into tokens, dropping the comment
+fn tokenize(text: &str, line: Pos) -> Result<Vec<(Token, Pos)>, ParseError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let pos = Pos {
+            column: i + 1,
+            ..line
+        };
+
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|c| *c == '"')
+                .ok_or((pos, "unterminated string".to_string()))?;
+            tokens.push((Token::Str(chars[i + 1..i + 1 + end].iter().collect()), pos));
+            i += end + 2;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            let len = chars[i..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '.')
+                .count();
+            let word: String = chars[i..i + len].iter().collect();
+
+            let token = match c.is_ascii_digit() {
+                true => {
+                    Token::Number(number(&word).ok_or((pos, format!("invalid number {}", word)))?)
+                }
+                false => Token::Ident(word),
+            };
+            tokens.push((token, pos));
+            i += len;
+        } else {
+            let rest: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let punct = PUNCTUATION
+                .iter()
+                .find(|p| rest.starts_with(**p))
+                .ok_or((pos, format!("unexpected character {}", c)))?;
+            tokens.push((Token::Punct(punct), pos));
+            i += punct.len();
+        }
+    }
+
+    Ok(tokens)
+}
+
+// a decimal, 0x hex or 0b binary number, with optional _ separators
+fn number(word: &str) -> Option<i64> {
+    let word = word.replace('_', "");
+    let lower = word.to_ascii_lowercase();
+
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
@@ -0,0 +1,291 @@
+use crate::video::Video;
+use crate::{EmulatorError, System};
+
+use std::fmt;
+
+// An expression over the state of the machine that a debugger stops on when
+// it is true, e.g. `pc == 0x2A4 && V3 > 10 && [I] == 0xFF`.
+//
+//   v0-vf, i, pc, sp, dt, st   registers and timers, in any case
+//   [expr]                     the byte of memory at expr
+//   12, 0x2A4, 0b1010          numbers
+//   || && | ^ & == != < <= > >= << >> + - * / %
+//                              operators, from lowest precedence to highest,
+//                              with C's meanings
+//   ! - ~ ( )                  negation, unary minus, not and grouping
+//
+// Everything is worked out as a signed 64 bit number. Comparisons give 1 or
+// 0, and the condition is true when the value isn't 0. Memory past the end
+// reads as 0, and dividing by 0 gives 0.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    text: String,
+    expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    Register(Register),
+    Byte(Box<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Register {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+// binary operators and their precedence, higher binds tighter. Two character
+// operators come before the one character operators they start with.
+const BINARY: [(&str, u8); 18] = [
+    ("||", 1),
+    ("&&", 2),
+    ("==", 6),
+    ("!=", 6),
+    ("<=", 7),
+    (">=", 7),
+    ("<<", 8),
+    (">>", 8),
+    ("|", 3),
+    ("^", 4),
+    ("&", 5),
+    ("<", 7),
+    (">", 7),
+    ("+", 9),
+    ("-", 9),
+    ("*", 10),
+    ("/", 10),
+    ("%", 10),
+];
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, EmulatorError> {
+        let mut parser = Parser { text, pos: 0 };
+        let expr = parser.expr(0)?;
+
+        parser.skip_space();
+        if parser.pos < text.len() {
+            return Err(parser.error("expected an operator"));
+        }
+
+        Ok(Condition {
+            text: text.trim().to_string(),
+            expr,
+        })
+    }
+
+    // the value of the expression for the system as it is now
+    pub fn value<V: Video>(&self, system: &System<V>) -> i64 {
+        eval(&self.expr, system)
+    }
+
+    pub fn is_true<V: Video>(&self, system: &System<V>) -> bool {
+        self.value(system) != 0
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn eval<V: Video>(expr: &Expr, system: &System<V>) -> i64 {
+    match expr {
+        Expr::Number(n) => *n,
+        Expr::Register(register) => {
+            let r = system.registers();
+            match register {
+                Register::V(x) => r.v[*x] as i64,
+                Register::I => r.i as i64,
+                Register::Pc => r.pc as i64,
+                Register::Sp => r.sp as i64,
+                Register::Dt => r.dt as i64,
+                Register::St => r.st as i64,
+            }
+        }
+        Expr::Byte(addr) => {
+            let addr = eval(addr, system);
+            usize::try_from(addr)
+                .ok()
+                .and_then(|addr| system.memory().get(addr))
+                .map_or(0, |byte| *byte as i64)
+        }
+        Expr::Unary(op, a) => {
+            let a = eval(a, system);
+            match *op {
+                "!" => (a == 0) as i64,
+                "-" => a.wrapping_neg(),
+                _ => !a,
+            }
+        }
+        Expr::Binary(op, a, b) => {
+            let a = eval(a, system);
+
+            // && and || don't look at the right side unless they need to
+            match *op {
+                "&&" => return (a != 0 && eval(b, system) != 0) as i64,
+                "||" => return (a != 0 || eval(b, system) != 0) as i64,
+                _ => {}
+            }
+
+            let b = eval(b, system);
+            match *op {
+                "|" => a | b,
+                "^" => a ^ b,
+                "&" => a & b,
+                "==" => (a == b) as i64,
+                "!=" => (a != b) as i64,
+                "<" => (a < b) as i64,
+                "<=" => (a <= b) as i64,
+                ">" => (a > b) as i64,
+                ">=" => (a >= b) as i64,
+                "<<" => a.wrapping_shl(b as u32),
+                ">>" => a.wrapping_shr(b as u32),
+                "+" => a.wrapping_add(b),
+                "-" => a.wrapping_sub(b),
+                "*" => a.wrapping_mul(b),
+                "/" => a.checked_div(b).unwrap_or(0),
+                _ => a.checked_rem(b).unwrap_or(0),
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> EmulatorError {
+        EmulatorError::InvalidCondition(format!("{} at column {}", message, self.pos + 1))
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    // take text if it comes next
+    fn eat(&mut self, text: &str) -> bool {
+        self.skip_space();
+        match self.rest().starts_with(text) {
+            true => {
+                self.pos += text.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    // binary operators of precedence min or higher, climbing to the tighter
+    // ones
+    fn expr(&mut self, min: u8) -> Result<Expr, EmulatorError> {
+        let mut left = self.unary()?;
+
+        loop {
+            self.skip_space();
+            let op = BINARY
+                .iter()
+                .find(|(op, precedence)| *precedence >= min && self.rest().starts_with(op));
+            let (op, precedence) = match op {
+                Some(op) => *op,
+                None => return Ok(left),
+            };
+
+            // a one character operator found where the two character one it
+            // starts has too low a precedence, e.g. | in a || b, ends here
+            let longer = BINARY.iter().any(|(other, _)| {
+                other.len() == 2 && other.starts_with(op) && self.rest().starts_with(other)
+            });
+            if op.len() == 1 && longer {
+                return Ok(left);
+            }
+
+            self.pos += op.len();
+            let right = self.expr(precedence + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, EmulatorError> {
+        for op in ["!", "-", "~"] {
+            if self.eat(op) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, EmulatorError> {
+        if self.eat("(") {
+            let expr = self.expr(0)?;
+            return match self.eat(")") {
+                true => Ok(expr),
+                false => Err(self.error("expected )")),
+            };
+        }
+
+        if self.eat("[") {
+            let addr = self.expr(0)?;
+            return match self.eat("]") {
+                true => Ok(Expr::Byte(Box::new(addr))),
+                false => Err(self.error("expected ]")),
+            };
+        }
+
+        self.skip_space();
+        let word: String = self
+            .rest()
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect();
+        if word.is_empty() {
+            return Err(self.error("expected a number, register or ["));
+        }
+
+        let lower = word.to_ascii_lowercase();
+        let expr = match lower.as_str() {
+            "i" => Expr::Register(Register::I),
+            "pc" => Expr::Register(Register::Pc),
+            "sp" => Expr::Register(Register::Sp),
+            "dt" => Expr::Register(Register::Dt),
+            "st" => Expr::Register(Register::St),
+            _ if lower.len() == 2 && lower.starts_with('v') => {
+                match usize::from_str_radix(&lower[1..], 16) {
+                    Ok(x) => Expr::Register(Register::V(x)),
+                    Err(_) => return Err(self.error(&format!("there is no register {}", word))),
+                }
+            }
+            _ => {
+                let number = match (lower.strip_prefix("0x"), lower.strip_prefix("0b")) {
+                    (Some(hex), _) => i64::from_str_radix(hex, 16),
+                    (_, Some(binary)) => i64::from_str_radix(binary, 2),
+                    _ => lower.parse(),
+                };
+                match number {
+                    Ok(n) => Expr::Number(n),
+                    Err(_) => {
+                        return Err(self.error(&format!("{} isn't a number or register", word)))
+                    }
+                }
+            }
+        };
+
+        self.pos += word.len();
+        Ok(expr)
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{Quirks, RngKind};
+
+// The Chip-8 dialect a program was written for. Each mode runs everything the
+// ones before it do, so modes compare by how much they support.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Mode {
+    // the original Chip-8 instruction set
+    Chip8,
+    // SUPER-CHIP 1.1: 128x64 hi-res mode, scrolling, the large font and the
+    // RPL user flags on top of Chip-8
+    SuperChip,
+    // XO-CHIP: SUPER-CHIP plus 64KiB of memory, two bitplanes for 4 colours,
+    // an audio pattern buffer and a few extra instructions
+    XoChip,
+}
+
+// How fast the cpu runs, either as a fixed number of instructions every 60Hz
+// frame or as a target number of instructions per second.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speed {
+    InstructionsPerFrame(u32),
+    Hz(u32),
+}
+
+impl Speed {
+    // number of instructions to run in the next frame. Rates that aren't a
+    // multiple of 60Hz carry the remainder over to later frames in remainder.
+    pub(crate) fn instructions(&self, remainder: &mut u32) -> u32 {
+        match *self {
+            Speed::InstructionsPerFrame(n) => n,
+            Speed::Hz(hz) => {
+                let total = *remainder + hz;
+                *remainder = total % 60;
+                total / 60
+            }
+        }
+    }
+}
+
+// Everything that decides how a program is run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub mode: Mode,
+    pub quirks: Quirks,
+    // number of instructions run in each 60Hz frame
+    pub speed: Speed,
+    // the random number generator Cxkk uses and what it starts from. Runs with
+    // the same seed and the same input always turn out the same.
+    pub rng: RngKind,
+    pub seed: u64,
+}
+
+impl Config {
+    // Chip-8 as it ran on the COSMAC VIP
+    pub fn chip8() -> Config {
+        Config {
+            mode: Mode::Chip8,
+            quirks: Quirks::cosmac_vip(),
+            speed: Speed::InstructionsPerFrame(11),
+            rng: RngKind::Xorshift,
+            seed: 0,
+        }
+    }
+
+    // SUPER-CHIP 1.1 on the HP-48
+    pub fn superchip() -> Config {
+        Config {
+            mode: Mode::SuperChip,
+            quirks: Quirks::superchip(),
+            speed: Speed::InstructionsPerFrame(30),
+            rng: RngKind::Xorshift,
+            seed: 0,
+        }
+    }
+
+    // XO-CHIP as implemented by Octo
+    pub fn xochip() -> Config {
+        Config {
+            mode: Mode::XoChip,
+            quirks: Quirks::octo(),
+            speed: Speed::InstructionsPerFrame(200),
+            rng: RngKind::Xorshift,
+            seed: 0,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::chip8()
+    }
+}
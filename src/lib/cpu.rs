@@ -1,9 +1,38 @@
 use crate::instruction::Instruction;
-use crate::memory::Memory;
+use crate::memory::{Memory, BIG_FONT_LOCATION, FONT_LOCATION};
+use crate::state::{invalid, State, StateWriter};
 use crate::system::Flags;
-use rand::{thread_rng, Rng};
+use crate::{Config, EmulatorError, Keypad, Mode, Op, Quirks};
+
+// Fx0A stops the cpu until a key is pressed and, like on the COSMAC VIP,
+// released again. The key is stored in Vx once the wait is over.
+#[derive(Clone, Copy)]
+pub(crate) struct KeyWait {
+    // register x the key is stored in
+    reg: u8,
+    // keys already down when the wait started. They only count once they have
+    // been let go and pressed again.
+    held: Keypad,
+    // the key pressed during the wait, if any
+    pressed: Option<u8>,
+}
+
+// The registers and timers of the cpu, as shown and changed by a debugger.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Registers {
+    // V0 to VF
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    // the number of return addresses on the stack
+    pub sp: u8,
+    // delay and sound timers
+    pub dt: u8,
+    pub st: u8,
+}
 
 // Chip-8 instructions are 2 bytes long
+#[derive(Clone)]
 pub struct CPU {
     // delay timer decrements to zero at a rate of 60Hz
     dt: u8,
@@ -17,10 +46,21 @@ pub struct CPU {
     sp: u8,
     // gerneral purpose registers V[x] from (0 <= x <= F)
     reg: [u8; 16],
+    // SUPER-CHIP RPL user flags, saved and restored by Fx75 and Fx85.
+    // SUPER-CHIP has 8 of them, XO-CHIP 16.
+    rpl: [u8; 16],
+    // XO-CHIP audio pitch register, set by Fx3A
+    pitch: u8,
+    // set while Fx0A is blocking the cpu waiting for a key
+    wait: Option<KeyWait>,
+    // which instruction set the cpu runs
+    mode: Mode,
+    // which reading of the ambiguous instructions to use
+    quirks: Quirks,
 }
 
 impl CPU {
-    pub(crate) fn init() -> CPU {
+    pub(crate) fn init(config: Config) -> CPU {
         CPU {
             dt: 60,
             st: 60,
@@ -28,15 +68,136 @@ impl CPU {
             pc: 0x200,
             sp: 0,
             reg: [0; 16],
+            rpl: [0; 16],
+            pitch: 64,
+            wait: None,
+            mode: config.mode,
+            quirks: config.quirks,
         }
     }
 
     // returns the memory address held by the PC register.
-    // the program counter is incremented by two bytes
-    pub(crate) fn register_pc(&mut self) -> u16 {
-        let loc = self.pc;
-        self.pc += 0x02;
-        loc
+    pub(crate) fn register_pc(&self) -> u16 {
+        self.pc
+    }
+
+    // move the program counter past an instruction len bytes long
+    pub(crate) fn advance_pc(&mut self, len: u16) {
+        self.pc = self.pc.wrapping_add(len);
+    }
+
+    pub(crate) fn registers(&self) -> Registers {
+        Registers {
+            v: self.reg,
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+        }
+    }
+
+    pub(crate) fn set_registers(&mut self, registers: Registers) {
+        self.reg = registers.v;
+        self.i = registers.i;
+        self.pc = registers.pc;
+        self.sp = registers.sp;
+        self.dt = registers.dt;
+        self.st = registers.st;
+    }
+
+    // returns the XO-CHIP audio pitch register
+    pub(crate) fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // the register Fx0A will store the key in while the cpu is waiting for one
+    pub(crate) fn key_wait(&self) -> Option<u8> {
+        self.wait.map(|wait| wait.reg)
+    }
+
+    // check the keypad while Fx0A is waiting. A key counts once it is pressed
+    // and then released, or as soon as it is pressed with the key_press quirk.
+    pub(crate) fn poll_key_wait(&mut self, keypad: &Keypad) {
+        let wait = match self.wait.as_mut() {
+            Some(wait) => wait,
+            None => return,
+        };
+
+        for key in 0..16 {
+            if !keypad.is_pressed(key) {
+                wait.held.release(key);
+            }
+        }
+
+        if wait.pressed.is_none() {
+            wait.pressed = (0..16).find(|k| keypad.is_pressed(*k) && !wait.held.is_pressed(*k));
+        }
+
+        if let Some(key) = wait.pressed {
+            if self.quirks.key_press || !keypad.is_pressed(key) {
+                self.reg[wait.reg as usize] = key;
+                self.wait = None;
+            }
+        }
+    }
+
+    // write the registers, timers and wait state to a save state
+    pub(crate) fn save(&self, w: &mut StateWriter) {
+        w.chunk(b"CPU ", |w| {
+            w.u8(self.mode as u8);
+            w.u8(self.dt);
+            w.u8(self.st);
+            w.u16(self.i);
+            w.u16(self.pc);
+            w.u8(self.sp);
+            w.bytes(&self.reg);
+            w.bytes(&self.rpl);
+            w.u8(self.pitch);
+
+            w.bool(self.wait.is_some());
+            if let Some(wait) = self.wait {
+                w.u8(wait.reg);
+                w.u16(wait.held.bits());
+                w.u8(wait.pressed.unwrap_or(0xFF));
+            }
+        });
+    }
+
+    // restore the registers, timers and wait state from a save state
+    pub(crate) fn load(&mut self, state: &State) -> Result<(), EmulatorError> {
+        let mut r = state.chunk(b"CPU ")?;
+        if r.u8()? != self.mode as u8 {
+            return Err(invalid("saved in a different mode".to_string()));
+        }
+
+        self.dt = r.u8()?;
+        self.st = r.u8()?;
+        self.i = r.u16()?;
+        self.pc = r.u16()?;
+        self.sp = r.u8()?;
+        r.bytes(&mut self.reg)?;
+        r.bytes(&mut self.rpl)?;
+        self.pitch = r.u8()?;
+
+        self.wait = match r.bool()? {
+            true => Some(KeyWait {
+                reg: r.u8()? & 0x0F,
+                held: Keypad::from_bits(r.u16()?),
+                pressed: match r.u8()? {
+                    0xFF => None,
+                    key => Some(key & 0x0F),
+                },
+            }),
+            false => None,
+        };
+
+        Ok(())
+    }
+
+    // the buzzer sounds for as long as the sound timer is running
+    pub(crate) fn sound_on(&self) -> bool {
+        self.st > 0
     }
 
     pub(crate) fn tick_timer(&mut self) {
@@ -54,350 +215,424 @@ impl CPU {
         instr: Instruction,
         flags: &mut Flags,
         mem: &mut Memory,
-    ) -> Result<(), String> {
-        match instr.itype() {
-            0x0 => self.opcode_0(instr, flags, mem),
-            0x1 => self.opcode_1(instr),
-            0x2 => self.opcode_2(instr, mem),
-            0x3 => self.opcode_3(instr),
-            0x4 => self.opcode_4(instr),
-            0x5 => self.opcode_5(instr),
-            0x6 => self.opcode_6(instr),
-            0x7 => self.opcode_7(instr),
-            0x8 => self.opcode_8(instr),
-            0x9 => self.opcode_9(instr),
-            0xA => self.opcode_a(instr),
-            0xB => self.opcode_b(instr),
-            0xC => self.opcode_c(instr),
-            0xD => self.opcode_d(instr, mem, flags),
-            0xE => self.opcode_e(instr, flags),
-            0xF => self.opcode_f(instr, mem, flags),
-            _ => Err(stringify!("Couldn't execute instruction: {}", instr).to_string()),
+    ) -> Result<(), EmulatorError> {
+        if !self.supports(instr.op()) {
+            return Err(self.invalid(&instr));
         }
-    }
 
-    // 00EE - return from subroutine
-    // 00E0 - set clear flag to clear the display
-    fn opcode_0(
-        &mut self,
-        instr: Instruction,
-        flags: &mut Flags,
-        mem: &mut Memory,
-    ) -> Result<(), String> {
-        match instr.kk() {
-            0xE0 => {
+        match instr.op() {
+            // 00E0 - CLS, set clear flag to clear the display
+            Op::Cls => {
                 flags.clear = true;
                 mem.clear_vram();
             }
-            0xEE => {
-                self.pc = mem.pop_stack();
+            // 00EE - RET, return from subroutine
+            Op::Ret => {
+                self.pc = mem.pop_stack()?;
                 self.sp -= 1;
             }
-            _ => {
-                return Err(stringify!("Unrecognized 0 opcode {}", instr).to_string());
+            // 00Cn - SCD nibble, scroll the display down n pixels
+            Op::Scd(n) => {
+                mem.scroll_down(n as usize);
+                flags.draw = true;
+            }
+            // 00Dn - SCU nibble, scroll the display up n pixels (XO-CHIP)
+            Op::Scu(n) => {
+                mem.scroll_up(n as usize);
+                flags.draw = true;
+            }
+            // 00FB - SCR, scroll the display right 4 pixels
+            Op::Scr => {
+                mem.scroll_right(4);
+                flags.draw = true;
+            }
+            // 00FC - SCL, scroll the display left 4 pixels
+            Op::Scl => {
+                mem.scroll_left(4);
+                flags.draw = true;
+            }
+            // 00FD - EXIT, stop the interpreter
+            Op::Exit => flags.exit = true,
+            // 00FE - LOW, switch to the 64x32 lo-res screen
+            Op::Low => {
+                mem.set_hires(false);
+                flags.draw = true;
+            }
+            // 00FF - HIGH, switch to the 128x64 hi-res screen
+            Op::High => {
+                mem.set_hires(true);
+                flags.draw = true;
             }
-        };
-
-        Ok(())
-    }
-
-    // 1nnn - JP addr, jump to location nnn
-    fn opcode_1(&mut self, instr: Instruction) -> Result<(), String> {
-        self.pc = instr.nnn();
-        Ok(())
-    }
-
-    // 2nnn - Call addr, call subroutine nnn
-    fn opcode_2(&mut self, instr: Instruction, mem: &mut Memory) -> Result<(), String> {
-        mem.push_stack(self.pc);
-        self.sp += 1;
-        self.pc = instr.nnn();
-
-        Ok(())
-    }
-
-    // 3xkk - SE Vx, byte, skip next instruction if Vx == kk
-    fn opcode_3(&mut self, instr: Instruction) -> Result<(), String> {
-        if self.reg[instr.x() as usize] == instr.kk() {
-            self.pc += 2;
-        }
 
-        Ok(())
-    }
+            // 1nnn - JP addr, jump to location nnn
+            Op::Jp(addr) => self.pc = addr,
 
-    // 4xkk - SNE Vx, byte, skip next instruction if Vx != kk
-    fn opcode_4(&mut self, instr: Instruction) -> Result<(), String> {
-        if self.reg[instr.x() as usize] != instr.kk() {
-            self.pc += 2;
-        }
+            // 2nnn - Call addr, call subroutine nnn
+            Op::Call(addr) => {
+                mem.push_stack(self.pc)?;
+                self.sp += 1;
+                self.pc = addr;
+            }
 
-        Ok(())
-    }
+            // 3xkk - SE Vx, byte, skip next instruction if Vx == kk
+            Op::SeByte { x, kk } => self.skip_if(self.reg[x as usize] == kk, mem),
 
-    // 5xy0 - SE Vx, Vy, skip next instruction if Vx = Vy
-    fn opcode_5(&mut self, instr: Instruction) -> Result<(), String> {
-        if self.reg[instr.x() as usize] == self.reg[instr.y() as usize] {
-            self.pc += 2;
-        }
+            // 4xkk - SNE Vx, byte, skip next instruction if Vx != kk
+            Op::SneByte { x, kk } => self.skip_if(self.reg[x as usize] != kk, mem),
 
-        Ok(())
-    }
+            // 5xy0 - SE Vx, Vy, skip next instruction if Vx = Vy
+            Op::Se { x, y } => self.skip_if(self.reg[x as usize] == self.reg[y as usize], mem),
 
-    // 6xkk - LD VX, byte, set Vx = kk
-    fn opcode_6(&mut self, instr: Instruction) -> Result<(), String> {
-        self.reg[instr.x() as usize] = instr.kk();
-        Ok(())
-    }
+            // 5xy2 - SAVE Vx - Vy, store Vx through Vy in memory starting at location I.
+            // I is left unchanged.
+            Op::Save { x, y } => {
+                for (offset, reg) in register_range(x, y).enumerate() {
+                    mem.write_byte(self.i.wrapping_add(offset as u16), self.reg[reg])?;
+                }
+            }
+            // 5xy3 - LOAD Vx - Vy, read Vx through Vy from memory starting at location I.
+            // I is left unchanged.
+            Op::Load { x, y } => {
+                for (offset, reg) in register_range(x, y).enumerate() {
+                    self.reg[reg] = mem.read_byte(self.i.wrapping_add(offset as u16))?;
+                }
+            }
 
-    // 7xkk - ADD Vx, byte, set Vx = Vx + kk
-    fn opcode_7(&mut self, instr: Instruction) -> Result<(), String> {
-        let x_val = self.reg[instr.x() as usize];
+            // 6xkk - LD VX, byte, set Vx = kk
+            Op::LdByte { x, kk } => self.reg[x as usize] = kk,
 
-        self.reg[instr.x() as usize] = x_val.wrapping_add(instr.kk());
-        Ok(())
-    }
+            // 7xkk - ADD Vx, byte, set Vx = Vx + kk
+            Op::AddByte { x, kk } => {
+                self.reg[x as usize] = self.reg[x as usize].wrapping_add(kk);
+            }
 
-    // 8xy0 - 8xy7, 8xyE opcodes
-    fn opcode_8(&mut self, instr: Instruction) -> Result<(), String> {
-        match instr.n() {
             // 8xy0 - LD Vx, vY: set Vx = Vy
-            0x0 => self.reg[instr.x() as usize] = self.reg[instr.y() as usize],
+            Op::Ld { x, y } => self.reg[x as usize] = self.reg[y as usize],
 
             // 8xy1 - OF Vx, Vy: set Vx = Vx OR Vy
-            0x1 => self.reg[instr.x() as usize] |= self.reg[instr.y() as usize],
+            Op::Or { x, y } => {
+                self.reg[x as usize] |= self.reg[y as usize];
+                self.vf_reset();
+            }
 
             // 8xy2 - AND Vx, Vy: Set Vx = Vx AND Vy
-            0x2 => self.reg[instr.x() as usize] &= self.reg[instr.y() as usize],
+            Op::And { x, y } => {
+                self.reg[x as usize] &= self.reg[y as usize];
+                self.vf_reset();
+            }
 
             // 8xy3 - XOR Vx, Vy: Set Vx = Vx XOR Vy
-            0x3 => self.reg[instr.x() as usize] ^= self.reg[instr.y() as usize],
+            Op::Xor { x, y } => {
+                self.reg[x as usize] ^= self.reg[y as usize];
+                self.vf_reset();
+            }
 
             // 8xy4 - ADD Vx, Vy: Set Vx = Vx + Vy, set Vf = carry
-            0x4 => {
-                let sum: u16 =
-                    self.reg[instr.x() as usize] as u16 + self.reg[instr.y() as usize] as u16;
-
-                if sum > 255 {
-                    self.reg[0xF] = 1;
-                } else {
-                    self.reg[0xF] = 0;
-                }
+            // VF is written last so the flag wins when x is F
+            Op::Add { x, y } => {
+                let (sum, carry) = self.reg[x as usize].overflowing_add(self.reg[y as usize]);
 
-                self.reg[instr.x() as usize] = sum as u8;
+                self.reg[x as usize] = sum;
+                self.reg[0xF] = carry as u8;
             }
-            // 8xy5 - SUB Vx, Vy: Set Vx = Vx - Vy, set VF = NOT borrow, if Vx > Vy, then VF is set to 1
-            0x5 => {
-                let x = self.reg[instr.x() as usize];
-                let y = self.reg[instr.y() as usize];
+            // 8xy5 - SUB Vx, Vy: Set Vx = Vx - Vy, set VF = NOT borrow, if Vx >= Vy, then VF is set to 1
+            Op::Sub { x, y } => {
+                let (vx, vy) = (self.reg[x as usize], self.reg[y as usize]);
 
-                self.reg[0xF] = 0;
-                if x > y {
-                    self.reg[0xF] = 1;
-                }
                 // wrapping_sub to keep from overflowing
-                self.reg[instr.x() as usize] = x.wrapping_sub(y);
+                self.reg[x as usize] = vx.wrapping_sub(vy);
+                self.reg[0xF] = (vx >= vy) as u8;
             }
             // 8xy6 - SHR Vx {, Vy}: Set Vx = Vx SHR 1
-            0x6 => {
-                let vx = instr.x() as usize;
+            // without the shift quirk Vy is shifted into Vx instead
+            Op::Shr { x, y } => {
+                let val = self.shift_source(x, y);
 
-                self.reg[0xF] = self.reg[vx] & 0x01;
-                self.reg[vx] >>= 1;
+                self.reg[x as usize] = val >> 1;
+                self.reg[0xF] = val & 0x01;
             }
             // 8xy7 - SUBN Vx, Vy: Set Vx = Vy - Vx, set VF = NOT borrow
-            0x7 => {
-                let x = self.reg[instr.x() as usize];
-                let y = self.reg[instr.y() as usize];
+            Op::Subn { x, y } => {
+                let (vx, vy) = (self.reg[x as usize], self.reg[y as usize]);
 
-                self.reg[0xF] = 0;
-                if y > x {
-                    self.reg[0xF] = 1;
-                }
                 // wrapping_sub to keep from overflowing
-                self.reg[instr.x() as usize] = y.wrapping_sub(x);
+                self.reg[x as usize] = vy.wrapping_sub(vx);
+                self.reg[0xF] = (vy >= vx) as u8;
             }
             // 8xyE - SHL Vx {, Vy}: Set Vx = Vx SHL 1
-            0xE => {
-                let vx = instr.x() as usize;
+            // without the shift quirk Vy is shifted into Vx instead
+            Op::Shl { x, y } => {
+                let val = self.shift_source(x, y);
 
-                self.reg[0xF] = (self.reg[vx] & 0x80) >> 7;
-                self.reg[vx] <<= 1;
-            }
-            _ => {
-                return Err(stringify!("Unrecognized 8 opcode {}", instr).to_string());
+                self.reg[x as usize] = val << 1;
+                self.reg[0xF] = (val & 0x80) >> 7;
             }
-        }
-
-        Ok(())
-    }
-
-    // 9xy0 - SNE Vx, Vy: Skip next instruction if Vx != Vy
-    fn opcode_9(&mut self, instr: Instruction) -> Result<(), String> {
-        match instr.n() {
-            0 => {
-                if self.reg[instr.x() as usize] != self.reg[instr.y() as usize] {
-                    self.pc += 2;
-                }
-                Ok(())
-            }
-            _ => Err(stringify!("Unrecognized 9 opcode {}", instr).to_string()),
-        }
-    }
-
-    // Annn - LD I, addr: Set I = nnn
-    fn opcode_a(&mut self, instr: Instruction) -> Result<(), String> {
-        self.i = instr.nnn();
-        Ok(())
-    }
-
-    // Bnnn - JP V0, addr: Jump to location nnn + V0
-    fn opcode_b(&mut self, instr: Instruction) -> Result<(), String> {
-        self.pc = instr.nnn() + (self.reg[0x0] as u16);
-        Ok(())
-    }
 
-    // Cxkk - RND Vx, byte: Set Vx = random byte AND kk.
-    fn opcode_c(&mut self, instr: Instruction) -> Result<(), String> {
-        let rnd_num = thread_rng().gen::<u8>();
-        self.reg[instr.x() as usize] = rnd_num & instr.kk();
+            // 9xy0 - SNE Vx, Vy: Skip next instruction if Vx != Vy
+            Op::Sne { x, y } => self.skip_if(self.reg[x as usize] != self.reg[y as usize], mem),
 
-        Ok(())
-    }
-
-    // Dxyn - DRW Vx, Vy, nibble
-    // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
-    fn opcode_d(
-        &mut self,
-        instr: Instruction,
-        mem: &mut Memory,
-        flags: &mut Flags,
-    ) -> Result<(), String> {
-        let x_cord = self.reg[instr.x() as usize] as usize;
-        let y_cord = self.reg[instr.y() as usize] as usize;
-        let mut collide = false;
-
-        let sprite_data = mem.read_n_bytes(instr.n() as usize, self.i as usize)?;
-        let pixels = mem.get_vram();
-
-        for i in 0..sprite_data.len() {
-            let byte = sprite_data[i];
-            let mut bitmask = 0x80;
-
-            for j in 0..8 {
-                let bit = (byte & bitmask) >> 7 - j;
-                bitmask >>= 1;
+            // Annn - LD I, addr: Set I = nnn
+            Op::LdI(addr) => self.i = addr,
 
-                if bit == 1 {
-                    let x = (x_cord + j) % 64;
-                    let y = (y_cord + i) % 32;
-                    let idx = y * 64 + x;
+            // Bnnn - JP V0, addr: Jump to location nnn + V0
+            // with the jump quirk this is Bxnn, jump to location xnn + Vx
+            Op::JpV0(addr) => {
+                let offset = match self.quirks.jump {
+                    true => self.reg[(addr >> 8) as usize],
+                    false => self.reg[0x0],
+                };
 
-                    collide = collide || pixels[idx as usize] == 1;
-                    pixels[idx as usize] ^= 1;
-                }
+                self.pc = addr + offset as u16;
             }
-        }
-
-        self.reg[0x0F] = match collide {
-            true => 1,
-            false => 0,
-        };
 
-        flags.draw = true;
-        Ok(())
-    }
+            // Cxkk - RND Vx, byte: Set Vx = random byte AND kk.
+            Op::Rnd { x, kk } => self.reg[x as usize] = flags.rng.byte() & kk,
 
-    // Ex9E, ExA1 opcodes
-    fn opcode_e(&mut self, instr: Instruction, flags: &mut Flags) -> Result<(), String> {
-        let key = flags.key.as_u8();
+            // Dxyn - DRW Vx, Vy, nibble
+            Op::Drw { x, y, n } => self.draw(x, y, n, mem, flags)?,
 
-        match instr.kk() {
             // Ex9E - SKP Vx
             // Skip next instruction if key with the value of Vx is pressed.
-            0x9E => {
-                if self.reg[instr.x() as usize] == key {
-                    self.pc += 2
-                }
-            }
+            Op::Skp(x) => self.skip_if(flags.keypad.is_pressed(self.reg[x as usize]), mem),
+
             // ExA1 - SKNP Vx
             // Skip next instruction if key with the value of Vx is not pressed.
-            0xA1 => {
-                if self.reg[instr.x() as usize] != key {
-                    self.pc += 2
-                }
-            }
-            _ => return Err(stringify!("Unrecognized E opcode {}", instr).to_string()),
-        };
+            Op::Sknp(x) => self.skip_if(!flags.keypad.is_pressed(self.reg[x as usize]), mem),
 
-        Ok(())
-    }
+            // F000 nnnn - LD I, long addr, Set I = nnnn (XO-CHIP)
+            Op::LdILong => self.i = instr.long(),
 
-    // Fx07 - Fx65 opcodes
-    fn opcode_f(
-        &mut self,
-        instr: Instruction,
-        mem: &mut Memory,
-        flags: &mut Flags,
-    ) -> Result<(), String> {
-        let key = flags.key.as_u8();
+            // Fn01 - PLANE n, Select the bitplanes n used for drawing (XO-CHIP)
+            Op::Plane(n) => mem.set_plane(n),
+
+            // F002 - AUDIO, Load the 16 byte audio pattern buffer from I (XO-CHIP)
+            Op::Audio => mem.load_audio_pattern(self.i)?,
 
-        match instr.kk() {
             // Fx07 - LD Vx, DT, Set Vx = delay timer value.
-            0x07 => self.reg[instr.x() as usize] = self.dt,
+            Op::LdVxDt(x) => self.reg[x as usize] = self.dt,
 
             // Fx0A - LD Vx, K, Wait for a key press, store the value of the key in Vx.
-            0x0A => {
-                // if the keypressed is one on the chip-8 keyboard 0-F store it.
-                // otherwise we will decrement the pc register and wait for a keypress
-                if key < 16 {
-                    self.reg[instr.x() as usize] = key;
-                } else {
-                    self.pc -= 2;
-                }
+            // the cpu stops running instructions until poll_key_wait sees a key
+            // pressed and released. The timers keep running in the meantime.
+            Op::LdVxK(x) => {
+                self.wait = Some(KeyWait {
+                    reg: x,
+                    held: flags.keypad,
+                    pressed: None,
+                });
             }
             // Fx15 - LD DT, Vx, Set delay timer = Vx.
-            0x15 => self.dt = self.reg[instr.x() as usize],
+            Op::LdDtVx(x) => self.dt = self.reg[x as usize],
 
             // Fx18 - LD ST, Vx, Set sound timer = Vx.
-            0x18 => self.st = self.reg[instr.x() as usize],
+            Op::LdStVx(x) => self.st = self.reg[x as usize],
 
             // Fx1E - ADD I, Vx, Set I = I + Vx.
-            0x1E => self.i = self.i + (self.reg[instr.x() as usize] as u16),
+            Op::AddI(x) => self.i = self.i.wrapping_add(self.reg[x as usize] as u16),
 
             // Fx29 - LD F, Vx, Set I = location of sprite for digit Vx.
             // fonts are stored at memory location 0x000 - 0x1FF. each font takes 5 bytes
-            0x29 => self.i = 0x000 + ((self.reg[instr.x() as usize] * 5) as u16),
+            Op::LdF(x) => self.i = FONT_LOCATION + (self.reg[x as usize] & 0x0F) as u16 * 5,
+
+            // Fx30 - LD HF, Vx, Set I = location of the large sprite for digit Vx.
+            // each large font takes 10 bytes
+            Op::LdHf(x) => self.i = BIG_FONT_LOCATION + (self.reg[x as usize] & 0x0F) as u16 * 10,
+
+            // Fx3A - PITCH Vx, Set the audio pitch register = Vx (XO-CHIP)
+            Op::LdPitch(x) => self.pitch = self.reg[x as usize],
 
             // Fx33 - LD B, Vx, Store BCD of Vx in memory locations I, I+1, and I+2.
             // places the hundreds digit in memory at location in I, the tens digit
             // at location I+1, and the ones digit at location I+2
-            0x33 => {
-                let mut x = self.reg[instr.x() as usize];
-                mem.write_byte(self.i + 2, x % 10)?;
-                x = x / 10;
-                mem.write_byte(self.i + 1, x % 10)?;
-                x = x / 10;
+            Op::LdB(x) => {
+                let mut x = self.reg[x as usize];
+                mem.write_byte(self.i.wrapping_add(2), x % 10)?;
+                x /= 10;
+                mem.write_byte(self.i.wrapping_add(1), x % 10)?;
+                x /= 10;
                 mem.write_byte(self.i, x % 10)?;
             }
 
             // Fx55 - LD [I], Vx, Store regs V0 through Vx in memory starting at location I.
             // The interpreter copies the values of registers V0 through Vx into memory,
             // starting at the address in I
-            0x55 => {
-                for loc in 0..=instr.x() {
-                    mem.write_byte(self.i + loc as u16, self.reg[loc as usize])?;
+            Op::LdMemVx(x) => {
+                for loc in 0..=x {
+                    mem.write_byte(self.i.wrapping_add(loc as u16), self.reg[loc as usize])?;
                 }
+                self.load_store_increment(x);
             }
 
             //Fx65 - LD Vx, [I], Read regs V0 through Vx from memory starting at location I.
-            0x65 => {
-                for loc in 0..=instr.x() {
-                    self.reg[loc as usize] = mem.read_byte(self.i + loc as u16)?;
+            Op::LdVxMem(x) => {
+                for loc in 0..=x {
+                    self.reg[loc as usize] = mem.read_byte(self.i.wrapping_add(loc as u16))?;
+                }
+                self.load_store_increment(x);
+            }
+
+            // Fx75 - LD R, Vx, Store V0 through Vx in the RPL user flags
+            Op::LdRVx(x) => {
+                let x = x as usize;
+                self.rpl[..=x].copy_from_slice(&self.reg[..=x]);
+            }
+
+            // Fx85 - LD Vx, R, Read V0 through Vx from the RPL user flags
+            Op::LdVxR(x) => {
+                let x = x as usize;
+                self.reg[..=x].copy_from_slice(&self.rpl[..=x]);
+            }
+        }
+
+        Ok(())
+    }
+
+    // whether the cpu's mode has the instruction. SUPER-CHIP only has 8 RPL
+    // user flags, so Fx75 and Fx85 need x <= 7.
+    fn supports(&self, op: Op) -> bool {
+        match op {
+            Op::LdRVx(x) | Op::LdVxR(x) if self.mode == Mode::SuperChip => x < 8,
+            _ => op.mode() <= self.mode,
+        }
+    }
+
+    // error for an instruction the cpu can't execute. The pc has already been
+    // moved past the instruction when it executes.
+    fn invalid(&self, instr: &Instruction) -> EmulatorError {
+        EmulatorError::InvalidOpcode {
+            pc: self.pc.wrapping_sub(instr.len()),
+            opcode: instr.opcode(),
+        }
+    }
+
+    // skip the next instruction if condition holds. On XO-CHIP the next
+    // instruction may be the 4 byte long F000 nnnn, which is skipped as a
+    // whole.
+    fn skip_if(&mut self, condition: bool, mem: &Memory) {
+        if !condition {
+            return;
+        }
+
+        let long = self.mode == Mode::XoChip && matches!(mem.read_word(self.pc), Ok(0xF000));
+
+        self.pc = self.pc.wrapping_add(match long {
+            true => 4,
+            false => 2,
+        });
+    }
+
+    // Dxyn - DRW Vx, Vy, nibble
+    // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
+    // SUPER-CHIP Dxy0 draws a 16x16 sprite made of 32 bytes, two bytes per row.
+    // The starting position always wraps around the screen, the rest of the sprite
+    // is either wrapped or clipped depending on the clipping quirk.
+    fn draw(
+        &mut self,
+        x: u8,
+        y: u8,
+        n: u8,
+        mem: &mut Memory,
+        flags: &mut Flags,
+    ) -> Result<(), EmulatorError> {
+        // with display wait only one sprite is drawn per frame, keep running this
+        // instruction until the start of the next frame
+        if self.quirks.display_wait {
+            if !flags.vblank {
+                self.pc = self.pc.wrapping_sub(2);
+                return Ok(());
+            }
+            flags.vblank = false;
+        }
+
+        let (width, height) = (mem.width(), mem.height());
+        let x_cord = self.reg[x as usize] as usize % width;
+        let y_cord = self.reg[y as usize] as usize % height;
+        let mut collide = false;
+
+        // sprite width in bytes and height in rows
+        let (cols, rows) = match n {
+            0 if self.mode != Mode::Chip8 => (2, 16),
+            n => (1, n as usize),
+        };
+
+        // XO-CHIP draws the sprite into each selected plane, plane 1 first. The
+        // sprite data for the next plane follows straight after the previous one.
+        let mut addr = self.i as usize;
+        for layer in [0x1, 0x2] {
+            if mem.plane() & layer == 0 {
+                continue;
+            }
+
+            let sprite_data = mem.read_n_bytes(cols * rows, addr)?;
+            let pixels = mem.get_vram();
+            addr += cols * rows;
+
+            for (i, row) in sprite_data.chunks(cols).enumerate() {
+                for (b, byte) in row.iter().enumerate() {
+                    let mut bitmask = 0x80;
+
+                    for j in 0..8 {
+                        let bit = (byte & bitmask) >> (7 - j);
+                        bitmask >>= 1;
+
+                        if bit == 1 {
+                            let x = x_cord + b * 8 + j;
+                            let y = y_cord + i;
+
+                            if self.quirks.clipping && (x >= width || y >= height) {
+                                continue;
+                            }
+
+                            let idx = (y % height) * width + x % width;
+
+                            collide = collide || pixels[idx] & layer != 0;
+                            pixels[idx] ^= layer;
+                        }
+                    }
                 }
             }
-            _ => return Err(stringify!("Unrecognized F opcode {}", instr).to_string()),
         }
 
+        self.reg[0x0F] = match collide {
+            true => 1,
+            false => 0,
+        };
+
+        flags.draw = true;
         Ok(())
     }
+
+    // the register 8xy6 and 8xyE shift, depending on the shift quirk
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        match self.quirks.shift {
+            true => self.reg[x as usize],
+            false => self.reg[y as usize],
+        }
+    }
+
+    // the COSMAC VIP left VF at 0 after 8xy1, 8xy2 and 8xy3
+    fn vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.reg[0xF] = 0;
+        }
+    }
+
+    // without the load/store quirk Fx55 and Fx65 leave I one past the last
+    // register read or written
+    fn load_store_increment(&mut self, x: u8) {
+        if !self.quirks.load_store {
+            self.i = self.i.wrapping_add(x as u16 + 1);
+        }
+    }
+}
+
+// the registers from Vx to Vy for 5xy2 and 5xy3, in that order. When x > y the
+// registers are walked backwards.
+fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+    let (x, y) = (x as usize, y as usize);
+
+    match x <= y {
+        true => Box::new(x..=y),
+        false => Box::new((y..=x).rev()),
+    }
 }
@@ -0,0 +1,285 @@
+use crate::video::Video;
+use crate::{Access, Condition, EmulatorError, Events, System};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+// The kind of access a watchpoint stops on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    // a read or a write
+    Access,
+}
+
+// Stop after an instruction reads or writes any of len bytes from addr.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    pub len: u16,
+    pub watch: Watch,
+}
+
+impl Watchpoint {
+    fn hit(&self, access: &Access) -> bool {
+        let kind = match self.watch {
+            Watch::Read => !access.write,
+            Watch::Write => access.write,
+            Watch::Access => true,
+        };
+
+        let (start, end) = (self.addr as u32, self.addr as u32 + self.len as u32);
+        let (from, to) = (access.addr as u32, access.addr as u32 + access.len as u32);
+        kind && from < end && start < to
+    }
+}
+
+// Why a running program stopped before the end of a frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    // the PC reached a breakpoint, the instruction there hasn't run yet
+    Breakpoint(u16),
+    // the instruction at pc made an access watchpoint id watches for. Running
+    // backwards the instruction is undone, the PC is back at it.
+    Watchpoint { id: u32, access: Access, pc: u16 },
+    // condition id is true after the instruction at pc ran
+    Condition { id: u32, pc: u16 },
+    // running backwards went back to the oldest instruction in the history
+    HistoryStart,
+    // the program ran the SUPER-CHIP exit instruction
+    Exited,
+    // the condition given to run_frame_until became true
+    Done,
+}
+
+// Runs a system one instruction at a time or until it reaches a breakpoint,
+// and with System::set_history backwards too. Frames are kept the same as
+// System::run_frame, the timers tick after the configured number of
+// instructions whether they were stepped through or run, so a program behaves
+// the same under the debugger as without it.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    // watchpoints and conditions by id, they share the numbers
+    watchpoints: BTreeMap<u32, Watchpoint>,
+    conditions: BTreeMap<u32, Condition>,
+    next_id: u32,
+    // where the program last stopped, at a breakpoint or after a step.
+    // Continuing from there runs the instruction instead of stopping at a
+    // breakpoint on it again.
+    resume: Option<u16>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            conditions: BTreeMap::new(),
+            next_id: 1,
+            resume: None,
+        }
+    }
+
+    // stop before the instruction at addr runs. Returns false if there
+    // already was a breakpoint there.
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    // returns false if there was no breakpoint at addr
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    // the addresses of the breakpoints, lowest first
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    // watch memory for accesses, returns the id of the watchpoint
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> u32 {
+        self.next_id += 1;
+        self.watchpoints.insert(self.next_id - 1, watchpoint);
+        self.next_id - 1
+    }
+
+    // returns false if there is no watchpoint with that id
+    pub fn remove_watchpoint(&mut self, id: u32) -> bool {
+        self.watchpoints.remove(&id).is_some()
+    }
+
+    pub fn watchpoint(&self, id: u32) -> Option<Watchpoint> {
+        self.watchpoints.get(&id).copied()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (u32, Watchpoint)> + '_ {
+        self.watchpoints.iter().map(|(id, w)| (*id, *w))
+    }
+
+    // stop after any instruction that leaves condition true, returns the id
+    // of the condition. A condition that stays true stops every instruction,
+    // so they usually check the PC too.
+    pub fn add_condition(&mut self, condition: Condition) -> u32 {
+        self.next_id += 1;
+        self.conditions.insert(self.next_id - 1, condition);
+        self.next_id - 1
+    }
+
+    // returns false if there is no condition with that id
+    pub fn remove_condition(&mut self, id: u32) -> bool {
+        self.conditions.remove(&id).is_some()
+    }
+
+    pub fn condition(&self, id: u32) -> Option<&Condition> {
+        self.conditions.get(&id)
+    }
+
+    pub fn conditions(&self) -> impl Iterator<Item = (u32, &Condition)> + '_ {
+        self.conditions.iter().map(|(id, c)| (*id, c))
+    }
+
+    // run one instruction, ignoring breakpoints. When it is the last one of a
+    // frame the timers tick after it.
+    pub fn step<V: Video>(&mut self, system: &mut System<V>) -> Result<Events, EmulatorError> {
+        let events = self.advance(system);
+        self.resume = Some(system.registers().pc);
+        events
+    }
+
+    fn advance<V: Video>(&mut self, system: &mut System<V>) -> Result<Events, EmulatorError> {
+        self.resume = None;
+        system.step_in_frame()
+    }
+
+    // undo the last instruction run. Returns false when there is no history
+    // left to go back through.
+    pub fn step_back<V: Video>(&mut self, system: &mut System<V>) -> bool {
+        let undone = system.step_back();
+        self.resume = Some(system.registers().pc);
+        undone
+    }
+
+    // step back until the PC reaches a breakpoint, an instruction that made
+    // an access a watchpoint watches for is undone, a condition is true or
+    // the history runs out.
+    pub fn reverse_continue<V: Video>(&mut self, system: &mut System<V>) -> Stop {
+        let stop = loop {
+            let entry = match system.undo() {
+                Some(entry) => entry,
+                None => break Stop::HistoryStart,
+            };
+
+            if let Some(stop) = self.watchpoint_hit(&entry.accesses, entry.pc) {
+                break stop;
+            }
+
+            if self.breakpoints.contains(&entry.pc) {
+                break Stop::Breakpoint(entry.pc);
+            }
+
+            // the state now is the one after the instruction before ran
+            let pc = system.last_pc().unwrap_or(entry.pc);
+            if let Some(stop) = self.condition_true(system, pc) {
+                break stop;
+            }
+        };
+
+        self.resume = Some(system.registers().pc);
+        stop
+    }
+
+    // run until the end of the current frame, or from the start of the next
+    // one to its end. Returns early if a breakpoint is reached or the program
+    // exits.
+    pub fn run_frame<V: Video>(
+        &mut self,
+        system: &mut System<V>,
+    ) -> Result<Option<Stop>, EmulatorError> {
+        self.run_frame_until(system, |_| false)
+    }
+
+    // run_frame, also stopping with Stop::Done after an instruction that
+    // leaves the system in a state done returns true for. Frontends use it to
+    // step over calls and through source lines.
+    pub fn run_frame_until<V: Video>(
+        &mut self,
+        system: &mut System<V>,
+        mut done: impl FnMut(&System<V>) -> bool,
+    ) -> Result<Option<Stop>, EmulatorError> {
+        let watching = !self.watchpoints.is_empty();
+
+        loop {
+            let pc = system.registers().pc;
+            if self.breakpoints.contains(&pc)
+                && self.resume != Some(pc)
+                && system.waiting_for_key().is_none()
+            {
+                self.resume = Some(pc);
+                return Ok(Some(Stop::Breakpoint(pc)));
+            }
+
+            system.watch_accesses(watching);
+            let events = self.advance(system);
+            system.watch_accesses(false);
+
+            let events = match events {
+                Ok(events) => events,
+                Err(e) => {
+                    self.resume = Some(system.registers().pc);
+                    return Err(e);
+                }
+            };
+
+            if events.exited {
+                return Ok(Some(Stop::Exited));
+            }
+
+            // conditions aren't checked while the cpu waits for a key,
+            // nothing changed
+            let waited = system.waiting_for_key().is_some() && system.registers().pc == pc;
+            let stop = match self.watchpoint_hit(system.accesses(), pc) {
+                None if !waited => self.condition_true(system, pc),
+                stop => stop,
+            };
+            if let Some(stop) = stop {
+                self.resume = Some(system.registers().pc);
+                return Ok(Some(stop));
+            }
+
+            if done(system) {
+                self.resume = Some(system.registers().pc);
+                return Ok(Some(Stop::Done));
+            }
+
+            if !system.mid_frame() {
+                return Ok(None);
+            }
+        }
+    }
+
+    // the first watchpoint hit by the accesses of the instruction at pc
+    fn watchpoint_hit(&self, accesses: &[Access], pc: u16) -> Option<Stop> {
+        accesses.iter().find_map(|access| {
+            let (id, _) = self.watchpoints.iter().find(|(_, w)| w.hit(access))?;
+            Some(Stop::Watchpoint {
+                id: *id,
+                access: *access,
+                pc,
+            })
+        })
+    }
+
+    // the first condition true after the instruction at pc ran
+    fn condition_true<V: Video>(&self, system: &System<V>, pc: u16) -> Option<Stop> {
+        self.conditions
+            .iter()
+            .find(|(_, condition)| condition.is_true(system))
+            .map(|(id, _)| Stop::Condition { id: *id, pc })
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
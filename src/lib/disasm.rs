@@ -0,0 +1,253 @@
+use crate::opcode::{self, Opcode, Operand};
+use crate::Op;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// where roms are loaded in memory, and where execution starts
+const START: usize = 0x200;
+
+// The two ways Chip-8 programs are usually written down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    // the mnemonics from Cowgod's Chip-8 technical reference, e.g.
+    // `LD V3, 0x1F` and `DRW V0, V1, 5`
+    Cowgod,
+    // the statements of the Octo language, e.g. `v3 := 0x1F` and
+    // `sprite v0 v1 5`
+    Octo,
+}
+
+// One instruction as a line of text, None if word isn't an instruction. next
+// is the word after it, which only F000 nnnn uses.
+pub fn disassemble_instruction(word: u16, next: u16, syntax: Syntax) -> Option<String> {
+    let op = opcode::lookup(word)?;
+    Some(format_instruction(op, word, next, syntax, &BTreeMap::new()))
+}
+
+// Disassemble a whole rom loaded at 0x200. The flow of control is followed
+// from 0x200 through jumps, calls and skips to find out which bytes are code,
+// everything that can't be reached is listed as data. Jump and call targets
+// and the addresses I is pointed at get labels.
+pub fn disassemble(rom: &[u8], syntax: Syntax) -> String {
+    let (code, covered) = trace(rom);
+    let labels = labels(rom, &code, &covered);
+
+    let comment = match syntax {
+        Syntax::Cowgod => ";",
+        Syntax::Octo => "#",
+    };
+
+    let mut out = String::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let addr = START + offset;
+
+        match (labels.get(&addr), syntax) {
+            (Some(name), Syntax::Cowgod) => writeln!(out, "{}:", name).unwrap(),
+            (Some(name), Syntax::Octo) => writeln!(out, ": {}", name).unwrap(),
+            (None, _) => {}
+        }
+
+        // traced code is always an instruction, a word that isn't is listed as
+        // data rather than trusted
+        let word = read_word(rom, offset);
+        if let (true, Some(op)) = (code[offset], opcode::lookup(word)) {
+            let next = read_word(rom, offset + 2);
+            let len = instruction_len(word);
+            let text = format_instruction(op, word, next, syntax, &labels);
+
+            let hex = match len {
+                4 => format!("{:04X} {:04X}", word, next),
+                _ => format!("{:04X}", word),
+            };
+            writeln!(out, "    {:<27} {} {:03X}  {}", text, comment, addr, hex).unwrap();
+            offset += len;
+            continue;
+        }
+
+        // a line of up to 8 bytes of data, stopping at the next label or code
+        let mut end = offset + 1;
+        while end < rom.len()
+            && end - offset < 8
+            && !code[end]
+            && !labels.contains_key(&(START + end))
+        {
+            end += 1;
+        }
+
+        let bytes: Vec<String> = rom[offset..end]
+            .iter()
+            .map(|byte| format!("0x{:02X}", byte))
+            .collect();
+        let line = match syntax {
+            Syntax::Cowgod => format!("db {}", bytes.join(", ")),
+            Syntax::Octo => bytes.join(" "),
+        };
+        writeln!(out, "    {:<27} {} {:03X}", line, comment, addr).unwrap();
+        offset = end;
+    }
+
+    out
+}
+
+// Mark the offset of every instruction reachable from the start of the rom,
+// and every byte those instructions cover. Flow stops at returns, exits and
+// anything that isn't an instruction. Bnnn jumps somewhere past nnn depending
+// on V0, nnn itself is taken as the most likely target.
+fn trace(rom: &[u8]) -> (Vec<bool>, Vec<bool>) {
+    let mut code = vec![false; rom.len()];
+    let mut covered = vec![false; rom.len()];
+    let mut pending = vec![START];
+
+    while let Some(addr) = pending.pop() {
+        let offset = match addr.checked_sub(START) {
+            Some(offset) if offset + 1 < rom.len() => offset,
+            _ => continue,
+        };
+
+        let op = match Op::decode(read_word(rom, offset)) {
+            Ok(op) => op,
+            Err(_) => continue,
+        };
+        let len = op.size() as usize;
+        let bytes = offset..(offset + len).min(rom.len());
+        if covered[bytes.clone()].contains(&true) {
+            continue;
+        }
+
+        code[offset] = true;
+        covered[bytes].fill(true);
+
+        let next = addr + len;
+
+        match op {
+            Op::Ret | Op::Exit => {}
+            Op::Jp(target) | Op::JpV0(target) => pending.push(target as usize),
+            // the subroutine returns to the next instruction
+            Op::Call(target) => pending.extend([target as usize, next]),
+            // skips, the next instruction may or may not run
+            Op::SeByte { .. }
+            | Op::SneByte { .. }
+            | Op::Se { .. }
+            | Op::Sne { .. }
+            | Op::Skp(_)
+            | Op::Sknp(_) => {
+                let skipped = match rom.get(next - START..next - START + 2) {
+                    Some(w) => instruction_len(u16::from_be_bytes([w[0], w[1]])),
+                    None => 2,
+                };
+                pending.extend([next, next + skipped]);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    (code, covered)
+}
+
+// Name the targets of calls, jumps and I, in that order of preference when an
+// address is more than one. Only addresses in the rom that are the start of an
+// instruction or data get a label.
+fn labels(rom: &[u8], code: &[bool], covered: &[bool]) -> BTreeMap<usize, String> {
+    let mut targets = BTreeMap::new();
+    targets.insert(START, "main");
+
+    for offset in (0..rom.len()).filter(|offset| code[*offset]) {
+        let (addr, kind) = match Op::decode(read_word(rom, offset)) {
+            Ok(Op::Call(addr)) => (addr as usize, "sub"),
+            Ok(Op::Jp(addr) | Op::JpV0(addr)) => (addr as usize, "label"),
+            Ok(Op::LdI(addr)) => (addr as usize, "data"),
+            Ok(Op::LdILong) => (read_word(rom, offset + 2) as usize, "data"),
+            _ => continue,
+        };
+
+        let labelled = match addr.checked_sub(START) {
+            Some(offset) if offset < rom.len() => code[offset] || !covered[offset],
+            _ => false,
+        };
+
+        let rank = |kind: &str| {
+            ["data", "label", "sub", "main"]
+                .iter()
+                .position(|k| *k == kind)
+        };
+        if labelled && targets.get(&addr).is_none_or(|old| rank(old) < rank(kind)) {
+            targets.insert(addr, kind);
+        }
+    }
+
+    targets
+        .into_iter()
+        .map(|(addr, kind)| match kind {
+            "main" => (addr, kind.to_string()),
+            _ => (addr, format!("{}_{:03X}", kind, addr)),
+        })
+        .collect()
+}
+
+// one instruction in the given syntax, naming addresses that have a label
+fn format_instruction(
+    op: &Opcode,
+    word: u16,
+    next: u16,
+    syntax: Syntax,
+    labels: &BTreeMap<usize, String>,
+) -> String {
+    let addr = |addr: u16, width: usize| match labels.get(&(addr as usize)) {
+        Some(name) => name.clone(),
+        None => format!("0x{:0width$X}", addr, width = width),
+    };
+
+    match syntax {
+        Syntax::Cowgod => {
+            let operands: Vec<String> = op
+                .operands
+                .iter()
+                .map(|operand| {
+                    let value = operand.extract(word);
+                    match operand {
+                        Operand::Vx | Operand::Vy => format!("V{:X}", value),
+                        Operand::Nibble | Operand::Plane => format!("{}", value),
+                        Operand::Byte => format!("0x{:02X}", value),
+                        Operand::Addr => addr(value, 3),
+                        Operand::Long => format!("LONG {}", addr(next, 4)),
+                        Operand::Fixed(keyword) => keyword.to_string(),
+                    }
+                })
+                .collect();
+
+            match operands.is_empty() {
+                true => op.mnemonic.to_string(),
+                false => format!("{} {}", op.mnemonic, operands.join(", ")),
+            }
+        }
+        Syntax::Octo => {
+            // a call to a label is just the name of the label
+            if word & 0xF000 == 0x2000 && labels.contains_key(&((word & 0xFFF) as usize)) {
+                return addr(word & 0xFFF, 3);
+            }
+
+            op.octo
+                .replace("{x}", &format!("v{:x}", Operand::Vx.extract(word)))
+                .replace("{y}", &format!("v{:x}", Operand::Vy.extract(word)))
+                .replace("{n}", &format!("{}", Operand::Nibble.extract(word)))
+                .replace("{kk}", &format!("0x{:02X}", Operand::Byte.extract(word)))
+                .replace("{nnn}", &addr(Operand::Addr.extract(word), 3))
+                .replace("{long}", &addr(next, 4))
+                .replace("{plane}", &format!("{}", Operand::Plane.extract(word)))
+        }
+    }
+}
+
+// the word at offset in the rom, reading past the end as 0
+fn read_word(rom: &[u8], offset: usize) -> u16 {
+    let byte = |offset: usize| rom.get(offset).copied().unwrap_or(0);
+    u16::from_be_bytes([byte(offset), byte(offset + 1)])
+}
+
+// length in bytes of the instruction word starts, 2 for anything that isn't one
+fn instruction_len(word: u16) -> usize {
+    Op::decode(word).map_or(2, |op| op.size() as usize)
+}
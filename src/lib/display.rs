@@ -1,62 +1,34 @@
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 
-// Chip-8 language had a 16-key hexadecimal keypad.
-pub(crate) enum Key {
-    ZERO,
-    ONE,
-    TWO,
-    THREE,
-    FOUR,
-    FIVE,
-    SIX,
-    SEVEN,
-    EIGHT,
-    NINE,
-    A,
-    B,
-    C,
-    D,
-    E,
-    F,
-    NONE,
-    QUIT,
-}
+use crate::video::{Command, Video};
+use crate::{EmulatorError, KeyMap, Keypad};
 
-impl Key {
-    pub(crate) fn as_u8(&self) -> u8 {
-        match self {
-            Key::ZERO => 0,
-            Key::ONE => 1,
-            Key::TWO => 2,
-            Key::THREE => 3,
-            Key::FOUR => 4,
-            Key::FIVE => 5,
-            Key::SIX => 6,
-            Key::SEVEN => 7,
-            Key::EIGHT => 8,
-            Key::NINE => 9,
-            Key::A => 10,
-            Key::B => 11,
-            Key::C => 12,
-            Key::D => 13,
-            Key::E => 14,
-            Key::F => 15,
-            Key::NONE => 16,
-            Key::QUIT => 17,
-        }
-    }
-}
+// colour of a pixel indexed by which bitplanes are set, background, plane 1
+// only, plane 2 only and both planes
+const PALETTE: [Color; 4] = [
+    Color::BLACK,
+    Color::GREEN,
+    Color::RGB(0xFF, 0x66, 0x00),
+    Color::WHITE,
+];
+
+// held down to play time backwards
+const REWIND_KEY: Keycode = Keycode::Backspace;
 
 // display
 pub struct Display {
     width: i32,
     height: i32,
     scale: i32,
+    palette: [Color; 4],
+    keymap: KeyMap,
+    // the rewind key is held down
+    rewinding: bool,
     sdl_ctx: sdl2::Sdl,
     sdl_canvas: Canvas<Window>,
 }
@@ -64,85 +36,140 @@ pub struct Display {
 impl Display {
     // scale
     pub fn create(title: String, scale: i32) -> Display {
+        let (width, height) = (64, 32);
         let sdl_ctx = sdl2::init().unwrap();
         let sdl_win = sdl_ctx
             .video()
             .unwrap()
-            .window(title.as_str(), 64 * scale as u32, 32 * scale as u32)
+            .window(
+                title.as_str(),
+                (width * scale) as u32,
+                (height * scale) as u32,
+            )
             .position_centered()
             .build()
             .unwrap();
         let sdl_canvas = sdl_win.into_canvas().build().unwrap();
 
         Display {
-            width: 64,
-            height: 32,
+            width,
+            height,
             scale,
+            palette: PALETTE,
+            keymap: KeyMap::default(),
+            rewinding: false,
             sdl_ctx,
             sdl_canvas,
         }
     }
+}
 
-    pub(crate) fn clear(&mut self) {
-        self.sdl_canvas.set_draw_color(Color::BLACK);
+impl Display {
+    // set which keyboard keys stand for the keys of the Chip-8 keypad
+    pub fn set_keymap(&mut self, keymap: KeyMap) {
+        self.keymap = keymap;
+    }
+
+    // set the colours used for the background, plane 1, plane 2 and both planes
+    // as (r, g, b)
+    pub fn set_palette(&mut self, palette: [(u8, u8, u8); 4]) {
+        for (colour, (r, g, b)) in self.palette.iter_mut().zip(palette) {
+            *colour = Color::RGB(r, g, b);
+        }
+    }
+}
+
+impl Video for Display {
+    fn clear(&mut self) {
+        self.sdl_canvas.set_draw_color(self.palette[0]);
         self.sdl_canvas.clear();
     }
 
-    pub(crate) fn draw(&mut self, pixels: &[u8; 32 * 64]) {
+    // the window is sized for the 64x32 screen, hi-res pixels are drawn at half
+    // the size to fit the same window. The colour of each pixel is picked from
+    // the palette by which XO-CHIP bitplanes are set.
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize) {
         self.clear();
-        self.sdl_canvas.set_draw_color(Color::GREEN);
 
-        for i in 0..pixels.len() {
-            if pixels[i] == 1 {
-                let x = i as i32 % self.width;
-                let y = i as i32 / self.width;
+        let size =
+            (self.width * self.scale / width as i32).min(self.height * self.scale / height as i32);
+        for (i, pixel) in pixels.iter().take(width * height).enumerate() {
+            if *pixel != 0 {
+                let x = (i % width) as i32;
+                let y = (i / width) as i32;
 
                 self.sdl_canvas
-                    .fill_rect(Rect::new(
-                        x * self.scale,
-                        y * self.scale,
-                        self.scale as u32,
-                        self.scale as u32,
-                    ))
+                    .set_draw_color(self.palette[(*pixel & 0x03) as usize]);
+                self.sdl_canvas
+                    .fill_rect(Rect::new(x * size, y * size, size as u32, size as u32))
                     .unwrap();
             }
         }
+    }
 
+    fn present(&mut self) {
         self.sdl_canvas.present();
     }
 
-    pub(crate) fn user_event(&mut self) -> Result<Key, String> {
-        let mut event_pump = self.sdl_ctx.event_pump()?;
-        let mut key = Key::NONE;
+    fn user_event(&mut self, keypad: &mut Keypad) -> Result<Option<Command>, EmulatorError> {
+        let mut event_pump = self.sdl_ctx.event_pump().map_err(EmulatorError::Video)?;
+        let mut command = None;
 
         for event in event_pump.poll_iter() {
-            key = match event {
+            match event {
                 Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    // backspace rewinds, F1-F9 load a save state slot and
+                    // shift+F1-F9 save one
+                    if key == REWIND_KEY {
+                        self.rewinding = true;
+                    } else if let Some(slot) = slot(key) {
+                        command = match keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            true => Some(Command::SaveState(slot)),
+                            false => Some(Command::LoadState(slot)),
+                        };
+                    } else if let Some(k) = self.keymap.keypad_key(&key.name()) {
+                        keypad.press(k);
+                    }
+                }
+                Event::KeyUp {
                     keycode: Some(key), ..
-                } => match key {
-                    Keycode::Num0 => Key::ZERO,
-                    Keycode::Num1 => Key::ONE,
-                    Keycode::Num2 => Key::TWO,
-                    Keycode::Num3 => Key::THREE,
-                    Keycode::Num4 => Key::FOUR,
-                    Keycode::Num5 => Key::FIVE,
-                    Keycode::Num6 => Key::SIX,
-                    Keycode::Num7 => Key::SEVEN,
-                    Keycode::Num8 => Key::EIGHT,
-                    Keycode::Num9 => Key::NINE,
-                    Keycode::A => Key::A,
-                    Keycode::B => Key::B,
-                    Keycode::C => Key::C,
-                    Keycode::D => Key::D,
-                    Keycode::E => Key::E,
-                    Keycode::F => Key::F,
-                    _ => Key::NONE,
-                },
-                Event::Quit { .. } => Key::QUIT,
-                _ => Key::NONE,
+                } => {
+                    if key == REWIND_KEY {
+                        self.rewinding = false;
+                    } else if let Some(k) = self.keymap.keypad_key(&key.name()) {
+                        keypad.release(k);
+                    }
+                }
+                Event::Quit { .. } => command = Some(Command::Quit),
+                _ => {}
             }
         }
 
-        Ok(key)
+        if command.is_none() && self.rewinding {
+            command = Some(Command::Rewind);
+        }
+
+        Ok(command)
     }
 }
+
+// the save state slot of a function key, F1-F9 are slots 1-9
+fn slot(key: Keycode) -> Option<u8> {
+    let slots = [
+        Keycode::F1,
+        Keycode::F2,
+        Keycode::F3,
+        Keycode::F4,
+        Keycode::F5,
+        Keycode::F6,
+        Keycode::F7,
+        Keycode::F8,
+        Keycode::F9,
+    ];
+    slots.iter().position(|k| *k == key).map(|n| n as u8 + 1)
+}
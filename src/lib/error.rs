@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// Everything that can go wrong while loading or running a Chip-8 program.
+#[derive(Debug)]
+pub enum EmulatorError {
+    // the word at pc isn't an instruction the interpreter knows how to run
+    InvalidOpcode {
+        pc: u16,
+        opcode: u16,
+    },
+    // an access of len bytes starting at addr runs past the end of memory
+    MemoryOutOfBounds {
+        addr: usize,
+        len: usize,
+    },
+    // a subroutine call was made with every stack slot already in use
+    StackOverflow,
+    // a return was made with nothing on the stack
+    StackUnderflow,
+    // the rom is bigger than the memory available from 0x200 onwards
+    RomTooLarge {
+        size: usize,
+        max: usize,
+    },
+    // a line of a key map file isn't `<keyboard key> = <keypad key>`
+    InvalidKeyMap {
+        line: usize,
+        text: String,
+    },
+    // a save state is corrupt or can't be loaded into this system
+    InvalidState(String),
+    // a movie is corrupt or was recorded on a different rom or config
+    InvalidMovie(String),
+    // a trace file is in neither trace format
+    InvalidTrace(String),
+    // a debugger condition isn't a valid expression
+    InvalidCondition(String),
+    // assembly or Octo source has a mistake at line and column of file
+    Assemble {
+        file: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // reading or writing a file failed
+    Io(io::Error),
+    // the video backend failed
+    Video(String),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::InvalidOpcode { pc, opcode } => {
+                write!(f, "invalid opcode {:#06x} at address {:#05x}", opcode, pc)
+            }
+            EmulatorError::MemoryOutOfBounds { addr, len } => write!(
+                f,
+                "memory access out of bounds: {} byte(s) at address {:#05x}",
+                len, addr
+            ),
+            EmulatorError::StackOverflow => write!(f, "stack overflow"),
+            EmulatorError::StackUnderflow => write!(f, "stack underflow"),
+            EmulatorError::RomTooLarge { size, max } => write!(
+                f,
+                "rom is {} bytes, the most that fits in memory is {} bytes",
+                size, max
+            ),
+            EmulatorError::InvalidKeyMap { line, text } => {
+                write!(f, "invalid key map entry on line {}: {}", line, text)
+            }
+            EmulatorError::InvalidState(reason) => write!(f, "invalid save state: {}", reason),
+            EmulatorError::InvalidMovie(reason) => write!(f, "invalid movie: {}", reason),
+            EmulatorError::InvalidTrace(reason) => write!(f, "invalid trace: {}", reason),
+            EmulatorError::InvalidCondition(reason) => write!(f, "invalid condition: {}", reason),
+            EmulatorError::Assemble {
+                file,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", file, line, column, message),
+            EmulatorError::Io(e) => write!(f, "i/o error: {}", e),
+            EmulatorError::Video(e) => write!(f, "video error: {}", e),
+        }
+    }
+}
+
+impl Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EmulatorError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EmulatorError {
+    fn from(e: io::Error) -> Self {
+        EmulatorError::Io(e)
+    }
+}
@@ -0,0 +1,112 @@
+use crate::video::{Command, Video};
+use crate::{EmulatorError, Keypad};
+
+use std::collections::VecDeque;
+
+// Headless video backend. Pixels are kept in memory instead of being shown in
+// a window, and input is whatever has been queued with press_key, release_key
+// and quit.
+pub struct Framebuffer {
+    // pixels and resolution drawn since the last present
+    back: Vec<u8>,
+    back_size: (usize, usize),
+    // pixels and resolution as of the last present
+    front: Vec<u8>,
+    front_size: (usize, usize),
+    // number of times present has been called
+    frames: u64,
+    input: VecDeque<Input>,
+}
+
+// input queued up for the next user_event call
+enum Input {
+    Press(u8),
+    Release(u8),
+    Command(Command),
+}
+
+impl Framebuffer {
+    pub fn create() -> Framebuffer {
+        Framebuffer {
+            back: vec![0; 64 * 32],
+            back_size: (64, 32),
+            front: vec![0; 64 * 32],
+            front_size: (64, 32),
+            frames: 0,
+            input: VecDeque::new(),
+        }
+    }
+
+    // the pixels as of the last present, in rows of width pixels
+    pub fn pixels(&self) -> &[u8] {
+        &self.front
+    }
+
+    // the (width, height) of the screen as of the last present
+    pub fn size(&self) -> (usize, usize) {
+        self.front_size
+    }
+
+    // number of frames presented so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // queue a press of key (0-F) for the next user_event call
+    pub fn press_key(&mut self, key: u8) {
+        self.input.push_back(Input::Press(key));
+    }
+
+    // queue a release of key (0-F) for the next user_event call
+    pub fn release_key(&mut self, key: u8) {
+        self.input.push_back(Input::Release(key));
+    }
+
+    // queue a request to quit for the next user_event call
+    pub fn quit(&mut self) {
+        self.command(Command::Quit);
+    }
+
+    // queue any command for the next user_event call
+    pub fn command(&mut self, command: Command) {
+        self.input.push_back(Input::Command(command));
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer::create()
+    }
+}
+
+impl Video for Framebuffer {
+    fn clear(&mut self) {
+        self.back.fill(0);
+    }
+
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize) {
+        self.back.clear();
+        self.back.extend_from_slice(&pixels[..width * height]);
+        self.back_size = (width, height);
+    }
+
+    fn present(&mut self) {
+        self.front.clone_from(&self.back);
+        self.front_size = self.back_size;
+        self.frames += 1;
+    }
+
+    fn user_event(&mut self, keypad: &mut Keypad) -> Result<Option<Command>, EmulatorError> {
+        let mut command = None;
+
+        while let Some(input) = self.input.pop_front() {
+            match input {
+                Input::Press(key) => keypad.press(key),
+                Input::Release(key) => keypad.release(key),
+                Input::Command(c) => command = Some(c),
+            }
+        }
+
+        Ok(command)
+    }
+}
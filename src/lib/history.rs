@@ -0,0 +1,90 @@
+use crate::memory::Undo;
+use crate::{Access, CPU};
+
+use std::collections::VecDeque;
+
+// Undo log for running a program backwards. Every instruction the system runs
+// adds an entry holding the machine as it was before and the old values of
+// the memory it changed, and stepping back undoes the newest entry. Only the
+// last depth instructions are kept.
+pub(crate) struct History {
+    depth: usize,
+    // oldest first
+    entries: VecDeque<Entry>,
+}
+
+// The last instruction in the history to write a byte of memory, from
+// System::last_write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryWrite {
+    // the instruction's number, counting from 0 when the rom was loaded
+    pub cycle: u64,
+    // where the instruction is
+    pub pc: u16,
+    // the byte before and after it was written
+    pub old: u8,
+    pub new: u8,
+}
+
+// how to undo one instruction
+pub(crate) struct Entry {
+    // the instruction's number, counting from 0 when the rom was loaded
+    pub(crate) cycle: u64,
+    // where the instruction is
+    pub(crate) pc: u16,
+    // the reads and writes it made
+    pub(crate) accesses: Vec<Access>,
+    pub(crate) cpu: CPU,
+    pub(crate) mem: Undo,
+    // the rest of the system: frame timing, the rng and the exit flag
+    pub(crate) vblank: bool,
+    pub(crate) exit: bool,
+    pub(crate) rng: u64,
+    pub(crate) remainder: u32,
+    pub(crate) frame: u64,
+    pub(crate) left: u32,
+}
+
+impl History {
+    pub(crate) fn new(depth: usize) -> History {
+        History {
+            depth,
+            entries: VecDeque::new(),
+        }
+    }
+
+    // the history is kept at all
+    pub(crate) fn on(&self) -> bool {
+        self.depth > 0
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn push(&mut self, entry: Entry) {
+        if self.entries.len() == self.depth {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    // take the newest entry to undo it
+    pub(crate) fn pop(&mut self) -> Option<Entry> {
+        self.entries.pop_back()
+    }
+
+    // the newest entry
+    pub(crate) fn last(&self) -> Option<&Entry> {
+        self.entries.back()
+    }
+
+    // newest first
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().rev()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
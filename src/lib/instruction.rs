@@ -1,3 +1,5 @@
+use crate::{disassemble_instruction, EmulatorError, Memory, Op, Syntax};
+
 use std::fmt;
 
 // Chip-8 instructions are all 16 bits long and stored in big-endian
@@ -7,104 +9,65 @@ use std::fmt;
 // x - A 4-bit value, the lower 4 bits of the high byte of the instruction
 // y - A 4-bit value, the upper 4 bits of the low byte of the instruction
 // kk or byte - An 8-bit value, the lowest 8 bits of the instruction
+//
+// XO-CHIP adds a single 32 bit instruction, F000 nnnn, the 16-bit address
+// nnnn is stored in the word following the opcode
 pub(crate) struct Instruction {
-    itype: u8,
-    n: u8,
-    x: u8,
-    y: u8,
-    kk: u8,
-    nnn: u16,
+    opcode: u16,
+    op: Op,
+    long: Option<u16>,
 }
 
+// the instruction in Cowgod's syntax, e.g. `LD V3, 0x1F`
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.itype {
-            0 => {
-                if self.n == 0 {
-                    write!(f, "0: Clear display\n\tkk: {:#03x}", self.kk)
-                } else {
-                    write!(f, "0: Return subroutine\n\tkk: {:#03x}", self.kk)
-                }
-            }
-            1 => write!(f, "1: Jump to nnn\n\tnnn: {:#04x}", self.nnn),
-            2 => write!(f, "2: Call\n\taddr: {:#04x}", self.nnn),
-            3..=5 => write!(
-                f,
-                "{}: Skip Instruction\n\tx: {:#03x}\n\ty:{:#03x}\n\tkk:{:#04x}",
-                self.itype, self.x, self.y, self.kk
-            ),
-            6..=8 => write!(
-                f,
-                "{}: Set Vx\n\tx: {:#03x}\n\ty: {:#03x}\n\tkk: {:#04x}",
-                self.itype, self.x, self.y, self.kk
-            ),
-            9 => write!(
-                f,
-                "9: Skip if x != y\n\tx: {:#03x}\n\ty: {:#03x}",
-                self.x, self.y
-            ),
-            10 => write!(f, "A: Set I to nnn\n\tnnn: {:#04x}", self.nnn),
-            11 => write!(f, "B: Jump nnn + V0\n\tnnn: {:#04x}", self.nnn),
-            12 => write!(
-                f,
-                "C: Set random + kk\n\tx: {:#03x}\n\tkk: {:#04x}",
-                self.x, self.kk
-            ),
-            13 => write!(
-                f,
-                "D: Draw\n\tx: {:#03x}\n\ty:{:#03x}\n\tn: {:#02x}",
-                self.x, self.y, self.n
-            ),
-            14 => write!(f, "E: Skip\n\tx: {:#03x}\n\tkk: {:#04x}", self.x, self.kk),
-            15 => write!(
-                f,
-                "F: Set Store Read\n\tx: {:#03x}\n\tkk: {:#04x}",
-                self.x, self.kk
-            ),
-            _ => write!(f, "unrecognized type: {:#03x}", self.itype),
+        match disassemble_instruction(self.opcode, self.long(), Syntax::Cowgod) {
+            Some(text) => write!(f, "{}", text),
+            None => write!(f, "invalid opcode {:#06x}", self.opcode),
         }
     }
 }
 
 impl Instruction {
-    pub(crate) fn decode(data: u16) -> Instruction {
-        Instruction {
-            itype: ((data & 0xF000) >> 12) as u8,
-            n: (data & 0x000F) as u8,
-            x: ((data & 0x0F00) >> 8) as u8,
-            y: ((data & 0x00F0) >> 4) as u8,
-            kk: (data & 0x00FF) as u8,
-            nnn: (data & 0x0FFF),
-        }
-    }
+    // fetch and decode the instruction stored at memory location addr, reading
+    // the second word of F000 nnnn as well. Words that aren't an instruction
+    // are an InvalidOpcode error.
+    pub(crate) fn fetch(mem: &Memory, addr: u16) -> Result<Instruction, EmulatorError> {
+        let data = mem.read_word(addr)?;
+        let op = Op::decode(data).map_err(|_| EmulatorError::InvalidOpcode {
+            pc: addr,
+            opcode: data,
+        })?;
 
-    // return the instruction type
-    pub(crate) fn itype(&self) -> u8 {
-        self.itype
-    }
+        let long = match op.size() {
+            4 => Some(mem.read_word(addr.wrapping_add(2))?),
+            _ => None,
+        };
 
-    // return the instruction n (lowest 4 bits)
-    pub(crate) fn n(&self) -> u8 {
-        self.n
+        Ok(Instruction {
+            opcode: data,
+            op,
+            long,
+        })
     }
 
-    // return the instruction x (lower 4 bits of the high byte)
-    pub(crate) fn x(&self) -> u8 {
-        self.x
+    // return the length of the instruction in bytes
+    pub(crate) fn len(&self) -> u16 {
+        self.op.size()
     }
 
-    // return the instruction y (upper 4 bits of the low byte)
-    pub(crate) fn y(&self) -> u8 {
-        self.y
+    // return the 16 bit address following F000
+    pub(crate) fn long(&self) -> u16 {
+        self.long.unwrap_or(0)
     }
 
-    // return the instruction kk (lowest 8 bits)
-    pub(crate) fn kk(&self) -> u8 {
-        self.kk
+    // return the full 16 bit instruction
+    pub(crate) fn opcode(&self) -> u16 {
+        self.opcode
     }
 
-    // return the instruction nnn (lowest 12 bits)
-    pub(crate) fn nnn(&self) -> u16 {
-        self.nnn
+    // return the decoded instruction
+    pub(crate) fn op(&self) -> Op {
+        self.op
     }
 }
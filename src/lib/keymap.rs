@@ -0,0 +1,133 @@
+use crate::EmulatorError;
+
+use std::fs;
+use std::path::Path;
+
+// Which keyboard key stands for each key of the Chip-8 keypad. Keyboard keys
+// are named the way SDL names them ("1", "Q", "Space", "Left", ...) and are
+// compared without regard to case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyMap {
+    keys: Vec<(String, u8)>,
+}
+
+impl KeyMap {
+    // the community standard layout. The left of the keyboard has the same
+    // shape as the COSMAC VIP hex keypad:
+    //
+    //   1 2 3 C        1 2 3 4
+    //   4 5 6 D   <-   Q W E R
+    //   7 8 9 E        A S D F
+    //   A 0 B F        Z X C V
+    pub fn qwerty() -> KeyMap {
+        KeyMap::from_pairs(&[
+            ("1", 0x1),
+            ("2", 0x2),
+            ("3", 0x3),
+            ("4", 0xC),
+            ("Q", 0x4),
+            ("W", 0x5),
+            ("E", 0x6),
+            ("R", 0xD),
+            ("A", 0x7),
+            ("S", 0x8),
+            ("D", 0x9),
+            ("F", 0xE),
+            ("Z", 0xA),
+            ("X", 0x0),
+            ("C", 0xB),
+            ("V", 0xF),
+        ])
+    }
+
+    // keyboard keys 0-9 and A-F stand for the keypad key with the same label
+    pub fn hex() -> KeyMap {
+        KeyMap::from_pairs(&[
+            ("0", 0x0),
+            ("1", 0x1),
+            ("2", 0x2),
+            ("3", 0x3),
+            ("4", 0x4),
+            ("5", 0x5),
+            ("6", 0x6),
+            ("7", 0x7),
+            ("8", 0x8),
+            ("9", 0x9),
+            ("A", 0xA),
+            ("B", 0xB),
+            ("C", 0xC),
+            ("D", 0xD),
+            ("E", 0xE),
+            ("F", 0xF),
+        ])
+    }
+
+    fn from_pairs(pairs: &[(&str, u8)]) -> KeyMap {
+        KeyMap {
+            keys: pairs
+                .iter()
+                .map(|(name, key)| (name.to_string(), *key))
+                .collect(),
+        }
+    }
+
+    // read a key map from text. Every line maps one keyboard key to a keypad
+    // key (a hex digit), anything after a # is a comment:
+    //
+    //   # movement
+    //   Up = 5
+    //   Left = 7
+    pub fn parse(text: &str) -> Result<KeyMap, EmulatorError> {
+        let mut map = KeyMap { keys: Vec::new() };
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || EmulatorError::InvalidKeyMap {
+                line: number + 1,
+                text: line.to_string(),
+            };
+
+            let (name, key) = line.split_once('=').ok_or_else(invalid)?;
+            let (name, key) = (name.trim(), key.trim());
+            let key =
+                u8::from_str_radix(key.trim_start_matches("0x"), 16).map_err(|_| invalid())?;
+
+            if name.is_empty() || key > 0xF {
+                return Err(invalid());
+            }
+
+            map.set(name, key);
+        }
+
+        Ok(map)
+    }
+
+    // read a key map from a file, see parse for the format
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KeyMap, EmulatorError> {
+        KeyMap::parse(&fs::read_to_string(path)?)
+    }
+
+    // make the keyboard key called name stand for keypad key (0-F)
+    pub fn set(&mut self, name: &str, key: u8) {
+        self.keys.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.keys.push((name.to_string(), key & 0x0F));
+    }
+
+    // the keypad key the keyboard key called name stands for
+    pub fn keypad_key(&self, name: &str) -> Option<u8> {
+        self.keys
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, key)| *key)
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::qwerty()
+    }
+}
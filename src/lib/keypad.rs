@@ -0,0 +1,56 @@
+// Chip-8 language had a 16-key hexadecimal keypad. The keypad tracks which of
+// the keys 0-F are held down, so any number of them can be down at once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Keypad {
+    keys: [bool; 16],
+}
+
+impl Keypad {
+    // a keypad with every key up
+    pub fn new() -> Keypad {
+        Keypad { keys: [false; 16] }
+    }
+
+    // mark key (0-F) as held down
+    pub fn press(&mut self, key: u8) {
+        self.keys[(key & 0x0F) as usize] = true;
+    }
+
+    // mark key (0-F) as up
+    pub fn release(&mut self, key: u8) {
+        self.keys[(key & 0x0F) as usize] = false;
+    }
+
+    // whether key (0-F) is held down. Only the low nibble of key is used, like
+    // the COSMAC VIP did for Ex9E and ExA1.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[(key & 0x0F) as usize]
+    }
+
+    // the lowest numbered key held down, if any
+    pub fn first_pressed(&self) -> Option<u8> {
+        self.keys.iter().position(|down| *down).map(|key| key as u8)
+    }
+
+    // release every key
+    pub fn clear(&mut self) {
+        self.keys = [false; 16];
+    }
+
+    // the keypad as a bitmask, bit n is set while key n is down
+    pub fn bits(&self) -> u16 {
+        self.keys
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (key, down)| bits | (*down as u16) << key)
+    }
+
+    // a keypad with the keys in the bitmask bits held down
+    pub fn from_bits(bits: u16) -> Keypad {
+        let mut keypad = Keypad::new();
+        for key in 0..16 {
+            keypad.keys[key] = bits & (1 << key) != 0;
+        }
+        keypad
+    }
+}
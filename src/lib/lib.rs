@@ -1,11 +1,55 @@
+#![allow(clippy::upper_case_acronyms)]
+
+mod asm;
+mod condition;
+mod config;
 mod cpu;
+mod debugger;
+mod disasm;
+#[cfg(feature = "sdl")]
 mod display;
+mod error;
+mod framebuffer;
+mod history;
 mod instruction;
+mod keymap;
+mod keypad;
 mod memory;
+mod movie;
+mod octo;
+mod op;
+mod opcode;
+mod quirks;
+mod rewind;
+mod rng;
+mod scheduler;
+mod state;
 mod system;
+mod trace;
+mod video;
 
+pub use asm::{assemble, assemble_file, Program, SourceLine};
+pub use condition::Condition;
+pub use config::{Config, Mode, Speed};
+pub use cpu::Registers;
+pub use debugger::{Debugger, Stop, Watch, Watchpoint};
+pub use disasm::{disassemble, disassemble_instruction, Syntax};
+#[cfg(feature = "sdl")]
 pub use display::Display;
-pub use system::System;
+pub use error::EmulatorError;
+pub use framebuffer::Framebuffer;
+pub use history::MemoryWrite;
+pub use keymap::KeyMap;
+pub use keypad::Keypad;
+pub use memory::Access;
+pub use movie::Movie;
+pub use octo::{compile_octo, compile_octo_file};
+pub use op::Op;
+pub use quirks::Quirks;
+pub use rng::{Rng, RngKind, VipRng, Xorshift};
+pub use system::{Events, Stopped, System};
+pub use trace::{first_difference, read_trace, Trace, TraceFormat, TraceLine};
+pub use video::{Command, Video};
 
 pub(crate) use cpu::CPU;
 pub(crate) use instruction::Instruction;
@@ -1,36 +1,124 @@
+use crate::state::{invalid, State, StateWriter};
+use crate::{EmulatorError, Mode};
+
+use std::cell::RefCell;
+
+// A read or write of len bytes of memory starting at addr, made by an
+// instruction. Instruction fetches aren't counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub addr: u16,
+    pub len: u16,
+    pub write: bool,
+}
+
+// What one instruction changed in memory, enough to put it back the way it
+// was.
+#[derive(Clone, Default)]
+pub(crate) struct Undo {
+    // the old value of every byte of ram written, in the order they were
+    // written
+    ram: Vec<(u16, u8)>,
+    // the old value of every pixel changed
+    vram: Vec<(u16, u8)>,
+    hires: bool,
+    plane: u8,
+    audio: [u8; 16],
+    stack: [u16; 16],
+    sp: usize,
+}
+
+impl Undo {
+    // the value addr had before the instruction, if it wrote addr
+    pub(crate) fn old_value(&self, addr: u16) -> Option<u8> {
+        self.ram
+            .iter()
+            .find(|(a, _)| *a == addr)
+            .map(|(_, old)| *old)
+    }
+}
+
+// an Undo being filled in while an instruction runs
+#[derive(Clone)]
+struct Journal {
+    undo: Undo,
+    // vram before the instruction, only kept for instructions that draw
+    vram: Option<Box<[u8; 128 * 64]>>,
+}
+
 // Chip-8 memory is 4096 bytes, byte addressable from 0x000 to 0xFFF inclusive.
 // The programs (ROM) will start at location 0x200
 // Memory address are 12 bits wide, giving Chip-8 2^12 (4096) memory address
+// XO-CHIP extends memory to 64KiB, addressable from 0x0000 to 0xFFFF.
 // The stack is an array of 16 16bit values used to store return address for subroutines
+// vram is big enough for the SUPER-CHIP 128x64 hi-res screen. In lo-res only
+// the first 64x32 pixels are used, one row after the other. Each pixel holds one
+// bit per XO-CHIP bitplane, bit 0 for plane 1 and bit 1 for plane 2.
+#[derive(Clone)]
 pub struct Memory {
     rom_location: u16,
-    ram: [u8; 0x1000],
-    vram: [u8; 64 * 32],
+    ram: Vec<u8>,
+    vram: [u8; 128 * 64],
+    hires: bool,
+    // bitmask of the planes drawn to, cleared and scrolled. Plain Chip-8 only
+    // ever uses plane 1.
+    plane: u8,
+    // XO-CHIP audio pattern, 128 1-bit samples
+    audio: [u8; 16],
     stack: [u16; 16],
     sp: usize,
+    // the reads and writes made while a debugger is watching memory, None
+    // when nothing is. Reads only have &self, so it is a RefCell.
+    accesses: RefCell<Option<Vec<Access>>>,
+    // what the running instruction changes, while the system keeps a history
+    journal: Option<Journal>,
 }
 
+// the small 4x5 font is stored at 0x000, the large 8x10 font right after it
+pub(crate) const FONT_LOCATION: u16 = 0x000;
+pub(crate) const BIG_FONT_LOCATION: u16 = 0x050;
+
 impl Memory {
-    pub(crate) fn allocate() -> Memory {
+    pub(crate) fn allocate(mode: Mode) -> Memory {
+        let size = match mode {
+            Mode::XoChip => 0x10000,
+            _ => 0x1000,
+        };
+
         let mut mem = Memory {
             rom_location: 0x200,
-            ram: [0; 0x1000],
-            vram: [0; 64 * 32],
+            ram: vec![0; size],
+            vram: [0; 128 * 64],
+            hires: false,
+            plane: 1,
+            audio: [0; 16],
             stack: [0; 16],
             sp: 0,
+            accesses: RefCell::new(None),
+            journal: None,
         };
 
-        // load the static font starting at memory location 0x000
+        // load the static fonts starting at memory location 0x000
         mem.write_font_data();
+        mem.write_big_font_data();
         mem
     }
 
     // write the data read from the rom file and load it into the stack starting
     // at memory location 0x200.
-    pub(crate) fn write_rom_data(&mut self, data: Vec<u8>) {
-        for offset in 0..data.len() {
-            self.ram[self.rom_location as usize + offset] = data[offset];
+    pub(crate) fn write_rom_data(&mut self, data: Vec<u8>) -> Result<(), EmulatorError> {
+        let start = self.rom_location as usize;
+        let max = self.ram.len() - start;
+
+        if data.len() > max {
+            return Err(EmulatorError::RomTooLarge {
+                size: data.len(),
+                max,
+            });
         }
+
+        self.ram[start..start + data.len()].copy_from_slice(&data);
+        Ok(())
     }
 
     // font data is static and loaded into the stack staring at memory
@@ -55,34 +143,159 @@ impl Memory {
             0xF0, 0x80, 0xF0, 0x80, 0x80, // F
         ];
 
-        for i in 0..fonts.len() {
-            self.ram[i] = fonts[i];
-        }
+        let start = FONT_LOCATION as usize;
+        self.ram[start..start + fonts.len()].copy_from_slice(&fonts);
+    }
+
+    // the SUPER-CHIP large font, 10 bytes per digit, is loaded right after the
+    // small font. SUPER-CHIP only had 0-9, A-F are the digits Octo added.
+    pub(crate) fn write_big_font_data(&mut self) {
+        let fonts = vec![
+            0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+            0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+            0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+            0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+            0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+            0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+            0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+            0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+            0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+            0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+            0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+            0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+        ];
+
+        let start = BIG_FONT_LOCATION as usize;
+        self.ram[start..start + fonts.len()].copy_from_slice(&fonts);
     }
 
     // write a single byte to memory location
-    pub(crate) fn write_byte(&mut self, location: u16, val: u8) -> Result<(), String> {
-        if location >= 0x1000 {
-            return Err(stringify!("Invalid write memory location: {}", loc).to_string());
+    pub(crate) fn write_byte(&mut self, location: u16, val: u8) -> Result<(), EmulatorError> {
+        if location as usize >= self.ram.len() {
+            return Err(EmulatorError::MemoryOutOfBounds {
+                addr: location as usize,
+                len: 1,
+            });
+        }
+
+        if let Some(journal) = &mut self.journal {
+            journal
+                .undo
+                .ram
+                .push((location, self.ram[location as usize]));
         }
 
         self.ram[location as usize] = val;
+        self.record(location, 1, true);
         Ok(())
     }
 
-    // clears the data from vram. Usually done because of the clear instruction
+    // clears the selected planes from vram. Usually done because of the clear instruction
     pub(crate) fn clear_vram(&mut self) {
-        for i in 0..self.vram.len() {
-            self.vram[i] = 0;
+        let keep = !self.plane;
+        for pixel in self.vram.iter_mut() {
+            *pixel &= keep;
+        }
+    }
+
+    // width of the screen in pixels, 64 in lo-res and 128 in hi-res
+    pub(crate) fn width(&self) -> usize {
+        match self.hires {
+            true => 128,
+            false => 64,
+        }
+    }
+
+    // height of the screen in pixels, 32 in lo-res and 64 in hi-res
+    pub(crate) fn height(&self) -> usize {
+        match self.hires {
+            true => 64,
+            false => 32,
+        }
+    }
+
+    // switch between the 64x32 lo-res and 128x64 hi-res screen. The layout of
+    // vram depends on the resolution so every plane is cleared.
+    pub(crate) fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.vram.fill(0);
+    }
+
+    // the bitmask of planes drawn to, cleared and scrolled
+    pub(crate) fn plane(&self) -> u8 {
+        self.plane
+    }
+
+    // select the XO-CHIP bitplanes used by later draw, clear and scroll instructions
+    pub(crate) fn set_plane(&mut self, plane: u8) {
+        self.plane = plane & 0x03;
+    }
+
+    // the XO-CHIP audio pattern buffer
+    pub(crate) fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio
+    }
+
+    // load the 16 byte XO-CHIP audio pattern buffer from memory starting at loc
+    pub(crate) fn load_audio_pattern(&mut self, loc: u16) -> Result<(), EmulatorError> {
+        let pattern = self.read_n_bytes(16, loc as usize)?;
+        self.audio.copy_from_slice(&pattern);
+        Ok(())
+    }
+
+    // move the selected planes down n pixels, blank rows come in at the top
+    pub(crate) fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    // move the selected planes up n pixels, blank rows come in at the bottom
+    pub(crate) fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n as isize));
+    }
+
+    // move the selected planes left n pixels, blank columns come in on the right
+    pub(crate) fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0);
+    }
+
+    // move the selected planes right n pixels, blank columns come in on the left
+    pub(crate) fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0);
+    }
+
+    // move the selected planes dx pixels right and dy pixels down. Pixels in
+    // planes that aren't selected stay where they are.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (w, h) = (self.width() as isize, self.height() as isize);
+        let old = self.vram[..(w * h) as usize].to_vec();
+
+        for y in 0..h {
+            for x in 0..w {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let src = match (0..w).contains(&src_x) && (0..h).contains(&src_y) {
+                    true => old[(src_y * w + src_x) as usize],
+                    false => 0,
+                };
+
+                let idx = (y * w + x) as usize;
+                self.vram[idx] = (self.vram[idx] & !self.plane) | (src & self.plane);
+            }
         }
     }
 
     // loc is the memory address likely taken from the PC register.
-    // If the location is greater than 0xFFF and error is returned.
+    // If either byte of the word is past the end of memory an error is returned.
     // Chip-8 instructions are 2 bytes long stored in big-endian
-    pub(crate) fn read_word(&self, loc: u16) -> Result<u16, String> {
-        if loc > 0xFFF {
-            return Err(stringify!("Invalid read memory location: {}", loc).to_string());
+    pub(crate) fn read_word(&self, loc: u16) -> Result<u16, EmulatorError> {
+        if loc as usize + 1 >= self.ram.len() {
+            return Err(EmulatorError::MemoryOutOfBounds {
+                addr: loc as usize,
+                len: 2,
+            });
         }
 
         let msb: u16 = self.ram[loc as usize] as u16;
@@ -93,65 +306,216 @@ impl Memory {
     }
 
     // read a single 8 bit value from memory location loc
-    pub(crate) fn read_byte(&self, loc: u16) -> Result<u8, String> {
-        if loc > 0xFFF {
-            return Err(stringify!("Invalid memory location: {}", loc).to_string());
+    pub(crate) fn read_byte(&self, loc: u16) -> Result<u8, EmulatorError> {
+        if loc as usize >= self.ram.len() {
+            return Err(EmulatorError::MemoryOutOfBounds {
+                addr: loc as usize,
+                len: 1,
+            });
         }
 
+        self.record(loc, 1, false);
         Ok(self.ram[loc as usize])
     }
 
     // read 'count' number of bytes out of memory starting from location 'loc'
-    pub(crate) fn read_n_bytes(&self, count: usize, loc: usize) -> Result<Vec<u8>, String> {
-        if loc + count >= self.ram.len() {
-            return Err(stringify!(
-                "Read memory out of bounds. Location: {}, Offset: {}",
-                loc,
-                count
-            )
-            .to_string());
+    pub(crate) fn read_n_bytes(&self, count: usize, loc: usize) -> Result<Vec<u8>, EmulatorError> {
+        if loc + count > self.ram.len() {
+            return Err(EmulatorError::MemoryOutOfBounds {
+                addr: loc,
+                len: count,
+            });
         }
 
         let mut mem = Vec::new();
         for i in 0..count {
             mem.push(self.ram[loc + i]);
         }
+
+        if count > 0 {
+            self.record(loc as u16, count as u16, false);
+        }
         Ok(mem)
     }
 
-    // get a copy of the contents of vram pixels
-    pub(crate) fn get_vram(&mut self) -> &mut [u8; 32 * 64] {
-        &mut self.vram
+    // get the vram pixels for the current resolution, one row after the other
+    pub(crate) fn get_vram(&mut self) -> &mut [u8] {
+        let len = self.width() * self.height();
+        &mut self.vram[..len]
+    }
+
+    // read only view of the vram pixels for the current resolution
+    pub(crate) fn vram(&self) -> &[u8] {
+        &self.vram[..self.width() * self.height()]
     }
 
     // remove and return the value on top of the stack
-    pub(crate) fn pop_stack(&mut self) -> u16 {
+    pub(crate) fn pop_stack(&mut self) -> Result<u16, EmulatorError> {
+        if self.sp == 0 {
+            return Err(EmulatorError::StackUnderflow);
+        }
+
         self.sp -= 1;
-        self.stack[self.sp]
+        Ok(self.stack[self.sp])
     }
 
     // push a value to the top of the stack
-    pub(crate) fn push_stack(&mut self, val: u16) {
+    pub(crate) fn push_stack(&mut self, val: u16) -> Result<(), EmulatorError> {
+        if self.sp == self.stack.len() {
+            return Err(EmulatorError::StackOverflow);
+        }
+
         self.stack[self.sp] = val;
         self.sp += 1;
+        Ok(())
+    }
+
+    // write ram, vram, the audio pattern and the stack to a save state
+    pub(crate) fn save(&self, w: &mut StateWriter) {
+        w.chunk(b"MEM ", |w| {
+            w.u32(self.ram.len() as u32);
+            w.bytes(&self.ram);
+        });
+        w.chunk(b"VRAM", |w| {
+            w.bool(self.hires);
+            w.u8(self.plane);
+            w.bytes(&self.vram);
+        });
+        w.chunk(b"AUDI", |w| w.bytes(&self.audio));
+        w.chunk(b"STCK", |w| {
+            w.u8(self.sp as u8);
+            for addr in self.stack {
+                w.u16(addr);
+            }
+        });
+    }
+
+    // restore ram, vram, the audio pattern and the stack from a save state
+    pub(crate) fn load(&mut self, state: &State) -> Result<(), EmulatorError> {
+        let mut r = state.chunk(b"MEM ")?;
+        if r.u32()? as usize != self.ram.len() {
+            return Err(invalid("memory size doesn't match".to_string()));
+        }
+        r.bytes(&mut self.ram)?;
+
+        let mut r = state.chunk(b"VRAM")?;
+        self.hires = r.bool()?;
+        self.plane = r.u8()? & 0x03;
+        r.bytes(&mut self.vram)?;
+
+        state.chunk(b"AUDI")?.bytes(&mut self.audio)?;
+
+        let mut r = state.chunk(b"STCK")?;
+        self.sp = (r.u8()? as usize).min(self.stack.len());
+        for addr in self.stack.iter_mut() {
+            *addr = r.u16()?;
+        }
+
+        Ok(())
+    }
+
+    // the return addresses on the stack, the most recent call last
+    pub(crate) fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
     }
 
-    // print the contents of the stack
-    pub(crate) fn print_stack(&self) {}
+    // set how many return addresses are on the stack, the addresses already
+    // in the slots are kept
+    pub(crate) fn set_stack_depth(&mut self, sp: usize) {
+        self.sp = sp.min(self.stack.len());
+    }
 
-    // print the contents of the stack from 0x000 to 0xFFF inclusive
-    pub(crate) fn print_memory(&self) {
-        let mut addr: u16 = 0x000;
+    // start noting what the next instruction changes, screen says whether it
+    // draws
+    pub(crate) fn begin_undo(&mut self, screen: bool) {
+        self.journal = Some(Journal {
+            undo: Undo {
+                ram: Vec::new(),
+                vram: Vec::new(),
+                hires: self.hires,
+                plane: self.plane,
+                audio: self.audio,
+                stack: self.stack,
+                sp: self.sp,
+            },
+            vram: screen.then(|| Box::new(self.vram)),
+        });
+    }
+
+    // stop noting changes, returns what changed since begin_undo
+    pub(crate) fn end_undo(&mut self) -> Undo {
+        let Journal { mut undo, vram } = match self.journal.take() {
+            Some(journal) => journal,
+            None => return Undo::default(),
+        };
 
-        print!("{:#09x}  ", addr);
-        for i in 0..self.ram.len() {
-            if i > 0 && i % 8 == 0 {
-                addr += 8;
-                print!("\n{:#09x}  ", addr);
+        if let Some(old) = vram {
+            for (idx, (old, new)) in old.iter().zip(self.vram.iter()).enumerate() {
+                if old != new {
+                    undo.vram.push((idx as u16, *old));
+                }
             }
+        }
+
+        undo
+    }
+
+    // put memory back the way it was before the changes in undo were made
+    pub(crate) fn undo(&mut self, undo: &Undo) {
+        for (addr, old) in undo.ram.iter().rev() {
+            self.ram[*addr as usize] = *old;
+        }
+        for (idx, old) in &undo.vram {
+            self.vram[*idx as usize] = *old;
+        }
 
-            print!("{:#04x} ", self.ram[i]);
+        self.hires = undo.hires;
+        self.plane = undo.plane;
+        self.audio = undo.audio;
+        self.stack = undo.stack;
+        self.sp = undo.sp;
+    }
+
+    // start or stop recording the reads and writes instructions make
+    pub(crate) fn record_accesses(&mut self, on: bool) {
+        *self.accesses.get_mut() = on.then(Vec::new);
+    }
+
+    // the accesses recorded since the last call, recording carries on
+    pub(crate) fn take_accesses(&mut self) -> Vec<Access> {
+        self.accesses
+            .get_mut()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // note an access if they are being recorded. Byte by byte accesses next to
+    // each other, like Fx55 and Fx33 make, are joined into one.
+    fn record(&self, addr: u16, len: u16, write: bool) {
+        let mut accesses = self.accesses.borrow_mut();
+        let accesses = match accesses.as_mut() {
+            Some(accesses) => accesses,
+            None => return,
+        };
+
+        if let Some(last) = accesses.last_mut() {
+            if last.write == write && addr == last.addr.wrapping_add(last.len) {
+                last.len += len;
+                return;
+            }
+            if last.write == write && addr.wrapping_add(len) == last.addr {
+                last.addr = addr;
+                last.len += len;
+                return;
+            }
         }
-        println!("");
+
+        accesses.push(Access { addr, len, write });
+    }
+
+    // all of ram, from 0x000 to the end of memory
+    pub(crate) fn ram(&self) -> &[u8] {
+        &self.ram
     }
 }
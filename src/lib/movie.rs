@@ -0,0 +1,212 @@
+use crate::state::{State, StateWriter};
+use crate::{Config, EmulatorError, Keypad, Mode, Quirks, RngKind, Speed};
+
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"C8MV";
+const MOVIE_VERSION: u16 = 1;
+// the most frames a movie holds, a day at 60 frames a second. Run lengths come
+// from the file, this keeps a corrupt one from asking for gigabytes.
+const MAX_FRAMES: usize = 24 * 60 * 60 * 60;
+
+// A recording of the keypad, one entry per 60Hz frame, along with everything
+// needed to play it back: the rom it was recorded on, the config the system was
+// created with and the state of the machine when recording started. Playing
+// the movie on a system made from the same rom and config repeats the
+// recorded run exactly.
+//
+// Movies are stored in the save state container (see state.rs) with these
+// chunks:
+//
+//   HEAD  crc32 of the rom, mode, quirks, speed, rng and seed
+//   STRT  the save state recording started from
+//   KEYS  the keypad of every frame as runs of (keypad bits u16, frames u32)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    rom_hash: u32,
+    config: Config,
+    start: Vec<u8>,
+    frames: Vec<u16>,
+}
+
+impl Movie {
+    pub(crate) fn new(rom_hash: u32, config: Config, start: Vec<u8>) -> Movie {
+        Movie {
+            rom_hash,
+            config,
+            start,
+            frames: Vec::new(),
+        }
+    }
+
+    // CRC-32 of the rom the movie was recorded on
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    // the config to create the system playing the movie back with
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    // number of frames recorded
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // the keys held down during frame n of the movie
+    pub fn keypad(&self, n: usize) -> Option<Keypad> {
+        self.frames.get(n).map(|bits| Keypad::from_bits(*bits))
+    }
+
+    // the save state the movie starts from
+    pub(crate) fn start(&self) -> &[u8] {
+        &self.start
+    }
+
+    // record the keys held down for frame n, dropping anything recorded after
+    // it. Recording stops after MAX_FRAMES.
+    pub(crate) fn record(&mut self, n: usize, keypad: &Keypad) {
+        self.frames.truncate(n);
+        if self.frames.len() < MAX_FRAMES {
+            self.frames.push(keypad.bits());
+        }
+    }
+
+    // drop every frame from n on
+    pub(crate) fn truncate(&mut self, n: usize) {
+        self.frames.truncate(n);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+
+        w.chunk(b"HEAD", |w| {
+            let config = &self.config;
+            let quirks = &config.quirks;
+
+            w.u32(self.rom_hash);
+            w.u8(config.mode as u8);
+            for quirk in [
+                quirks.shift,
+                quirks.load_store,
+                quirks.jump,
+                quirks.vf_reset,
+                quirks.clipping,
+                quirks.display_wait,
+                quirks.key_press,
+            ] {
+                w.bool(quirk);
+            }
+            match config.speed {
+                Speed::InstructionsPerFrame(n) => {
+                    w.u8(0);
+                    w.u32(n);
+                }
+                Speed::Hz(hz) => {
+                    w.u8(1);
+                    w.u32(hz);
+                }
+            }
+            w.u8(config.rng as u8);
+            w.u64(config.seed);
+        });
+
+        w.chunk(b"STRT", |w| w.bytes(&self.start));
+
+        w.chunk(b"KEYS", |w| {
+            for run in self.frames.chunk_by(|a, b| a == b) {
+                w.u16(run[0]);
+                w.u32(run.len() as u32);
+            }
+        });
+
+        w.finish_as(MAGIC, MOVIE_VERSION)
+    }
+
+    pub fn from_bytes(movie: &[u8]) -> Result<Movie, EmulatorError> {
+        Movie::parse(movie).map_err(|e| match e {
+            EmulatorError::InvalidState(reason) => EmulatorError::InvalidMovie(reason),
+            e => e,
+        })
+    }
+
+    fn parse(movie: &[u8]) -> Result<Movie, EmulatorError> {
+        let movie = State::parse_as(movie, MAGIC, MOVIE_VERSION)?;
+
+        let mut r = movie.chunk(b"HEAD")?;
+        let rom_hash = r.u32()?;
+        let mode = match r.u8()? {
+            0 => Mode::Chip8,
+            1 => Mode::SuperChip,
+            2 => Mode::XoChip,
+            mode => return Err(invalid(format!("unknown mode {}", mode))),
+        };
+        let quirks = Quirks {
+            shift: r.bool()?,
+            load_store: r.bool()?,
+            jump: r.bool()?,
+            vf_reset: r.bool()?,
+            clipping: r.bool()?,
+            display_wait: r.bool()?,
+            key_press: r.bool()?,
+        };
+        let speed = match r.u8()? {
+            0 => Speed::InstructionsPerFrame(r.u32()?),
+            1 => Speed::Hz(r.u32()?),
+            speed => return Err(invalid(format!("unknown speed {}", speed))),
+        };
+        let rng = match r.u8()? {
+            0 => RngKind::Xorshift,
+            1 => RngKind::CosmacVip,
+            rng => return Err(invalid(format!("unknown rng {}", rng))),
+        };
+        let seed = r.u64()?;
+
+        let mut r = movie.chunk(b"STRT")?;
+        let mut start = vec![0; r.remaining()];
+        r.bytes(&mut start)?;
+
+        let mut r = movie.chunk(b"KEYS")?;
+        let mut frames = Vec::new();
+        while r.remaining() > 0 {
+            let keys = r.u16()?;
+            let len = r.u32()? as usize;
+            if len > MAX_FRAMES - frames.len() {
+                return Err(invalid(format!("more than {} frames", MAX_FRAMES)));
+            }
+            frames.extend(std::iter::repeat_n(keys, len));
+        }
+
+        Ok(Movie {
+            rom_hash,
+            config: Config {
+                mode,
+                quirks,
+                speed,
+                rng,
+                seed,
+            },
+            start,
+            frames,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Movie, EmulatorError> {
+        Movie::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), EmulatorError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+}
+
+fn invalid(reason: String) -> EmulatorError {
+    EmulatorError::InvalidMovie(reason)
+}
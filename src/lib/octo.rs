@@ -0,0 +1,996 @@
+use crate::asm::{Program, SourceLine};
+use crate::opcode::{Opcode, Operand, OPCODES};
+use crate::{EmulatorError, Mode};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// where roms are loaded in memory, execution starts at the first byte
+const START: usize = 0x200;
+
+// macros expanding more than this many times are taken to be recursive
+const MAX_EXPANSIONS: usize = 100_000;
+
+// words with a meaning of their own that can't be used as names
+const KEYWORDS: &[&str] = &[
+    "if", "then", "begin", "else", "end", "loop", "again", "while", ";", ":", "{", "}",
+];
+
+// Compile a program written in Octo for the instruction set of mode.
+// Instructions SUPER-CHIP or XO-CHIP added are an error for modes without
+// them. Besides the statements of the instructions themselves this handles
+//
+//   : name                   a label, a bare name calls it
+//   :alias name vx           another name for a register
+//   :const name value        a constant
+//   :calc name { expr }      a constant worked out from other constants and
+//                            labels already defined
+//   :macro name args { ... } statements repeated wherever name is used
+//   :byte value, numbers     a byte of data
+//   :org addr                carry on compiling at addr
+//   :next name               label the operand byte of the next instruction
+//   :unpack n name           v0 and v1 := n << 12 | name, or a 16 bit address
+//                            with :unpack long
+//   if .. then, if .. begin .. else .. end
+//   loop .. while .. again
+//
+// Execution starts at 0x200, if anything comes before the label main a jump
+// to it is put there.
+pub fn compile_octo(source: &str, mode: Mode) -> Result<Program> {
+    Compiler::new(PathBuf::from("<source>"), mode).compile(source)
+}
+
+// Compile an Octo source file, see compile_octo
+pub fn compile_octo_file<P: AsRef<Path>>(path: P, mode: Mode) -> Result<Program> {
+    let source = fs::read_to_string(&path)?;
+    Compiler::new(path.as_ref().to_path_buf(), mode).compile(&source)
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    // a token made up by the compiler, e.g. the parts of a comparison
+    fn synthetic(text: &str, at: &Token) -> Token {
+        Token {
+            text: text.to_string(),
+            ..*at
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Name {
+    Label(usize),
+    Constant(f64),
+    Alias(u8),
+    Macro(Vec<String>, Vec<Token>),
+}
+
+// how a label that isn't defined yet is filled in once it is
+#[derive(Clone, Copy)]
+enum Patch {
+    // an operand of the instruction word
+    Field(Operand),
+    // the word following F000
+    Long,
+    // the high byte of :unpack, n << 12 | label
+    Unpack(u16),
+    // the low byte of :unpack
+    Low,
+}
+
+struct Fixup {
+    // address of the instruction to patch
+    addr: usize,
+    name: Token,
+    patch: Patch,
+}
+
+// an unfinished if or loop, with the addresses of the jumps out of it
+enum Flow {
+    Branch {
+        jump: usize,
+        at: Token,
+    },
+    Loop {
+        start: usize,
+        exits: Vec<usize>,
+        at: Token,
+    },
+}
+
+struct Compiler {
+    file: PathBuf,
+    mode: Mode,
+    memory: Vec<u8>,
+    // the address of the next instruction, and the end of the program so far
+    here: usize,
+    end: usize,
+    // whether it has been decided if 0x200 holds a jump to main
+    started: bool,
+    names: HashMap<String, Name>,
+    fixups: Vec<Fixup>,
+    flow: Vec<Flow>,
+    lines: Vec<SourceLine>,
+    // the tokens still to compile, last one first
+    tokens: Vec<Token>,
+    expansions: usize,
+}
+
+type Result<T> = std::result::Result<T, EmulatorError>;
+
+impl Compiler {
+    fn new(file: PathBuf, mode: Mode) -> Compiler {
+        let size = match mode {
+            Mode::XoChip => 0x10000,
+            _ => 0x1000,
+        };
+
+        Compiler {
+            file,
+            mode,
+            memory: vec![0; size],
+            here: START,
+            end: START,
+            started: false,
+            names: HashMap::new(),
+            fixups: Vec::new(),
+            flow: Vec::new(),
+            lines: Vec::new(),
+            tokens: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn error(&self, at: &Token, message: String) -> EmulatorError {
+        EmulatorError::Assemble {
+            file: self.file.display().to_string(),
+            line: at.line,
+            column: at.column,
+            message,
+        }
+    }
+
+    fn compile(mut self, source: &str) -> Result<Program> {
+        self.tokens = tokenize(source);
+        self.tokens.reverse();
+
+        while let Some(token) = self.tokens.pop() {
+            self.statement(token)?;
+        }
+
+        match self.flow.last() {
+            Some(Flow::Branch { at, .. }) => return Err(self.error(at, "if without an end".into())),
+            Some(Flow::Loop { at, .. }) => {
+                return Err(self.error(at, "loop without an again".into()))
+            }
+            None => {}
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.apply(fixup)?;
+        }
+
+        self.lines.sort_by_key(|line| line.addr);
+        Ok(Program {
+            rom: self.memory[START..self.end].to_vec(),
+            lines: self.lines,
+        })
+    }
+
+    fn statement(&mut self, token: Token) -> Result<()> {
+        // ; is short for return
+        let token = match token.text.as_str() {
+            ";" => Token::synthetic("return", &token),
+            _ => token,
+        };
+        let text = token.text.as_str();
+
+        // definitions don't take up any space, so they don't decide where main
+        // goes
+        match text {
+            ":const" => {
+                let name = self.name(&token)?;
+                let value = self.next(&token)?;
+                let value = self.constant(&value)?;
+                self.names.insert(name.text, Name::Constant(value));
+                return Ok(());
+            }
+            ":alias" => {
+                let name = self.name(&token)?;
+                let register = self.next(&token)?;
+                let r = self
+                    .register(&register.text)
+                    .ok_or_else(|| self.error(&register, "expected a register".into()))?;
+                self.names.insert(name.text, Name::Alias(r));
+                return Ok(());
+            }
+            ":calc" => {
+                let name = self.name(&token)?;
+                let value = self.calc(&token)?;
+                self.names.insert(name.text, Name::Constant(value));
+                return Ok(());
+            }
+            ":macro" => return self.define_macro(&token),
+            ":proto" | ":breakpoint" => {
+                self.next(&token)?;
+                return Ok(());
+            }
+            ":monitor" => {
+                self.next(&token)?;
+                self.next(&token)?;
+                return Ok(());
+            }
+            ":" if self.tokens.last().is_some_and(|t| t.text == "main") => {
+                let name = self.name(&token)?;
+                return self.define_label(name, self.here);
+            }
+            _ => {}
+        }
+
+        if let Some(Name::Macro(params, body)) = self.names.get(text).cloned() {
+            return self.expand(&token, params, body);
+        }
+
+        self.start()?;
+
+        match text {
+            ":" => {
+                let name = self.name(&token)?;
+                self.define_label(name, self.here)
+            }
+            ":next" => {
+                let name = self.name(&token)?;
+                // the address of i := long is the whole word after F000
+                let ahead: Vec<&str> = self
+                    .tokens
+                    .iter()
+                    .rev()
+                    .take(3)
+                    .map(|t| &t.text[..])
+                    .collect();
+                let operand = match ahead[..] {
+                    ["i", ":=", "long"] => 2,
+                    _ => 1,
+                };
+                self.define_label(name, self.here + operand)
+            }
+            ":org" => {
+                let addr = self.number(&token)?;
+                if !(START as f64..self.memory.len() as f64).contains(&addr) {
+                    return Err(self.error(&token, format!(":org {} is outside memory", addr)));
+                }
+                self.here = addr as usize;
+                Ok(())
+            }
+            ":byte" => {
+                let value = self.number(&token)?;
+                let byte = self.fit(&token, value as i64, -0x80, 0xFF)?;
+                self.emit(&[byte as u8], &token)
+            }
+            ":unpack" => self.unpack(&token),
+            "if" => self.conditional(&token),
+            "else" => match self.flow.pop() {
+                Some(Flow::Branch { jump, .. }) => {
+                    let skip = self.jump(&token)?;
+                    self.patch_jump(jump, self.here, &token)?;
+                    self.flow.push(Flow::Branch {
+                        jump: skip,
+                        at: token,
+                    });
+                    Ok(())
+                }
+                _ => Err(self.error(&token, "else without an if .. begin".into())),
+            },
+            "end" => match self.flow.pop() {
+                Some(Flow::Branch { jump, .. }) => self.patch_jump(jump, self.here, &token),
+                _ => Err(self.error(&token, "end without an if .. begin".into())),
+            },
+            "loop" => {
+                self.flow.push(Flow::Loop {
+                    start: self.here,
+                    exits: Vec::new(),
+                    at: token,
+                });
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition(&token, "while")?;
+                if !self.flow.iter().any(|f| matches!(f, Flow::Loop { .. })) {
+                    return Err(self.error(&token, "while outside a loop".into()));
+                }
+
+                self.skip_unless(&condition, true, &token)?;
+                let exit = self.jump(&token)?;
+                if let Some(Flow::Loop { exits, .. }) = self
+                    .flow
+                    .iter_mut()
+                    .rev()
+                    .find(|f| matches!(f, Flow::Loop { .. }))
+                {
+                    exits.push(exit);
+                }
+                Ok(())
+            }
+            "again" => match self.flow.pop() {
+                Some(Flow::Loop { start, exits, .. }) => {
+                    let back = self.jump(&token)?;
+                    self.patch_jump(back, start, &token)?;
+                    for exit in exits {
+                        self.patch_jump(exit, self.here, &token)?;
+                    }
+                    Ok(())
+                }
+                _ => Err(self.error(&token, "again without a loop".into())),
+            },
+            _ => {
+                // everything else is an instruction, a byte or a call
+                self.tokens.push(token.clone());
+                if self.instruction()? {
+                    return Ok(());
+                }
+                self.tokens.pop();
+
+                if let Some(n) = parse_number(text) {
+                    let byte = self.fit(&token, n, -0x80, 0xFF)?;
+                    return self.emit(&[byte as u8], &token);
+                }
+                if self.is_name(text) {
+                    let call = [Token::synthetic(":call", &token), token.clone()];
+                    return self.encode_tokens(&call).map(|_| ());
+                }
+
+                Err(self.error(&token, format!("unexpected {}", text)))
+            }
+        }
+    }
+
+    // put a jump to main at 0x200 if something comes before it
+    fn start(&mut self) -> Result<()> {
+        if self.started {
+            return Ok(());
+        }
+
+        self.started = true;
+        if self.names.contains_key("main") {
+            return Ok(());
+        }
+
+        let at = Token {
+            text: "main".into(),
+            line: 1,
+            column: 1,
+        };
+        self.here = START + 2;
+        self.end = self.end.max(self.here);
+        self.memory[START..START + 2].copy_from_slice(&0x1000u16.to_be_bytes());
+        self.fixups.push(Fixup {
+            addr: START,
+            name: at,
+            patch: Patch::Field(Operand::Addr),
+        });
+        Ok(())
+    }
+
+    fn next(&mut self, after: &Token) -> Result<Token> {
+        self.tokens.pop().ok_or_else(|| {
+            self.error(
+                after,
+                format!("{} is missing something after it", after.text),
+            )
+        })
+    }
+
+    // a new name for a label, constant, alias or macro
+    fn name(&mut self, after: &Token) -> Result<Token> {
+        let name = self.next(after)?;
+
+        match self.is_name(&name.text) || self.names.contains_key(&name.text) {
+            true => Ok(name),
+            false => Err(self.error(&name, format!("{} can't be used as a name", name.text))),
+        }
+    }
+
+    // whether text could name something rather than being a keyword, number or
+    // register
+    fn is_name(&self, text: &str) -> bool {
+        !KEYWORDS.contains(&text)
+            && !text.starts_with(':')
+            && !template_words().any(|word| word == text)
+            && parse_number(text).is_none()
+            && self.register(text).is_none()
+    }
+
+    fn define_label(&mut self, name: Token, addr: usize) -> Result<()> {
+        if let Some(Name::Label(_)) = self.names.get(&name.text) {
+            return Err(self.error(&name, format!("{} is already defined", name.text)));
+        }
+
+        self.names.insert(name.text, Name::Label(addr));
+        Ok(())
+    }
+
+    // the register v0 to vf, or an alias of one
+    fn register(&self, text: &str) -> Option<u8> {
+        if let Some(Name::Alias(r)) = self.names.get(text) {
+            return Some(*r);
+        }
+
+        let r = text.strip_prefix(['v', 'V'])?;
+        match r.len() {
+            1 => u8::from_str_radix(r, 16).ok(),
+            _ => None,
+        }
+    }
+
+    // a number, constant or label that's already defined
+    fn constant(&self, token: &Token) -> Result<f64> {
+        if let Some(n) = parse_number(&token.text) {
+            return Ok(n as f64);
+        }
+
+        match self.names.get(&token.text) {
+            Some(Name::Constant(value)) => Ok(*value),
+            Some(Name::Label(addr)) => Ok(*addr as f64),
+            _ => Err(self.error(token, format!("{} isn't defined", token.text))),
+        }
+    }
+
+    // the operand of :org or :byte, a constant or { expression }
+    fn number(&mut self, after: &Token) -> Result<f64> {
+        match self.tokens.last() {
+            Some(t) if t.text == "{" => self.calc(after),
+            _ => {
+                let token = self.next(after)?;
+                self.constant(&token)
+            }
+        }
+    }
+
+    fn fit(&self, token: &Token, value: i64, min: i64, max: i64) -> Result<i64> {
+        match (min..=max).contains(&value) {
+            true => Ok(value),
+            false => Err(self.error(
+                token,
+                format!(
+                    "{} is out of range, it needs to be {} to {}",
+                    value, min, max
+                ),
+            )),
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8], at: &Token) -> Result<()> {
+        if self.here + bytes.len() > self.memory.len() {
+            return Err(self.error(at, "program doesn't fit in memory".into()));
+        }
+
+        self.lines.push(SourceLine {
+            addr: self.here as u16,
+            file: self.file.clone(),
+            line: at.line,
+        });
+        self.memory[self.here..self.here + bytes.len()].copy_from_slice(bytes);
+        self.here += bytes.len();
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    // compile the instruction at the front of the tokens if there is one. The
+    // longest template in the opcode table that matches is used.
+    fn instruction(&mut self) -> Result<bool> {
+        let ahead: Vec<Token> = self.tokens.iter().rev().take(5).cloned().collect();
+
+        match self.encode_tokens(&ahead)? {
+            Some(len) => {
+                self.tokens.truncate(self.tokens.len() - len);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // encode the instruction tokens start with, returning how many tokens it
+    // took up or None if they aren't an instruction
+    fn encode_tokens(&mut self, tokens: &[Token]) -> Result<Option<usize>> {
+        let mut best: Option<&'static Opcode> = None;
+        for op in OPCODES.iter().filter(|op| self.matches(op, tokens)) {
+            let len = op.octo.split_whitespace().count();
+            if best.is_none_or(|b| b.octo.split_whitespace().count() < len) {
+                best = Some(op);
+            }
+        }
+
+        let op = match best {
+            Some(op) => op,
+            None => return Ok(None),
+        };
+
+        let mode = op.decode(op.pattern).mode();
+        if mode > self.mode {
+            let needs = match mode {
+                Mode::XoChip => "XO-CHIP",
+                _ => "SUPER-CHIP",
+            };
+            return Err(self.error(
+                &tokens[0],
+                format!(
+                    "{} needs {}",
+                    op.octo.split_whitespace().next().unwrap_or(""),
+                    needs
+                ),
+            ));
+        }
+
+        let addr = self.here;
+        let mut word = op.pattern;
+        let mut long = None;
+
+        for (template, token) in op.octo.split_whitespace().zip(tokens) {
+            let operand = match placeholder(template) {
+                Some(operand) => operand,
+                None => continue,
+            };
+
+            let value = match operand {
+                Operand::Vx | Operand::Vy => self.register(&token.text).unwrap_or(0) as u16,
+                Operand::Long => {
+                    long = Some(self.value(token, operand, addr)?);
+                    continue;
+                }
+                _ => self.value(token, operand, addr)?,
+            };
+            word = operand.insert(word, value);
+        }
+
+        let mut bytes = word.to_be_bytes().to_vec();
+        if let Some(long) = long {
+            bytes.extend_from_slice(&long.to_be_bytes());
+        }
+        self.emit(&bytes, &tokens[0])?;
+
+        Ok(Some(op.octo.split_whitespace().count()))
+    }
+
+    // whether tokens start with the octo template of op
+    fn matches(&self, op: &Opcode, tokens: &[Token]) -> bool {
+        let template: Vec<&str> = op.octo.split_whitespace().collect();
+        template.len() <= tokens.len()
+            && template
+                .iter()
+                .zip(tokens)
+                .all(|(word, token)| match placeholder(word) {
+                    Some(Operand::Vx | Operand::Vy) => self.register(&token.text).is_some(),
+                    Some(_) => {
+                        parse_number(&token.text).is_some()
+                            || (self.is_name(&token.text)
+                                && !matches!(self.names.get(&token.text), Some(Name::Macro(..))))
+                    }
+                    None => *word == token.text,
+                })
+    }
+
+    // the value of an operand. Labels that aren't defined yet are filled in
+    // once the whole program has been compiled.
+    fn value(&mut self, token: &Token, operand: Operand, addr: usize) -> Result<u16> {
+        let value = match parse_number(&token.text) {
+            Some(n) => n,
+            None => match self.names.get(&token.text) {
+                Some(Name::Constant(value)) => *value as i64,
+                Some(Name::Label(label)) => *label as i64,
+                _ => {
+                    let patch = match operand {
+                        Operand::Long => Patch::Long,
+                        _ => Patch::Field(operand),
+                    };
+                    self.fixups.push(Fixup {
+                        addr,
+                        name: token.clone(),
+                        patch,
+                    });
+                    return Ok(0);
+                }
+            },
+        };
+
+        let (min, max) = match operand {
+            Operand::Byte => (-0x80, 0xFF),
+            Operand::Addr => (0, 0xFFF),
+            Operand::Long => (0, 0xFFFF),
+            _ => (0, 0xF),
+        };
+        Ok(self.fit(token, value, min, max)? as u16)
+    }
+
+    // fill in a label used before it was defined
+    fn apply(&mut self, fixup: Fixup) -> Result<()> {
+        // :unpack leaves anything but a number to be filled in here, so
+        // constants are looked up as well as labels
+        let value = match (
+            parse_number(&fixup.name.text),
+            self.names.get(&fixup.name.text),
+        ) {
+            (Some(n), _) => n,
+            (None, Some(Name::Label(addr))) => *addr as i64,
+            (None, Some(Name::Constant(value))) => *value as i64,
+            _ => return Err(self.error(&fixup.name, format!("{} isn't defined", fixup.name.text))),
+        };
+
+        let addr = fixup.addr;
+        let word = u16::from_be_bytes([self.memory[addr], self.memory[addr + 1]]);
+
+        match fixup.patch {
+            Patch::Field(operand) => {
+                let max = operand.field().map_or(0, |(_, mask)| mask as i64);
+                let value = self.fit(&fixup.name, value, 0, max)?;
+                let word = operand.insert(word, value as u16);
+                self.memory[addr..addr + 2].copy_from_slice(&word.to_be_bytes());
+            }
+            Patch::Long => {
+                let value = self.fit(&fixup.name, value, 0, 0xFFFF)?;
+                self.memory[addr + 2..addr + 4].copy_from_slice(&(value as u16).to_be_bytes());
+            }
+            Patch::Unpack(n) => {
+                let max = if n == 0 { 0xFFFF } else { 0xFFF };
+                let value = self.fit(&fixup.name, value, 0, max)? as u16;
+                self.memory[addr + 1] = ((n << 12 | value) >> 8) as u8;
+            }
+            Patch::Low => self.memory[addr + 1] = value as u8,
+        }
+
+        Ok(())
+    }
+
+    // :unpack n label, or :unpack long label
+    fn unpack(&mut self, at: &Token) -> Result<()> {
+        let high = self.next(at)?;
+        let n = match high.text.as_str() {
+            "long" => 0,
+            _ => self.fit(&high, self.constant(&high)? as i64, 0, 0xF)? as u16,
+        };
+        let name = self.next(at)?;
+
+        for (register, patch) in [("v0", Patch::Unpack(n)), ("v1", Patch::Low)] {
+            let addr = self.here;
+            let load = [
+                Token::synthetic(register, at),
+                Token::synthetic(":=", at),
+                Token::synthetic("0", at),
+            ];
+            self.encode_tokens(&load)?;
+            self.fixups.push(Fixup {
+                addr,
+                name: name.clone(),
+                patch,
+            });
+        }
+        Ok(())
+    }
+
+    // a jump to be pointed somewhere later with patch_jump, returns its address
+    fn jump(&mut self, at: &Token) -> Result<usize> {
+        let addr = self.here;
+        self.encode_tokens(&[Token::synthetic("jump", at), Token::synthetic("0", at)])?;
+        Ok(addr)
+    }
+
+    fn patch_jump(&mut self, jump: usize, target: usize, at: &Token) -> Result<()> {
+        let target = self.fit(at, target as i64, 0, 0xFFF)? as u16;
+        let word = Operand::Addr.insert(0x1000, target);
+        self.memory[jump..jump + 2].copy_from_slice(&word.to_be_bytes());
+        Ok(())
+    }
+
+    // if <condition> then, or if <condition> begin
+    fn conditional(&mut self, at: &Token) -> Result<()> {
+        let condition = self.condition(at, "if")?;
+
+        match self.next(at)?.text.as_str() {
+            "then" => self.skip_unless(&condition, false, at),
+            "begin" => {
+                self.skip_unless(&condition, true, at)?;
+                let jump = self.jump(at)?;
+                self.flow.push(Flow::Branch {
+                    jump,
+                    at: at.clone(),
+                });
+                Ok(())
+            }
+            _ => Err(self.error(at, "if needs a then or begin".into())),
+        }
+    }
+
+    // the tokens of a condition, vx key, vx -key or vx <op> operand
+    fn condition(&mut self, at: &Token, what: &str) -> Result<Vec<Token>> {
+        let first = self.next(at)?;
+        let op = self.next(at)?;
+
+        match op.text.as_str() {
+            "key" | "-key" => Ok(vec![first, op]),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Ok(vec![first, op, self.next(at)?]),
+            _ => Err(self.error(&op, format!("{} needs a comparison", what))),
+        }
+    }
+
+    // skip the next instruction unless condition holds, or if negate is set
+    // unless it doesn't. Octo's if .. then does the same.
+    fn skip_unless(&mut self, condition: &[Token], negate: bool, at: &Token) -> Result<()> {
+        let mut condition = condition.to_vec();
+        if negate {
+            let flipped = match condition[1].text.as_str() {
+                "key" => "-key",
+                "-key" => "key",
+                "==" => "!=",
+                "!=" => "==",
+                "<" => ">=",
+                ">=" => "<",
+                ">" => "<=",
+                _ => ">",
+            };
+            condition[1].text = flipped.to_string();
+        }
+
+        // there's no instruction comparing the size of registers, vf is set to
+        // the carry of a subtraction and compared with 0 instead
+        if matches!(condition[1].text.as_str(), "<" | ">" | "<=" | ">=") {
+            let (x, op, y) = (&condition[0], condition[1].text.clone(), &condition[2]);
+
+            // x < y and x >= y look at whether x >= y, the others at y >= x
+            let x_at_least_y = matches!(op.as_str(), "<" | ">=");
+            let (first, sub, other) = match (self.register(&y.text).is_some(), x_at_least_y) {
+                (true, true) => (x, "-=", y),
+                (true, false) => (x, "=-", y),
+                (false, true) => (y, "=-", x),
+                (false, false) => (y, "-=", x),
+            };
+
+            let vf = Token::synthetic("vf", at);
+            let load = [vf.clone(), Token::synthetic(":=", at), first.clone()];
+            let subtract = [vf.clone(), Token::synthetic(sub, at), other.clone()];
+            for tokens in [&load[..], &subtract[..]] {
+                if self.encode_tokens(tokens)?.is_none() {
+                    return Err(self.error(at, "invalid comparison".into()));
+                }
+            }
+
+            let test = match op.as_str() {
+                "<" | ">" => "==",
+                _ => "!=",
+            };
+            condition = vec![vf, Token::synthetic(test, at), Token::synthetic("0", at)];
+        }
+
+        let mut tokens = vec![Token::synthetic("if", at)];
+        tokens.extend(condition);
+        tokens.push(Token::synthetic("then", at));
+
+        match self.encode_tokens(&tokens)? {
+            Some(_) => Ok(()),
+            None => Err(self.error(at, "invalid condition".into())),
+        }
+    }
+
+    // :calc name { expression }. Octo evaluates expressions right to left
+    // without precedence, 1 + 2 * 3 is 1 + (2 * 3) but 2 * 3 + 1 is 2 * 4.
+    fn calc(&mut self, at: &Token) -> Result<f64> {
+        let open = self.next(at)?;
+        if open.text != "{" {
+            return Err(self.error(&open, "expected {".into()));
+        }
+
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next(at)?;
+            if token.text == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+
+        tokens.reverse();
+        let value = self.expr(&mut tokens, at)?;
+        match tokens.pop() {
+            Some(extra) => Err(self.error(&extra, format!("unexpected {}", extra.text))),
+            None => Ok(value),
+        }
+    }
+
+    fn expr(&mut self, tokens: &mut Vec<Token>, at: &Token) -> Result<f64> {
+        let a = self.term(tokens, at)?;
+
+        let op = match tokens.last() {
+            Some(t) if t.text != ")" => tokens.pop().unwrap(),
+            _ => return Ok(a),
+        };
+        let b = self.expr(tokens, at)?;
+
+        let bool = |b: bool| if b { 1.0 } else { 0.0 };
+        let int = |x: f64| x as i64;
+        Ok(match op.text.as_str() {
+            "+" => a + b,
+            "-" => a - b,
+            "*" => a * b,
+            "/" => a / b,
+            "%" => a % b,
+            "&" => (int(a) & int(b)) as f64,
+            "|" => (int(a) | int(b)) as f64,
+            "^" => (int(a) ^ int(b)) as f64,
+            "<<" => int(a).wrapping_shl(int(b) as u32) as f64,
+            ">>" => int(a).wrapping_shr(int(b) as u32) as f64,
+            "pow" => a.powf(b),
+            "min" => a.min(b),
+            "max" => a.max(b),
+            "<" => bool(a < b),
+            ">" => bool(a > b),
+            "<=" => bool(a <= b),
+            ">=" => bool(a >= b),
+            "==" => bool(a == b),
+            "!=" => bool(a != b),
+            _ => return Err(self.error(&op, format!("unknown operator {}", op.text))),
+        })
+    }
+
+    fn term(&mut self, tokens: &mut Vec<Token>, at: &Token) -> Result<f64> {
+        let token = tokens
+            .pop()
+            .ok_or_else(|| self.error(at, "expression is missing a value".into()))?;
+
+        let unary = |f: fn(f64) -> f64, this: &mut Compiler, tokens: &mut Vec<Token>| {
+            this.term(tokens, at).map(f)
+        };
+
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expr(tokens, at)?;
+                match tokens.pop() {
+                    Some(t) if t.text == ")" => Ok(value),
+                    _ => Err(self.error(&token, "missing )".into())),
+                }
+            }
+            "-" => unary(|x| -x, self, tokens),
+            "~" => unary(|x| !(x as i64) as f64, self, tokens),
+            "!" => unary(|x| if x == 0.0 { 1.0 } else { 0.0 }, self, tokens),
+            "abs" => unary(f64::abs, self, tokens),
+            "sqrt" => unary(f64::sqrt, self, tokens),
+            "sin" => unary(f64::sin, self, tokens),
+            "cos" => unary(f64::cos, self, tokens),
+            "tan" => unary(f64::tan, self, tokens),
+            "exp" => unary(f64::exp, self, tokens),
+            "log" => unary(f64::ln, self, tokens),
+            "sign" => unary(f64::signum, self, tokens),
+            "ceil" => unary(f64::ceil, self, tokens),
+            "floor" => unary(f64::floor, self, tokens),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            "HERE" => {
+                self.start()?;
+                Ok(self.here as f64)
+            }
+            _ => self.constant(&token),
+        }
+    }
+
+    // :macro name params { body }
+    fn define_macro(&mut self, at: &Token) -> Result<()> {
+        let name = self.name(at)?;
+
+        let mut params = Vec::new();
+        loop {
+            let token = self.next(at)?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next(at)?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+
+        self.names.insert(name.text, Name::Macro(params, body));
+        Ok(())
+    }
+
+    // replace a use of a macro with its body, the parameters replaced by the
+    // tokens following the name
+    fn expand(&mut self, at: &Token, params: Vec<String>, body: Vec<Token>) -> Result<()> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(at, format!("{} expands forever", at.text)));
+        }
+
+        let mut args = HashMap::new();
+        for param in params {
+            let arg = self.next(at)?;
+            args.insert(param, arg.text);
+        }
+
+        for token in body.into_iter().rev() {
+            let text = args.get(&token.text).cloned().unwrap_or(token.text);
+            self.tokens.push(Token { text, ..token });
+        }
+        Ok(())
+    }
+}
+
+// the operand a placeholder of an octo template stands for
+fn placeholder(word: &str) -> Option<Operand> {
+    Some(match word {
+        "{x}" => Operand::Vx,
+        "{y}" => Operand::Vy,
+        "{n}" => Operand::Nibble,
+        "{kk}" => Operand::Byte,
+        "{nnn}" => Operand::Addr,
+        "{long}" => Operand::Long,
+        "{plane}" => Operand::Plane,
+        _ => return None,
+    })
+}
+
+// every fixed word of the octo templates, e.g. sprite, := and delay
+fn template_words() -> impl Iterator<Item = &'static str> {
+    OPCODES
+        .iter()
+        .flat_map(|op| op.octo.split_whitespace())
+        .filter(|word| placeholder(word).is_none())
+}
+
+// split source into whitespace separated tokens, dropping # comments
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (n, line) in source.lines().enumerate() {
+        let line_text = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+
+        let mut column = 0;
+        for word in line_text.split_whitespace() {
+            let start = line_text[column..].find(word).unwrap_or(0) + column;
+            column = start + word.len();
+            tokens.push(Token {
+                text: word.to_string(),
+                line: n + 1,
+                column: line_text[..start].chars().count() + 1,
+            });
+        }
+    }
+
+    tokens
+}
+
+// a decimal, 0x hex or 0b binary number, possibly negative
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
@@ -0,0 +1,201 @@
+use crate::opcode::{self, OPCODES};
+use crate::{EmulatorError, Mode};
+
+use std::mem::discriminant;
+
+// Every instruction across Chip-8, SUPER-CHIP and XO-CHIP, with its operands
+// pulled out of the instruction word. x and y are register numbers, n is a
+// nibble, kk a byte and addresses are 12 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    // 00E0 - clear the display
+    Cls,
+    // 00EE - return from a subroutine
+    Ret,
+    // 00Cn - scroll the display down n pixels (SUPER-CHIP)
+    Scd(u8),
+    // 00Dn - scroll the display up n pixels (XO-CHIP)
+    Scu(u8),
+    // 00FB - scroll the display right 4 pixels (SUPER-CHIP)
+    Scr,
+    // 00FC - scroll the display left 4 pixels (SUPER-CHIP)
+    Scl,
+    // 00FD - stop the interpreter (SUPER-CHIP)
+    Exit,
+    // 00FE - switch to the 64x32 lo-res screen (SUPER-CHIP)
+    Low,
+    // 00FF - switch to the 128x64 hi-res screen (SUPER-CHIP)
+    High,
+    // 1nnn - jump to nnn
+    Jp(u16),
+    // 2nnn - call the subroutine at nnn
+    Call(u16),
+    // 3xkk - skip the next instruction if Vx == kk
+    SeByte { x: u8, kk: u8 },
+    // 4xkk - skip the next instruction if Vx != kk
+    SneByte { x: u8, kk: u8 },
+    // 5xy0 - skip the next instruction if Vx == Vy
+    Se { x: u8, y: u8 },
+    // 5xy2 - store Vx through Vy at I (XO-CHIP)
+    Save { x: u8, y: u8 },
+    // 5xy3 - load Vx through Vy from I (XO-CHIP)
+    Load { x: u8, y: u8 },
+    // 6xkk - Vx = kk
+    LdByte { x: u8, kk: u8 },
+    // 7xkk - Vx = Vx + kk
+    AddByte { x: u8, kk: u8 },
+    // 8xy0 - Vx = Vy
+    Ld { x: u8, y: u8 },
+    // 8xy1 - Vx = Vx OR Vy
+    Or { x: u8, y: u8 },
+    // 8xy2 - Vx = Vx AND Vy
+    And { x: u8, y: u8 },
+    // 8xy3 - Vx = Vx XOR Vy
+    Xor { x: u8, y: u8 },
+    // 8xy4 - Vx = Vx + Vy, VF = carry
+    Add { x: u8, y: u8 },
+    // 8xy5 - Vx = Vx - Vy, VF = NOT borrow
+    Sub { x: u8, y: u8 },
+    // 8xy6 - Vx = Vy >> 1 (or Vx >> 1), VF = the bit shifted out
+    Shr { x: u8, y: u8 },
+    // 8xy7 - Vx = Vy - Vx, VF = NOT borrow
+    Subn { x: u8, y: u8 },
+    // 8xyE - Vx = Vy << 1 (or Vx << 1), VF = the bit shifted out
+    Shl { x: u8, y: u8 },
+    // 9xy0 - skip the next instruction if Vx != Vy
+    Sne { x: u8, y: u8 },
+    // Annn - I = nnn
+    LdI(u16),
+    // Bnnn - jump to nnn + V0
+    JpV0(u16),
+    // Cxkk - Vx = random byte AND kk
+    Rnd { x: u8, kk: u8 },
+    // Dxyn - draw the n byte sprite at I at (Vx, Vy), VF = collision
+    Drw { x: u8, y: u8, n: u8 },
+    // Ex9E - skip the next instruction if the key in Vx is pressed
+    Skp(u8),
+    // ExA1 - skip the next instruction if the key in Vx isn't pressed
+    Sknp(u8),
+    // F000 nnnn - I = nnnn, the address is the word after (XO-CHIP)
+    LdILong,
+    // Fn01 - draw to the bitplanes n (XO-CHIP)
+    Plane(u8),
+    // F002 - load the audio pattern buffer from I (XO-CHIP)
+    Audio,
+    // Fx07 - Vx = delay timer
+    LdVxDt(u8),
+    // Fx0A - wait for a key and store it in Vx
+    LdVxK(u8),
+    // Fx15 - delay timer = Vx
+    LdDtVx(u8),
+    // Fx18 - sound timer = Vx
+    LdStVx(u8),
+    // Fx1E - I = I + Vx
+    AddI(u8),
+    // Fx29 - I = the font sprite for digit Vx
+    LdF(u8),
+    // Fx30 - I = the large font sprite for digit Vx (SUPER-CHIP)
+    LdHf(u8),
+    // Fx33 - store the BCD of Vx at I, I+1 and I+2
+    LdB(u8),
+    // Fx3A - audio pitch = Vx (XO-CHIP)
+    LdPitch(u8),
+    // Fx55 - store V0 through Vx at I
+    LdMemVx(u8),
+    // Fx65 - load V0 through Vx from I
+    LdVxMem(u8),
+    // Fx75 - store V0 through Vx in the RPL user flags (SUPER-CHIP)
+    LdRVx(u8),
+    // Fx85 - load V0 through Vx from the RPL user flags (SUPER-CHIP)
+    LdVxR(u8),
+}
+
+use Op::*;
+
+impl Op {
+    // the instruction that word is, an InvalidOpcode error if it isn't one. The
+    // error's pc is 0, the word wasn't read from anywhere.
+    pub fn decode(word: u16) -> Result<Op, EmulatorError> {
+        match opcode::lookup(word) {
+            Some(opcode) => Ok(opcode.decode(word)),
+            None => Err(EmulatorError::InvalidOpcode {
+                pc: 0,
+                opcode: word,
+            }),
+        }
+    }
+
+    // the instruction word, decode gives back the same op. Operands are masked
+    // to the size of their field.
+    pub fn encode(&self) -> u16 {
+        // the entry for an op is the one whose pattern decodes to the same kind
+        // of op, every op has one
+        let opcode = OPCODES
+            .iter()
+            .find(|opcode| discriminant(&opcode.decode(opcode.pattern)) == discriminant(self))
+            .expect("every op is in the opcode table");
+        opcode.encode(&self.operands())
+    }
+
+    // the operands stored in the instruction word, in the order the opcode
+    // table lists them
+    fn operands(&self) -> Vec<u16> {
+        match *self {
+            Cls | Ret | Scr | Scl | Exit | Low | High | LdILong | Audio => vec![],
+            Scd(n) | Scu(n) => vec![n as u16],
+            Jp(addr) | Call(addr) | LdI(addr) | JpV0(addr) => vec![addr],
+            SeByte { x, kk }
+            | SneByte { x, kk }
+            | LdByte { x, kk }
+            | AddByte { x, kk }
+            | Rnd { x, kk } => vec![x as u16, kk as u16],
+            Se { x, y }
+            | Save { x, y }
+            | Load { x, y }
+            | Ld { x, y }
+            | Or { x, y }
+            | And { x, y }
+            | Xor { x, y }
+            | Add { x, y }
+            | Sub { x, y }
+            | Shr { x, y }
+            | Subn { x, y }
+            | Shl { x, y }
+            | Sne { x, y } => vec![x as u16, y as u16],
+            Drw { x, y, n } => vec![x as u16, y as u16, n as u16],
+            Skp(x) | Sknp(x) | Plane(x) | LdVxDt(x) | LdVxK(x) | LdDtVx(x) | LdStVx(x)
+            | AddI(x) | LdF(x) | LdHf(x) | LdB(x) | LdPitch(x) | LdMemVx(x) | LdVxMem(x)
+            | LdRVx(x) | LdVxR(x) => vec![x as u16],
+        }
+    }
+
+    // size of the instruction in bytes, F000 nnnn is the only one with a
+    // second word
+    pub fn size(&self) -> u16 {
+        match self {
+            LdILong => 4,
+            _ => 2,
+        }
+    }
+
+    // the instruction changes the screen
+    pub fn draws(&self) -> bool {
+        matches!(
+            self,
+            Cls | Scd(_) | Scu(_) | Scr | Scl | Low | High | Drw { .. }
+        )
+    }
+
+    // the first dialect to have the instruction
+    pub fn mode(&self) -> Mode {
+        match self {
+            Scu(_) | Save { .. } | Load { .. } | LdILong | Plane(_) | Audio | LdPitch(_) => {
+                Mode::XoChip
+            }
+            Scd(_) | Scr | Scl | Exit | Low | High | LdHf(_) | LdRVx(_) | LdVxR(_) => {
+                Mode::SuperChip
+            }
+            _ => Mode::Chip8,
+        }
+    }
+}
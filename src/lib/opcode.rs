@@ -0,0 +1,178 @@
+use crate::Op::{self, *};
+
+// How every instruction the interpreter knows is written down, across Chip-8,
+// SUPER-CHIP and XO-CHIP. A word is the instruction of the first entry where
+// word & mask == pattern, the rest of its bits are the operands. The
+// disassembler, assembler and Octo compiler go through this table, and
+// Op::decode and Op::encode do too, so there's one place the words are spelled
+// out.
+pub(crate) struct Opcode {
+    pub(crate) pattern: u16,
+    pub(crate) mask: u16,
+    // Cowgod's mnemonic, and the operands in the order Cowgod writes them
+    pub(crate) mnemonic: &'static str,
+    pub(crate) operands: &'static [Operand],
+    // the same instruction as an Octo statement, operands are filled in for
+    // {x}, {y}, {n}, {kk}, {nnn}, {long} and {plane}
+    pub(crate) octo: &'static str,
+    // the Op, given the values of the operands stored in the word in the order
+    // they're listed
+    op: fn(&[u16; 3]) -> Op,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Operand {
+    // register Vx, the low nibble of the high byte
+    Vx,
+    // register Vy, the high nibble of the low byte
+    Vy,
+    // the low nibble
+    Nibble,
+    // the low byte
+    Byte,
+    // the low 12 bits
+    Addr,
+    // the 16 bit address in the word after F000
+    Long,
+    // a number stored where Vx usually is, the bitplanes selected by Fn01
+    Plane,
+    // a keyword that is always the same, e.g. I, DT or [I]
+    Fixed(&'static str),
+}
+
+use Operand::{Addr, Byte, Fixed, Long, Nibble, Plane, Vx, Vy};
+
+impl Operand {
+    // where the operand is kept in the instruction word as (shift, mask), None
+    // for operands that aren't stored in the word
+    pub(crate) fn field(&self) -> Option<(u16, u16)> {
+        match self {
+            Vx | Plane => Some((8, 0xF)),
+            Vy => Some((4, 0xF)),
+            Nibble => Some((0, 0xF)),
+            Byte => Some((0, 0xFF)),
+            Addr => Some((0, 0xFFF)),
+            Long | Fixed(_) => None,
+        }
+    }
+
+    // the value of the operand in word
+    pub(crate) fn extract(&self, word: u16) -> u16 {
+        match self.field() {
+            Some((shift, mask)) => (word >> shift) & mask,
+            None => 0,
+        }
+    }
+
+    // word with the operand set to value, which must fit the field
+    pub(crate) fn insert(&self, word: u16, value: u16) -> u16 {
+        match self.field() {
+            Some((shift, mask)) => word | (value & mask) << shift,
+            None => word,
+        }
+    }
+}
+
+#[rustfmt::skip]
+pub(crate) const OPCODES: &[Opcode] = &[
+    op(0x00E0, 0xFFFF, "CLS", &[], "clear", |_| Cls),
+    op(0x00EE, 0xFFFF, "RET", &[], "return", |_| Ret),
+    op(0x00C0, 0xFFF0, "SCD", &[Nibble], "scroll-down {n}", |o| Scd(o[0] as u8)),
+    op(0x00D0, 0xFFF0, "SCU", &[Nibble], "scroll-up {n}", |o| Scu(o[0] as u8)),
+    op(0x00FB, 0xFFFF, "SCR", &[], "scroll-right", |_| Scr),
+    op(0x00FC, 0xFFFF, "SCL", &[], "scroll-left", |_| Scl),
+    op(0x00FD, 0xFFFF, "EXIT", &[], "exit", |_| Exit),
+    op(0x00FE, 0xFFFF, "LOW", &[], "lores", |_| Low),
+    op(0x00FF, 0xFFFF, "HIGH", &[], "hires", |_| High),
+    op(0x1000, 0xF000, "JP", &[Addr], "jump {nnn}", |o| Jp(o[0])),
+    op(0x2000, 0xF000, "CALL", &[Addr], ":call {nnn}", |o| Call(o[0])),
+    op(0x3000, 0xF000, "SE", &[Vx, Byte], "if {x} != {kk} then", |o| SeByte { x: o[0] as u8, kk: o[1] as u8 }),
+    op(0x4000, 0xF000, "SNE", &[Vx, Byte], "if {x} == {kk} then", |o| SneByte { x: o[0] as u8, kk: o[1] as u8 }),
+    op(0x5000, 0xF00F, "SE", &[Vx, Vy], "if {x} != {y} then", |o| Se { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x5002, 0xF00F, "SAVE", &[Vx, Vy], "save {x} - {y}", |o| Save { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x5003, 0xF00F, "LOAD", &[Vx, Vy], "load {x} - {y}", |o| Load { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x6000, 0xF000, "LD", &[Vx, Byte], "{x} := {kk}", |o| LdByte { x: o[0] as u8, kk: o[1] as u8 }),
+    op(0x7000, 0xF000, "ADD", &[Vx, Byte], "{x} += {kk}", |o| AddByte { x: o[0] as u8, kk: o[1] as u8 }),
+    op(0x8000, 0xF00F, "LD", &[Vx, Vy], "{x} := {y}", |o| Ld { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x8001, 0xF00F, "OR", &[Vx, Vy], "{x} |= {y}", |o| Or { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x8002, 0xF00F, "AND", &[Vx, Vy], "{x} &= {y}", |o| And { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x8003, 0xF00F, "XOR", &[Vx, Vy], "{x} ^= {y}", |o| Xor { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x8004, 0xF00F, "ADD", &[Vx, Vy], "{x} += {y}", |o| Add { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x8005, 0xF00F, "SUB", &[Vx, Vy], "{x} -= {y}", |o| Sub { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x8006, 0xF00F, "SHR", &[Vx, Vy], "{x} >>= {y}", |o| Shr { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x8007, 0xF00F, "SUBN", &[Vx, Vy], "{x} =- {y}", |o| Subn { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x800E, 0xF00F, "SHL", &[Vx, Vy], "{x} <<= {y}", |o| Shl { x: o[0] as u8, y: o[1] as u8 }),
+    op(0x9000, 0xF00F, "SNE", &[Vx, Vy], "if {x} == {y} then", |o| Sne { x: o[0] as u8, y: o[1] as u8 }),
+    op(0xA000, 0xF000, "LD", &[Fixed("I"), Addr], "i := {nnn}", |o| LdI(o[0])),
+    op(0xB000, 0xF000, "JP", &[Fixed("V0"), Addr], "jump0 {nnn}", |o| JpV0(o[0])),
+    op(0xC000, 0xF000, "RND", &[Vx, Byte], "{x} := random {kk}", |o| Rnd { x: o[0] as u8, kk: o[1] as u8 }),
+    op(0xD000, 0xF000, "DRW", &[Vx, Vy, Nibble], "sprite {x} {y} {n}", |o| Drw { x: o[0] as u8, y: o[1] as u8, n: o[2] as u8 }),
+    op(0xE09E, 0xF0FF, "SKP", &[Vx], "if {x} -key then", |o| Skp(o[0] as u8)),
+    op(0xE0A1, 0xF0FF, "SKNP", &[Vx], "if {x} key then", |o| Sknp(o[0] as u8)),
+    op(0xF000, 0xFFFF, "LD", &[Fixed("I"), Long], "i := long {long}", |_| LdILong),
+    op(0xF001, 0xF0FF, "PLANE", &[Plane], "plane {plane}", |o| Op::Plane(o[0] as u8)),
+    op(0xF002, 0xFFFF, "AUDIO", &[], "audio", |_| Audio),
+    op(0xF007, 0xF0FF, "LD", &[Vx, Fixed("DT")], "{x} := delay", |o| LdVxDt(o[0] as u8)),
+    op(0xF00A, 0xF0FF, "LD", &[Vx, Fixed("K")], "{x} := key", |o| LdVxK(o[0] as u8)),
+    op(0xF015, 0xF0FF, "LD", &[Fixed("DT"), Vx], "delay := {x}", |o| LdDtVx(o[0] as u8)),
+    op(0xF018, 0xF0FF, "LD", &[Fixed("ST"), Vx], "buzzer := {x}", |o| LdStVx(o[0] as u8)),
+    op(0xF01E, 0xF0FF, "ADD", &[Fixed("I"), Vx], "i += {x}", |o| AddI(o[0] as u8)),
+    op(0xF029, 0xF0FF, "LD", &[Fixed("F"), Vx], "i := hex {x}", |o| LdF(o[0] as u8)),
+    op(0xF030, 0xF0FF, "LD", &[Fixed("HF"), Vx], "i := bighex {x}", |o| LdHf(o[0] as u8)),
+    op(0xF033, 0xF0FF, "LD", &[Fixed("B"), Vx], "bcd {x}", |o| LdB(o[0] as u8)),
+    op(0xF03A, 0xF0FF, "LD", &[Fixed("PITCH"), Vx], "pitch := {x}", |o| LdPitch(o[0] as u8)),
+    op(0xF055, 0xF0FF, "LD", &[Fixed("[I]"), Vx], "save {x}", |o| LdMemVx(o[0] as u8)),
+    op(0xF065, 0xF0FF, "LD", &[Vx, Fixed("[I]")], "load {x}", |o| LdVxMem(o[0] as u8)),
+    op(0xF075, 0xF0FF, "LD", &[Fixed("R"), Vx], "saveflags {x}", |o| LdRVx(o[0] as u8)),
+    op(0xF085, 0xF0FF, "LD", &[Vx, Fixed("R")], "loadflags {x}", |o| LdVxR(o[0] as u8)),
+];
+
+const fn op(
+    pattern: u16,
+    mask: u16,
+    mnemonic: &'static str,
+    operands: &'static [Operand],
+    octo: &'static str,
+    op: fn(&[u16; 3]) -> Op,
+) -> Opcode {
+    Opcode {
+        pattern,
+        mask,
+        mnemonic,
+        operands,
+        octo,
+        op,
+    }
+}
+
+impl Opcode {
+    // the operands kept in the instruction word, in the order they're listed
+    fn stored(&self) -> impl Iterator<Item = &Operand> {
+        self.operands
+            .iter()
+            .filter(|operand| operand.field().is_some())
+    }
+
+    // the op word is, word must be one of this opcode's
+    pub(crate) fn decode(&self, word: u16) -> Op {
+        let mut values = [0; 3];
+        for (value, operand) in values.iter_mut().zip(self.stored()) {
+            *value = operand.extract(word);
+        }
+        (self.op)(&values)
+    }
+
+    // the word with the stored operands set to values
+    pub(crate) fn encode(&self, values: &[u16]) -> u16 {
+        self.stored()
+            .zip(values)
+            .fold(self.pattern, |word, (operand, value)| {
+                operand.insert(word, *value)
+            })
+    }
+}
+
+// the table entry for word, None if it isn't an instruction
+pub(crate) fn lookup(word: u16) -> Option<&'static Opcode> {
+    OPCODES.iter().find(|op| word & op.mask == op.pattern)
+}
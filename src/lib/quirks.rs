@@ -0,0 +1,78 @@
+// Chip-8 interpreters don't agree on what some instructions do. Programs were
+// written against whichever interpreter their author had, so each of these
+// flags picks one reading of an ambiguous instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8xy6/8xyE shift Vx in place and ignore Vy, instead of storing Vy shifted
+    // by one in Vx
+    pub shift: bool,
+    // Fx55/Fx65 leave I untouched, instead of leaving it at I + x + 1
+    pub load_store: bool,
+    // Bnnn jumps to nnn + Vx, where x is the highest nibble of nnn, instead of
+    // nnn + V0
+    pub jump: bool,
+    // 8xy1/8xy2/8xy3 reset VF to 0
+    pub vf_reset: bool,
+    // Dxyn cuts sprites off at the edges of the screen instead of wrapping them
+    // around to the other side
+    pub clipping: bool,
+    // Dxyn waits for the start of the next 60Hz frame before drawing
+    pub display_wait: bool,
+    // Fx0A finishes as soon as a key is pressed, instead of waiting for the key
+    // to be released like the COSMAC VIP did
+    pub key_press: bool,
+}
+
+impl Quirks {
+    // the original interpreter on the RCA COSMAC VIP
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: false,
+            jump: false,
+            vf_reset: true,
+            clipping: true,
+            display_wait: true,
+            key_press: false,
+        }
+    }
+
+    // CHIP-48 on the HP-48 calculators. SUPER-CHIP 1.1 was built on it and
+    // none of these flags tell the two apart, so this is superchip() under the
+    // name programs of the time were written for.
+    pub fn chip48() -> Quirks {
+        Quirks::superchip()
+    }
+
+    // SUPER-CHIP 1.1, which inherited its behaviour from CHIP-48
+    pub fn superchip() -> Quirks {
+        Quirks {
+            shift: true,
+            load_store: true,
+            jump: true,
+            vf_reset: false,
+            clipping: true,
+            display_wait: false,
+            key_press: false,
+        }
+    }
+
+    // Octo, and the XO-CHIP programs written for it
+    pub fn octo() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: false,
+            jump: false,
+            vf_reset: false,
+            clipping: false,
+            display_wait: false,
+            key_press: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::cosmac_vip()
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+// Rewind history. The newest snapshot of the machine is kept whole and every
+// older one is stored as the difference from the snapshot taken after it, so
+// going back in time is undoing one difference after another. Consecutive
+// snapshots are almost identical, so the differences are run-length encoded
+// XORs that are usually only a few bytes long.
+pub(crate) struct Rewind {
+    // frames between snapshots
+    interval: u32,
+    // most snapshots kept, the oldest are dropped first
+    capacity: usize,
+    // the frame and contents of the newest snapshot
+    latest: Option<(u64, Vec<u8>)>,
+    // differences back to older snapshots, oldest first
+    deltas: VecDeque<Delta>,
+}
+
+// the changes that turn a snapshot back into the one taken before it
+struct Delta {
+    // the frame the older snapshot was taken at
+    frame: u64,
+    changes: Changes,
+}
+
+enum Changes {
+    // run-length encoded XOR of the two snapshots
+    Xor(Vec<u8>),
+    // the whole older snapshot, when the two aren't the same length
+    Full(Vec<u8>),
+}
+
+impl Rewind {
+    pub(crate) fn new(interval: u32, capacity: usize) -> Rewind {
+        Rewind {
+            interval: interval.max(1),
+            capacity,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // true when a snapshot should be taken at frame
+    pub(crate) fn due(&self, frame: u64) -> bool {
+        self.capacity > 0 && (self.latest.is_none() || frame.is_multiple_of(self.interval as u64))
+    }
+
+    // record the snapshot of the machine at frame
+    pub(crate) fn push(&mut self, frame: u64, snapshot: Vec<u8>) {
+        if let Some((latest_frame, latest)) = self.latest.take() {
+            if latest_frame < frame {
+                let changes = match latest.len() == snapshot.len() {
+                    true => Changes::Xor(encode(&latest, &snapshot)),
+                    false => Changes::Full(latest),
+                };
+
+                self.deltas.push_back(Delta {
+                    frame: latest_frame,
+                    changes,
+                });
+            }
+        }
+
+        self.latest = Some((frame, snapshot));
+
+        while self.deltas.len() + 1 > self.capacity.max(1) {
+            self.deltas.pop_front();
+        }
+    }
+
+    // step back to the newest snapshot taken at or before frame, or the oldest
+    // one if the history doesn't go back that far. Returns the frame and the
+    // snapshot, which stays in the history as the newest one.
+    pub(crate) fn rewind(&mut self, frame: u64) -> Option<(u64, &[u8])> {
+        let (latest_frame, latest) = self.latest.as_mut()?;
+
+        while *latest_frame > frame {
+            let delta = match self.deltas.pop_back() {
+                Some(delta) => delta,
+                None => break,
+            };
+
+            match delta.changes {
+                Changes::Xor(changes) => apply(latest, &changes),
+                Changes::Full(older) => *latest = older,
+            }
+            *latest_frame = delta.frame;
+        }
+
+        Some((*latest_frame, latest))
+    }
+
+    // forget the whole history
+    pub(crate) fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+}
+
+// Encode the XOR of two snapshots of the same length as a list of runs: the
+// number of unchanged bytes to skip, then the number of changed bytes followed
+// by the changes themselves. Both counts are LEB128 varints.
+fn encode(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < older.len() {
+        let skip = older[pos..]
+            .iter()
+            .zip(&newer[pos..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        pos += skip;
+
+        let len = older[pos..]
+            .iter()
+            .zip(&newer[pos..])
+            .take_while(|(a, b)| a != b)
+            .count();
+
+        write_varint(&mut out, skip);
+        write_varint(&mut out, len);
+        out.extend(
+            older[pos..pos + len]
+                .iter()
+                .zip(&newer[pos..pos + len])
+                .map(|(a, b)| a ^ b),
+        );
+        pos += len;
+    }
+
+    out
+}
+
+// undo the changes made by encode, turning the newer snapshot into the older
+fn apply(snapshot: &mut [u8], changes: &[u8]) {
+    let mut pos = 0;
+    let mut rest = changes;
+
+    while !rest.is_empty() {
+        pos += read_varint(&mut rest);
+        let len = read_varint(&mut rest);
+
+        for (byte, change) in snapshot[pos..pos + len].iter_mut().zip(&rest[..len]) {
+            *byte ^= change;
+        }
+
+        pos += len;
+        rest = &rest[len..];
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push((val as u8 & 0x7F) | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn read_varint(data: &mut &[u8]) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+
+    while let Some((byte, rest)) = data.split_first() {
+        *data = rest;
+        val |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    val
+}
@@ -0,0 +1,115 @@
+// Source of the random numbers Cxkk uses. The generator is part of the machine,
+// its state is saved and restored along with everything else, so a run can be
+// replayed exactly from the same seed.
+pub trait Rng {
+    // the next random byte
+    fn byte(&mut self) -> u8;
+
+    // the whole state of the generator, enough for set_state to carry on with
+    // the same sequence
+    fn state(&self) -> u64;
+
+    fn set_state(&mut self, state: u64);
+}
+
+// The random number generators the emulator comes with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RngKind {
+    // a fast, good quality xorshift generator
+    Xorshift,
+    // the routine the COSMAC VIP interpreter used, see VipRng
+    CosmacVip,
+}
+
+// xorshift64* by Sebastiano Vigna
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Xorshift {
+        let mut rng = Xorshift { state: 0 };
+        rng.set_state(seed);
+        rng
+    }
+}
+
+impl Rng for Xorshift {
+    fn byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    // xorshift gets stuck at 0, so 0 is swapped for another seed
+    fn set_state(&mut self, state: u64) {
+        self.state = match state {
+            0 => 0x9E37_79B9_7F4A_7C15,
+            _ => state,
+        };
+    }
+}
+
+// The COSMAC VIP interpreter kept a 16 bit seed in its R9 register. For every
+// Cxkk it incremented R9 and added the byte of the interpreter at 0x100 plus the
+// low byte to the high byte. The sum rotated right through the carry was added
+// to the sum again, and that became the high byte and the random number. The
+// numbers are as streaky as the original's were.
+pub struct VipRng {
+    r9: u16,
+}
+
+// The second page of the VIP interpreter, 0x100-0x1FF, which the Cxkk routine at
+// 0x1D9 runs from and reads its bytes out of
+const VIP_INTERPRETER_PAGE: [u8; 256] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x45, 0xA3, 0x98, 0x56, 0xD4, 0xF8, 0x81, 0xBC, 0xF8, 0x95, 0xAC,
+    0x22, 0xDC, 0x12, 0x56, 0xD4, 0x06, 0xB8, 0xD4, 0x06, 0xA8, 0xD4, 0x64, 0x0A, 0x01, 0xE6, 0x8A,
+    0xF4, 0xAA, 0x3B, 0x28, 0x9A, 0xFC, 0x01, 0xBA, 0xD4, 0xF8, 0x81, 0xBA, 0x06, 0xFA, 0x0F, 0xAA,
+    0x0A, 0xAA, 0xD4, 0xE6, 0x06, 0xBF, 0x93, 0xBE, 0xF8, 0x1B, 0xAE, 0x2A, 0x1A, 0xF8, 0x00, 0x5A,
+    0x0E, 0xF5, 0x3B, 0x4B, 0x56, 0x0A, 0xFC, 0x01, 0x5A, 0x30, 0x40, 0x4E, 0xF6, 0x3B, 0x3C, 0x9F,
+    0x56, 0x2A, 0x2A, 0xD4, 0x00, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x07, 0x5A, 0x87, 0xF3, 0x17,
+    0x1A, 0x3A, 0x5B, 0x12, 0xD4, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x0A, 0x57, 0x87, 0xF3, 0x17,
+    0x1A, 0x3A, 0x6B, 0x12, 0xD4, 0x15, 0x85, 0x22, 0x73, 0x95, 0x52, 0x25, 0x45, 0xA5, 0x86, 0xFA,
+    0x0F, 0xB5, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x8A, 0x15, 0x15, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x88,
+    0xD4, 0x45, 0x07, 0x30, 0x8C, 0x45, 0x07, 0x30, 0x84, 0xE6, 0x62, 0x26, 0x45, 0xA3, 0x36, 0x88,
+    0xD4, 0x3E, 0x88, 0xD4, 0xF8, 0xF0, 0xA7, 0xE7, 0x45, 0xF4, 0xA5, 0x86, 0xFA, 0x0F, 0x3B, 0xB2,
+    0xFC, 0x01, 0xB5, 0xD4, 0x45, 0x56, 0xD4, 0x45, 0xE6, 0xF4, 0x56, 0xD4, 0x45, 0xFA, 0x0F, 0x3A,
+    0xC4, 0x07, 0x56, 0xD4, 0xAF, 0x22, 0xF8, 0xD3, 0x73, 0x8F, 0xF9, 0xF0, 0x52, 0xE6, 0x07, 0xD2,
+    0x56, 0xF8, 0xFF, 0xA6, 0xF8, 0x00, 0x7E, 0x56, 0xD4, 0x19, 0x89, 0xAE, 0x93, 0xBE, 0x99, 0xEE,
+    0xF4, 0x56, 0x76, 0xE6, 0xF4, 0xB9, 0x56, 0x45, 0xF2, 0x56, 0xD4, 0x45, 0xAA, 0x86, 0xFA, 0x0F,
+    0xBA, 0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0, 0x00, 0x4B,
+];
+
+impl VipRng {
+    // seed is the starting value of R9, only the low 16 bits are used
+    pub fn new(seed: u64) -> VipRng {
+        VipRng { r9: seed as u16 }
+    }
+}
+
+impl Rng for VipRng {
+    fn byte(&mut self) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+        let [hi, lo] = self.r9.to_be_bytes();
+
+        // ADD, SHRC and ADD again, as the 1802 code does them
+        let (sum, carry) = hi.overflowing_add(VIP_INTERPRETER_PAGE[lo as usize]);
+        let hi = ((sum >> 1) | ((carry as u8) << 7)).wrapping_add(sum);
+
+        self.r9 = u16::from_be_bytes([hi, lo]);
+        hi
+    }
+
+    fn state(&self) -> u64 {
+        self.r9 as u64
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.r9 = state as u16;
+    }
+}
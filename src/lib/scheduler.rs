@@ -0,0 +1,53 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// length of one 60Hz frame
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// most frames run back to back to catch up after falling behind. Anything
+// later than that is dropped instead of running the emulator flat out.
+const MAX_CATCH_UP: u32 = 4;
+
+// Paces emulation against the wall clock. A frame is due every 1/60th of a
+// second no matter how long emulating the previous one took.
+pub(crate) struct Scheduler {
+    // when the next frame is due
+    next: Instant,
+}
+
+impl Scheduler {
+    // the first frame is due straight away
+    pub(crate) fn start() -> Scheduler {
+        Scheduler {
+            next: Instant::now(),
+        }
+    }
+
+    // number of frames that are due to run now
+    pub(crate) fn frames_due(&mut self) -> u32 {
+        let now = Instant::now();
+        let mut due = 0;
+
+        while self.next <= now && due < MAX_CATCH_UP {
+            self.next += FRAME;
+            due += 1;
+        }
+
+        // too far behind, drop the frames we couldn't catch up on
+        if self.next <= now {
+            self.next = now + FRAME;
+        }
+
+        due
+    }
+
+    // sleep until the next frame is due, whatever time is left after emulating
+    // and drawing the last one
+    pub(crate) fn wait(&self) {
+        let now = Instant::now();
+
+        if self.next > now {
+            thread::sleep(self.next - now);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::EmulatorError;
+
+// Save states are a header followed by a list of chunks. Movies are stored the
+// same way under their own magic and version.
+//
+//   magic     4 bytes  "C8ST"
+//   version   u16      STATE_VERSION
+//   length    u32      length of the chunks in bytes
+//   checksum  u32      CRC-32 of the chunks
+//   chunks
+//
+// Every chunk is a 4 byte tag naming what it holds, a u32 length and that many
+// bytes of data. Readers skip chunks they don't know about, so chunks can be
+// added without breaking older states. All numbers are big-endian.
+const MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u16 = 1;
+const HEADER_LEN: usize = 14;
+
+// builds the chunks of a save state
+pub(crate) struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> StateWriter {
+        StateWriter { buf: Vec::new() }
+    }
+
+    // write a chunk tagged tag holding whatever f writes
+    pub(crate) fn chunk<F: FnOnce(&mut StateWriter)>(&mut self, tag: &[u8; 4], f: F) {
+        self.buf.extend_from_slice(tag);
+        let len_at = self.buf.len();
+        self.u32(0);
+
+        f(self);
+
+        let len = (self.buf.len() - len_at - 4) as u32;
+        self.buf[len_at..len_at + 4].copy_from_slice(&len.to_be_bytes());
+    }
+
+    pub(crate) fn u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub(crate) fn bool(&mut self, val: bool) {
+        self.buf.push(val as u8);
+    }
+
+    pub(crate) fn u16(&mut self, val: u16) {
+        self.buf.extend_from_slice(&val.to_be_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, val: u32) {
+        self.buf.extend_from_slice(&val.to_be_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, val: u64) {
+        self.buf.extend_from_slice(&val.to_be_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, val: &[u8]) {
+        self.buf.extend_from_slice(val);
+    }
+
+    // the chunks written so far, without a header
+    pub(crate) fn into_chunks(self) -> Vec<u8> {
+        self.buf
+    }
+
+    // the finished save state, header and all
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.finish_as(MAGIC, STATE_VERSION)
+    }
+
+    // the finished file with a header naming it as magic, version
+    pub(crate) fn finish_as(self, magic: &[u8; 4], version: u16) -> Vec<u8> {
+        let mut state = Vec::with_capacity(HEADER_LEN + self.buf.len());
+        state.extend_from_slice(magic);
+        state.extend_from_slice(&version.to_be_bytes());
+        state.extend_from_slice(&(self.buf.len() as u32).to_be_bytes());
+        state.extend_from_slice(&crc32(&self.buf).to_be_bytes());
+        state.extend_from_slice(&self.buf);
+        state
+    }
+}
+
+// a save state with a checked header
+pub(crate) struct State<'a> {
+    chunks: &'a [u8],
+}
+
+impl<'a> State<'a> {
+    // check the magic, version, length and checksum of a save state
+    pub(crate) fn parse(state: &'a [u8]) -> Result<State<'a>, EmulatorError> {
+        State::parse_as(state, MAGIC, STATE_VERSION)
+    }
+
+    // check the header of a file written by StateWriter::finish_as
+    pub(crate) fn parse_as(
+        state: &'a [u8],
+        magic: &[u8; 4],
+        version: u16,
+    ) -> Result<State<'a>, EmulatorError> {
+        if state.len() < HEADER_LEN || &state[..4] != magic {
+            return Err(invalid("unrecognised file type".to_string()));
+        }
+
+        let found = u16::from_be_bytes([state[4], state[5]]);
+        if found != version {
+            return Err(invalid(format!("unsupported version {}", found)));
+        }
+
+        let len = u32::from_be_bytes([state[6], state[7], state[8], state[9]]) as usize;
+        let checksum = u32::from_be_bytes([state[10], state[11], state[12], state[13]]);
+        let chunks = &state[HEADER_LEN..];
+
+        if chunks.len() != len || crc32(chunks) != checksum {
+            return Err(invalid("checksum mismatch".to_string()));
+        }
+
+        Ok(State { chunks })
+    }
+
+    // chunks made by StateWriter::into_chunks, which have no header to check
+    pub(crate) fn from_chunks(chunks: &'a [u8]) -> State<'a> {
+        State { chunks }
+    }
+
+    // find the chunk tagged tag
+    pub(crate) fn chunk(&self, tag: &[u8; 4]) -> Result<StateReader<'a>, EmulatorError> {
+        let mut rest = self.chunks;
+
+        while rest.len() >= 8 {
+            let len = u32::from_be_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+            if rest.len() < 8 + len {
+                break;
+            }
+
+            if &rest[..4] == tag {
+                return Ok(StateReader {
+                    tag: &rest[..4],
+                    data: &rest[8..8 + len],
+                });
+            }
+            rest = &rest[8 + len..];
+        }
+
+        Err(invalid(format!(
+            "missing {} chunk",
+            String::from_utf8_lossy(tag)
+        )))
+    }
+}
+
+// reads the fields of one chunk of a save state
+pub(crate) struct StateReader<'a> {
+    tag: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], EmulatorError> {
+        if self.data.len() < n {
+            return Err(invalid(format!(
+                "{} chunk is too short",
+                String::from_utf8_lossy(self.tag)
+            )));
+        }
+
+        let (val, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(val)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, EmulatorError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, EmulatorError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, EmulatorError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, EmulatorError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, EmulatorError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(b))
+    }
+
+    // number of bytes left in the chunk
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    // fill val with the next val.len() bytes
+    pub(crate) fn bytes(&mut self, val: &mut [u8]) -> Result<(), EmulatorError> {
+        val.copy_from_slice(self.take(val.len())?);
+        Ok(())
+    }
+}
+
+pub(crate) fn invalid(reason: String) -> EmulatorError {
+    EmulatorError::InvalidState(reason)
+}
+
+// CRC-32 (the one zip and png use) of data
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
@@ -1,95 +1,796 @@
-use crate::display::{Display, Key};
-use crate::{Instruction, Memory, CPU};
+use crate::history::{Entry, History};
+use crate::movie::Movie;
+use crate::rewind::Rewind;
+use crate::scheduler::Scheduler;
+use crate::state::{crc32, State, StateWriter};
+use crate::video::{Command, Video};
+use crate::{
+    compile_octo_file, Access, Config, EmulatorError, Instruction, Keypad, Memory, MemoryWrite,
+    Registers, Rng, RngKind, Trace, TraceLine, VipRng, Xorshift, CPU,
+};
 
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
-use std::path::Path;
-use std::{thread, time};
+use std::path::{Path, PathBuf};
 
 pub(crate) struct Flags {
     pub(crate) draw: bool,
     pub(crate) clear: bool,
-    pub(crate) sound: bool,
-    pub(crate) key: Key,
+    pub(crate) keypad: Keypad,
+    // set at the start of every 60Hz frame, cleared by a draw that waits for it
+    pub(crate) vblank: bool,
+    // set by the SUPER-CHIP exit instruction
+    pub(crate) exit: bool,
+    // where Cxkk gets its random numbers
+    pub(crate) rng: Box<dyn Rng>,
 }
 
-pub struct System {
+// What happened while the system ran one or more instructions. When more than
+// one instruction ran each field is set if it happened for any of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Events {
+    // vram changed and the screen needs to be drawn
+    pub drew: bool,
+    // the screen was cleared
+    pub cleared: bool,
+    // the sound timer is running, the buzzer should be on
+    pub sound: bool,
+    // the cpu is blocked on Fx0A waiting for a key
+    pub waiting: bool,
+    // the program ran the SUPER-CHIP exit instruction
+    pub exited: bool,
+}
+
+// Why System::run returned. Saving and loading a state hand the result back
+// for the host to report, calling run again carries on running.
+#[derive(Debug)]
+pub enum Stopped {
+    // the user quit or the program exited
+    Quit,
+    // the user saved to a slot
+    SavedState(u8, Result<(), EmulatorError>),
+    // the user loaded from a slot
+    LoadedState(u8, Result<(), EmulatorError>),
+}
+
+impl Events {
+    // combine the events of a later run into these
+    fn merge(&mut self, other: Events) {
+        self.drew |= other.drew;
+        self.cleared |= other.cleared;
+        self.sound = other.sound;
+        self.waiting = other.waiting;
+        self.exited |= other.exited;
+    }
+}
+
+pub struct System<V: Video> {
     cpu: CPU,
     mem: Memory,
-    display: Display,
+    video: V,
     flags: Flags,
+    // the config the system was created with. Its speed is how many
+    // instructions run_frame executes per 60Hz frame.
+    config: Config,
+    // instructions per second left over from previous frames when the speed
+    // isn't a multiple of 60Hz
+    remainder: u32,
+    // the rom file, save state slots are stored next to it
+    rom: Option<PathBuf>,
+    // CRC-32 of the rom, movies are checked against it
+    rom_hash: u32,
+    // 60Hz frames run since the rom was loaded
+    frame: u64,
+    // instructions run since the rom was loaded
+    cycles: u64,
+    // instructions left in the frame a debugger is stepping through, 0
+    // between frames
+    left: u32,
+    // the reads and writes the last instruction made, recorded while a
+    // debugger watches memory or the history is kept
+    watching: bool,
+    accesses: Vec<Access>,
+    // undo log of the last instructions run, to step back through
+    history: History,
+    // snapshots of the machine to go back in time through
+    rewind: Rewind,
+    // the movie being recorded and the frame recording started at
+    recording: Option<(u64, Movie)>,
+    // the movie being played back and the frame playback started at
+    playback: Option<(u64, Movie)>,
+    // where every instruction run is written, while tracing
+    trace: Option<Trace>,
 }
 
-impl System {
-    pub fn create(display: Display) -> System {
-        let mem = Memory::allocate();
-        let cpu = CPU::init();
+// by default a snapshot is taken every other frame and five minutes of them
+// are kept
+const REWIND_INTERVAL: u32 = 2;
+const REWIND_CAPACITY: usize = 5 * 60 * 60 / REWIND_INTERVAL as usize;
+
+// run rewinds this many frames for every frame of real time the hotkey is held
+const REWIND_SPEED: u32 = 2;
+
+impl<V: Video> System<V> {
+    // config selects the instruction set and which interpreter's behaviour the
+    // cpu follows
+    pub fn create(video: V, config: Config) -> System<V> {
+        let mem = Memory::allocate(config.mode);
+        let cpu = CPU::init(config);
+        let rng: Box<dyn Rng> = match config.rng {
+            RngKind::Xorshift => Box::new(Xorshift::new(config.seed)),
+            RngKind::CosmacVip => Box::new(VipRng::new(config.seed)),
+        };
 
         System {
             cpu,
             mem,
-            display,
+            video,
             flags: Flags {
                 draw: false,
                 clear: false,
-                sound: false,
-                key: Key::NONE,
+                keypad: Keypad::new(),
+                vblank: true,
+                exit: false,
+                rng,
             },
+            config,
+            remainder: 0,
+            rom: None,
+            rom_hash: crc32(&[]),
+            frame: 0,
+            cycles: 0,
+            left: 0,
+            watching: false,
+            accesses: Vec::new(),
+            history: History::new(0),
+            rewind: Rewind::new(REWIND_INTERVAL, REWIND_CAPACITY),
+            recording: None,
+            playback: None,
+            trace: None,
+        }
+    }
+
+    // read the rom file from disk and load into memory. Files ending in .8o
+    // are Octo source, compiled for the system's mode first.
+    pub fn load_rom<P: AsRef<Path>>(&mut self, path: P) -> Result<(), EmulatorError> {
+        let mut data: Vec<u8> = Vec::new();
+        if path.as_ref().extension().is_some_and(|ext| ext == "8o") {
+            data = compile_octo_file(&path, self.config.mode)?.rom;
+        } else {
+            let mut f = File::open(&path)?;
+            f.read_to_end(&mut data)?;
+        }
+
+        self.load_rom_data(data)?;
+        self.rom = Some(path.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    // load a rom that is already in memory, e.g. one just assembled. Save
+    // state slots are kept in the current directory.
+    pub fn load_rom_data(&mut self, data: Vec<u8>) -> Result<(), EmulatorError> {
+        self.rom_hash = crc32(&data);
+        self.mem.write_rom_data(data)?;
+        self.rom = None;
+        self.frame = 0;
+        self.cycles = 0;
+        self.left = 0;
+        self.rewind.clear();
+        self.history.clear();
+        Ok(())
+    }
+
+    // capture the whole machine: registers, timers, memory, the screen, the
+    // stack and the keypad. The state can be written to disk and given back to
+    // load_state, on this system or another one created with the same mode.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        self.write_state(&mut w);
+        w.finish()
+    }
+
+    // restore the machine from a state made by save_state. Nothing changes if
+    // the state is corrupt or was saved in a different mode. The rewind and
+    // undo histories are dropped, they belong to the timeline that was left
+    // behind.
+    // Loading a state ends movie playback, and a recording starts over from
+    // the loaded state.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+        self.read_state(&State::parse(state)?)?;
+        self.rewind.clear();
+        self.history.clear();
+        self.playback = None;
+
+        if self.recording.is_some() {
+            self.record();
         }
+        Ok(())
+    }
+
+    fn write_state(&self, w: &mut StateWriter) {
+        self.cpu.save(w);
+        self.mem.save(w);
+        w.chunk(b"SYS ", |w| {
+            w.u16(self.flags.keypad.bits());
+            w.bool(self.flags.vblank);
+            w.bool(self.flags.exit);
+            w.u32(self.remainder);
+        });
+        w.chunk(b"RNG ", |w| w.u64(self.flags.rng.state()));
+    }
+
+    fn read_state(&mut self, state: &State) -> Result<(), EmulatorError> {
+        // load into copies so a bad chunk can't leave the machine half restored
+        let mut cpu = self.cpu.clone();
+        let mut mem = self.mem.clone();
+        cpu.load(state)?;
+        mem.load(state)?;
+
+        let mut r = state.chunk(b"SYS ")?;
+        let keypad = Keypad::from_bits(r.u16()?);
+        let vblank = r.bool()?;
+        let exit = r.bool()?;
+        let remainder = r.u32()?;
+        let rng = state.chunk(b"RNG ")?.u64()?;
+
+        self.cpu = cpu;
+        self.mem = mem;
+        self.flags.draw = false;
+        self.flags.clear = false;
+        self.flags.keypad = keypad;
+        self.flags.vblank = vblank;
+        self.flags.exit = exit;
+        self.flags.rng.set_state(rng);
+        self.remainder = remainder;
+        Ok(())
+    }
+
+    // take a snapshot every interval frames and keep up to capacity of them to
+    // rewind through. A capacity of 0 turns rewind off.
+    pub fn set_rewind(&mut self, interval: u32, capacity: usize) {
+        self.rewind = Rewind::new(interval, capacity);
     }
 
-    // read the rom file from disk and load into memory
-    pub fn load_rom<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
-        match File::open(path) {
-            Ok(mut f) => {
-                let mut data: Vec<u8> = Vec::new();
-                f.read_to_end(&mut data).map_err(|e| e.to_string())?;
+    // go back frames 60Hz frames in time, to the last snapshot taken at or
+    // before then. Returns how many frames were actually rewound, which is
+    // fewer when the history doesn't go back far enough. The screen isn't
+    // redrawn, call render to show where the machine ended up.
+    pub fn rewind(&mut self, frames: u32) -> Result<u32, EmulatorError> {
+        if frames == 0 {
+            return Ok(0);
+        }
+
+        let target = self.frame.saturating_sub(frames as u64);
 
-                // write the data from the file into memory
-                self.mem.write_rom_data(data);
-                self.mem.print_memory();
-                Ok(())
+        let (frame, snapshot) = match self.rewind.rewind(target) {
+            Some((frame, snapshot)) => (frame, snapshot.to_vec()),
+            None => return Ok(0),
+        };
+
+        // the keys held down now are still held down after going back
+        let keypad = self.flags.keypad;
+        self.read_state(&State::from_chunks(&snapshot))?;
+        self.flags.keypad = keypad;
+
+        let rewound = (self.frame - frame) as u32;
+        self.frame = frame;
+        self.left = 0;
+        self.history.clear();
+        self.movies_back_to(frame);
+
+        Ok(rewound)
+    }
+
+    // movies follow the machine back to the start of frame, unless it went
+    // back past their start
+    fn movies_back_to(&mut self, frame: u64) {
+        if let Some((start, movie)) = &mut self.recording {
+            match frame >= *start {
+                true => movie.truncate((frame - *start) as usize),
+                false => self.record(),
             }
-            Err(_) => Err(String::from("Failed to open ROM file")),
+        }
+        if matches!(self.playback, Some((start, _)) if frame < start) {
+            self.playback = None;
         }
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
-        'running: loop {
-            let key_press = self.display.user_event()?;
+    // keep an undo log of the last depth instructions run, so the program can
+    // be stepped back through. 0 turns it off, which it is to start with.
+    // Anything already in the log is dropped.
+    pub fn set_history(&mut self, depth: usize) {
+        self.history = History::new(depth);
+    }
+
+    // how many instructions can be stepped back through
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    // undo the last instruction run, putting the whole machine back to the
+    // moment before it ran. Returns false when there is no history left. The
+    // screen isn't redrawn, call render to show it.
+    pub fn step_back(&mut self) -> bool {
+        self.undo().is_some()
+    }
 
-            match key_press {
-                Key::QUIT => break 'running,
-                _ => self.flags.key = key_press,
+    // step back and return the entry that was undone
+    pub(crate) fn undo(&mut self) -> Option<Entry> {
+        let entry = self.history.pop()?;
+
+        self.cpu = entry.cpu.clone();
+        self.mem.undo(&entry.mem);
+        self.flags.vblank = entry.vblank;
+        self.flags.exit = entry.exit;
+        self.flags.rng.set_state(entry.rng);
+        self.remainder = entry.remainder;
+        self.left = entry.left;
+        self.cycles = entry.cycle;
+        self.accesses.clear();
+
+        // the rewind snapshots and movie frames after the frame gone back to
+        // belong to the timeline that was left behind. The frame had already
+        // started, its own keypad is kept.
+        if entry.frame < self.frame {
+            match self.rewind.rewind(entry.frame) {
+                Some((frame, _)) if frame <= entry.frame => {}
+                _ => self.rewind.clear(),
             }
+            self.movies_back_to(entry.frame + 1);
+        }
+        self.frame = entry.frame;
+
+        Some(entry)
+    }
+
+    // the address of the last instruction run, None if the history is empty
+    pub fn last_pc(&self) -> Option<u16> {
+        self.history.last().map(|entry| entry.pc)
+    }
+
+    // the last instruction in the history that wrote addr, None if there
+    // isn't one
+    pub fn last_write(&self, addr: u16) -> Option<MemoryWrite> {
+        let new = *self.mem.ram().get(addr as usize)?;
+
+        self.history.iter().find_map(|entry| {
+            let old = entry.mem.old_value(addr)?;
+            Some(MemoryWrite {
+                cycle: entry.cycle,
+                pc: entry.pc,
+                old,
+                new,
+            })
+        })
+    }
+
+    // instructions run since the rom was loaded
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // write every instruction run from now on to a trace, see trace.rs.
+    // A trace already being written is finished first.
+    pub fn trace(&mut self, mut trace: Trace) -> Result<(), EmulatorError> {
+        self.stop_trace()?;
+        trace.start()?;
+        self.trace = Some(trace);
+        Ok(())
+    }
+
+    // stop tracing, writing out what's left of the trace
+    pub fn stop_trace(&mut self) -> Result<(), EmulatorError> {
+        if let Some(mut trace) = self.trace.take() {
+            trace.flush()?;
+        }
+        Ok(())
+    }
+
+    // CRC-32 of the loaded rom
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    // start recording the keypad of every frame to a movie, starting from the
+    // current state of the machine. Anything recorded so far is thrown away.
+    pub fn record(&mut self) {
+        let movie = Movie::new(self.rom_hash, self.config, self.save_state());
+        self.recording = Some((self.frame, movie));
+    }
+
+    // stop recording and return the movie, None if nothing was being recorded
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        self.recording.take().map(|(_, movie)| movie)
+    }
+
+    // play a movie back from its start. Until the movie runs out the keypad of
+    // every frame comes from the movie instead of the video backend. The movie
+    // must have been recorded on the same rom with the same config.
+    pub fn play(&mut self, movie: Movie) -> Result<(), EmulatorError> {
+        if movie.rom_hash() != self.rom_hash {
+            return Err(EmulatorError::InvalidMovie(format!(
+                "recorded on a rom with hash {:08x}, this one is {:08x}",
+                movie.rom_hash(),
+                self.rom_hash
+            )));
+        }
+
+        if movie.config() != self.config {
+            return Err(EmulatorError::InvalidMovie(
+                "recorded with a different config".to_string(),
+            ));
+        }
+
+        self.load_state(movie.start())?;
+        self.playback = Some((self.frame, movie));
+        Ok(())
+    }
+
+    // a movie is being played back
+    pub fn playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    // the file save state slot n is kept in, e.g. roms/Tetris.ch8.state1
+    fn slot_path(&self, slot: u8) -> PathBuf {
+        let mut name = match &self.rom {
+            Some(rom) => rom.as_os_str().to_owned(),
+            None => "chip8".into(),
+        };
+        name.push(format!(".state{}", slot));
+        PathBuf::from(name)
+    }
+
+    fn save_slot(&self, slot: u8) -> Result<(), EmulatorError> {
+        fs::write(self.slot_path(slot), self.save_state())?;
+        Ok(())
+    }
+
+    fn load_slot(&mut self, slot: u8) -> Result<(), EmulatorError> {
+        let state = fs::read(self.slot_path(slot))?;
+        self.load_state(&state)
+    }
+
+    // replace the random number generator Cxkk uses
+    pub fn set_rng(&mut self, rng: Box<dyn Rng>) {
+        self.flags.rng = rng;
+    }
+
+    // the video backend the system is drawing to
+    pub fn video(&self) -> &V {
+        &self.video
+    }
 
-            // fetch the 2 byte instruction at memory address held by the PC register
-            let mem_addr = self.cpu.register_pc();
-            let data = self.mem.read_word(mem_addr)?;
+    pub fn video_mut(&mut self) -> &mut V {
+        &mut self.video
+    }
+
+    // the screen pixels, one byte per pixel in rows of width pixels. See
+    // Video::draw for what the pixel values mean.
+    pub fn vram(&self) -> &[u8] {
+        self.mem.vram()
+    }
+
+    // (width, height) of the screen, 64x32 in lo-res and 128x64 in hi-res
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.mem.width(), self.mem.height())
+    }
+
+    // draw the screen to the video backend and present it
+    pub fn render(&mut self) {
+        let (width, height) = self.resolution();
+        self.video.draw(self.mem.vram(), width, height);
+        self.video.present();
+    }
+
+    // the XO-CHIP audio pattern, 128 1-bit samples played back while the sound
+    // timer is running
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        self.mem.audio_pattern()
+    }
+
+    // the XO-CHIP pitch register. Samples of the audio pattern are played at
+    // 4000 * 2^((pitch - 64) / 48) Hz.
+    pub fn pitch(&self) -> u8 {
+        self.cpu.pitch()
+    }
+
+    // the keys currently held down
+    pub fn keypad(&self) -> &Keypad {
+        &self.flags.keypad
+    }
+
+    // press and release keys on the keypad
+    pub fn keypad_mut(&mut self) -> &mut Keypad {
+        &mut self.flags.keypad
+    }
+
+    // the registers and timers of the cpu
+    pub fn registers(&self) -> Registers {
+        self.cpu.registers()
+    }
+
+    // change the registers and timers of the cpu. SP sets how many return
+    // addresses are on the call stack, at most 16.
+    pub fn set_registers(&mut self, mut registers: Registers) {
+        self.mem.set_stack_depth(registers.sp as usize);
+        registers.sp = self.mem.stack().len() as u8;
+        self.cpu.set_registers(registers);
+    }
+
+    // the return addresses of the subroutines being run, the most recent call
+    // last
+    pub fn call_stack(&self) -> &[u16] {
+        self.mem.stack()
+    }
+
+    // all of memory, the rom starts at 0x200
+    pub fn memory(&self) -> &[u8] {
+        self.mem.ram()
+    }
 
-            // decode
-            let instr = Instruction::decode(data);
+    // write bytes to memory starting at addr. Nothing is written if they don't
+    // all fit.
+    pub fn write_memory(&mut self, addr: u16, data: &[u8]) -> Result<(), EmulatorError> {
+        if addr as usize + data.len() > self.mem.ram().len() {
+            return Err(EmulatorError::MemoryOutOfBounds {
+                addr: addr as usize,
+                len: data.len(),
+            });
+        }
+
+        for (offset, byte) in data.iter().enumerate() {
+            self.mem.write_byte(addr + offset as u16, *byte)?;
+        }
+        Ok(())
+    }
+
+    // start or stop recording the memory accesses instructions make
+    pub(crate) fn watch_accesses(&mut self, on: bool) {
+        self.watching = on;
+    }
+
+    // the reads and writes the last instruction made while they were watched
+    pub(crate) fn accesses(&self) -> &[Access] {
+        &self.accesses
+    }
 
-            println!("address: {:#09x}, data = {:#05x}", mem_addr, data);
-            println!("{}", instr);
+    // check the video backend for input. Keys pressed and released update the
+    // keypad, anything the user asked the host to do is returned.
+    pub fn poll_input(&mut self) -> Result<Option<Command>, EmulatorError> {
+        self.video.user_event(&mut self.flags.keypad)
+    }
+
+    // while Fx0A is waiting for a key this is the register Vx the key will be
+    // stored in, None when the cpu is running normally
+    pub fn waiting_for_key(&self) -> Option<u8> {
+        self.cpu.key_wait()
+    }
 
-            // execute
-            self.cpu.execute(instr, &mut self.flags, &mut self.mem)?;
+    // fetch, decode and execute the instruction at the PC register. Nothing is
+    // drawn, the events say what the caller needs to show. While Fx0A is
+    // waiting for a key no instruction runs, the keypad is checked instead.
+    pub fn step(&mut self) -> Result<Events, EmulatorError> {
+        self.accesses.clear();
+
+        if self.flags.exit {
+            return Ok(self.events());
+        }
+
+        if self.cpu.key_wait().is_some() {
+            self.cpu.poll_key_wait(&self.flags.keypad);
+            return Ok(self.events());
+        }
+
+        // fetch and decode the instruction at memory address held by the PC register
+        let mem_addr = self.cpu.register_pc();
+        let instr = Instruction::fetch(&self.mem, mem_addr)?;
+
+        if let Some(trace) = &mut self.trace {
+            trace.write(&TraceLine {
+                cycle: self.cycles,
+                opcode: instr.opcode(),
+                long: instr.long(),
+                registers: self.cpu.registers(),
+            })?;
+        }
+
+        self.mem.record_accesses(self.watching || self.history.on());
+        let before = self.checkpoint(mem_addr, instr.op().draws());
+        self.cpu.advance_pc(instr.len());
+
+        // execute. An instruction that fails part way is still in the
+        // history, stepping back undoes what it did get done.
+        let result = self.cpu.execute(instr, &mut self.flags, &mut self.mem);
+
+        self.accesses = self.mem.take_accesses();
+        self.mem.record_accesses(false);
+        if let Some(mut entry) = before {
+            entry.mem = self.mem.end_undo();
+            entry.accesses = self.accesses.clone();
+            self.history.push(entry);
+        }
+        self.cycles += 1;
 
-            if self.flags.clear {
-                self.flags.clear = false;
-                self.display.clear();
+        result?;
+        Ok(self.events())
+    }
+
+    // the history entry for the instruction at pc, before it runs. Nothing
+    // is kept while the history is off.
+    fn checkpoint(&mut self, pc: u16, draws: bool) -> Option<Entry> {
+        if !self.history.on() {
+            return None;
+        }
+
+        self.mem.begin_undo(draws);
+        Some(Entry {
+            cycle: self.cycles,
+            pc,
+            accesses: Vec::new(),
+            cpu: self.cpu.clone(),
+            mem: Default::default(),
+            vblank: self.flags.vblank,
+            exit: self.flags.exit,
+            rng: self.flags.rng.state(),
+            remainder: self.remainder,
+            frame: self.frame,
+            left: self.left,
+        })
+    }
+
+    // run up to n instructions, stopping early if the program exits or starts
+    // waiting for a key
+    pub fn run_cycles(&mut self, n: u32) -> Result<Events, EmulatorError> {
+        let mut events = Events::default();
+
+        for _ in 0..n {
+            events.merge(self.step()?);
+
+            if events.exited || events.waiting {
+                break;
             }
+        }
 
-            if self.flags.draw {
-                self.flags.draw = false;
-                self.display.draw(self.mem.get_vram());
+        Ok(events)
+    }
+
+    // run one 60Hz frame: the configured number of instructions followed by a
+    // tick of the delay and sound timers
+    pub fn run_frame(&mut self) -> Result<Events, EmulatorError> {
+        let n = self.begin_frame();
+        let mut events = self.run_cycles(n)?;
+        events.merge(self.end_frame());
+        Ok(events)
+    }
+
+    // run one instruction of the frame a debugger is stepping through,
+    // starting the frame first if the last one is over. Like run_frame the
+    // timers tick after the frame's last instruction, or straight away when
+    // the program exits or starts waiting for a key.
+    pub(crate) fn step_in_frame(&mut self) -> Result<Events, EmulatorError> {
+        if self.left == 0 {
+            // at speeds below 60 instructions a second some frames run none,
+            // stepping always runs one
+            self.left = self.begin_frame().max(1);
+        }
+
+        let mut events = self.step()?;
+        self.left -= 1;
+
+        if self.left == 0 || events.exited || events.waiting {
+            self.left = 0;
+            events.merge(self.end_frame());
+        }
+
+        Ok(events)
+    }
+
+    // a debugger is part way through a frame
+    pub(crate) fn mid_frame(&self) -> bool {
+        self.left > 0
+    }
+
+    // the start of a 60Hz frame: take a rewind snapshot and set the keypad of
+    // the movie being played or recorded. Returns how many instructions the
+    // frame runs.
+    fn begin_frame(&mut self) -> u32 {
+        if self.rewind.due(self.frame) {
+            let mut w = StateWriter::new();
+            self.write_state(&mut w);
+            self.rewind.push(self.frame, w.into_chunks());
+        }
+
+        if let Some((start, movie)) = &self.playback {
+            match movie.keypad((self.frame - start) as usize) {
+                Some(keypad) => self.flags.keypad = keypad,
+                None => self.playback = None,
             }
+        }
 
-            self.cpu.tick_timer();
-            thread::sleep(time::Duration::from_millis(16));
+        if let Some((start, movie)) = &mut self.recording {
+            movie.record((self.frame - *start) as usize, &self.flags.keypad);
         }
 
-        Ok(())
+        self.config.speed.instructions(&mut self.remainder)
+    }
+
+    // the end of a 60Hz frame, after its instructions ran: tick the timers
+    fn end_frame(&mut self) -> Events {
+        self.cpu.tick_timer();
+        self.flags.vblank = true;
+        self.frame += 1;
+        self.events()
+    }
+
+    // collect and reset the flags set by the last instruction
+    fn events(&mut self) -> Events {
+        let events = Events {
+            drew: self.flags.draw,
+            cleared: self.flags.clear,
+            sound: self.cpu.sound_on(),
+            waiting: self.cpu.key_wait().is_some(),
+            exited: self.flags.exit,
+        };
+
+        self.flags.draw = false;
+        self.flags.clear = false;
+        events
+    }
+
+    // run the program in real time, drawing to the video backend, until the
+    // user quits, the program exits or a state is saved or loaded. Frames run at
+    // 60Hz by the wall clock, so the timers tick at their real rate whatever the
+    // instruction rate is.
+    pub fn run(&mut self) -> Result<Stopped, EmulatorError> {
+        let mut scheduler = Scheduler::start();
+
+        loop {
+            match self.poll_input()? {
+                Some(Command::Quit) => return Ok(Stopped::Quit),
+                Some(Command::SaveState(slot)) => {
+                    return Ok(Stopped::SavedState(slot, self.save_slot(slot)))
+                }
+                Some(Command::LoadState(slot)) => {
+                    let result = self.load_slot(slot);
+                    if result.is_ok() {
+                        self.render();
+                    }
+                    return Ok(Stopped::LoadedState(slot, result));
+                }
+                Some(Command::Rewind) => {
+                    // play time backwards instead of running the due frames
+                    let frames = scheduler.frames_due();
+                    if frames > 0 {
+                        self.rewind(frames * REWIND_SPEED)?;
+                        self.render();
+                    }
+
+                    scheduler.wait();
+                    continue;
+                }
+                None => {}
+            }
+
+            let mut events = Events::default();
+            for _ in 0..scheduler.frames_due() {
+                events.merge(self.run_frame()?);
+
+                if events.exited {
+                    return Ok(Stopped::Quit);
+                }
+            }
+
+            if events.cleared {
+                self.video.clear();
+                self.video.present();
+            }
+
+            if events.drew {
+                self.render();
+            }
+
+            scheduler.wait();
+        }
     }
 }
@@ -0,0 +1,294 @@
+use crate::{disassemble_instruction, EmulatorError, Registers, Syntax};
+
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+// An execution trace is a record of every instruction a system runs, with the
+// registers as they were before it ran. Tracing is off until System::trace is
+// given a Trace to write to. The text format is one line per instruction:
+//
+//      cycle pc   opcode   instruction            registers
+//         12 0204 F033     LD B, V0               V0=7B ... VF=00 I=0300 SP=0 DT=3B ST=3B
+//
+// The cycle is decimal and everything else is hex. The opcode is 8 digits for
+// XO-CHIP's F000 nnnn. The instruction text is only there for people, and the
+// registers can come in any order, so another emulator can write the same shape
+// of trace to compare against. Lines that are empty or start with # are skipped.
+//
+// The binary format is the magic "C8TR" and a u16 version, then a record of
+// RECORD_LEN bytes per instruction. A record is the cycle u64, pc u16, opcode
+// u16, the word after the opcode u16, V0-VF, I u16, SP, DT and ST, all big-endian.
+const MAGIC: &[u8; 4] = b"C8TR";
+const TRACE_VERSION: u16 = 1;
+const HEADER_LEN: usize = 6;
+const RECORD_LEN: usize = 35;
+
+// the registers of a text line, V0-VF first so their index is x
+const REGISTERS: [&str; 20] = [
+    "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF",
+    "I", "SP", "DT", "ST",
+];
+
+// How a trace is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+// One instruction in a trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceLine {
+    // the instruction's number, counting from 0 when the rom was loaded
+    pub cycle: u64,
+    pub opcode: u16,
+    // the address that follows F000, 0 for every other instruction
+    pub long: u16,
+    // the registers and timers before the instruction ran. pc is where the
+    // instruction is.
+    pub registers: Registers,
+}
+
+// Where a trace is written and which instructions go in it.
+pub struct Trace {
+    out: Box<dyn Write>,
+    format: TraceFormat,
+    addresses: RangeInclusive<u16>,
+    cycles: RangeInclusive<u64>,
+}
+
+impl Trace {
+    // trace every instruction to out. Writing a line per instruction is slow
+    // unless out is buffered.
+    pub fn new(format: TraceFormat, out: impl Write + 'static) -> Trace {
+        Trace {
+            out: Box::new(out),
+            format,
+            addresses: 0..=u16::MAX,
+            cycles: 0..=u64::MAX,
+        }
+    }
+
+    // only trace the instructions at addresses in range
+    pub fn set_addresses(&mut self, addresses: RangeInclusive<u16>) {
+        self.addresses = addresses;
+    }
+
+    // only trace the instructions with cycle numbers in range
+    pub fn set_cycles(&mut self, cycles: RangeInclusive<u64>) {
+        self.cycles = cycles;
+    }
+
+    // the header, before the first line
+    pub(crate) fn start(&mut self) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Text => Ok(()),
+            TraceFormat::Binary => {
+                self.out.write_all(MAGIC)?;
+                self.out.write_all(&TRACE_VERSION.to_be_bytes())
+            }
+        }
+    }
+
+    pub(crate) fn write(&mut self, line: &TraceLine) -> io::Result<()> {
+        if !self.addresses.contains(&line.registers.pc) || !self.cycles.contains(&line.cycle) {
+            return Ok(());
+        }
+
+        match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", line),
+            TraceFormat::Binary => self.out.write_all(&line.to_bytes()),
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl TraceLine {
+    // true if both lines ran the same instruction from the same registers.
+    // Cycles aren't compared, emulators count them differently.
+    pub fn same(&self, other: &TraceLine) -> bool {
+        self.opcode == other.opcode && self.long == other.long && self.registers == other.registers
+    }
+
+    // a line of a text trace
+    pub fn parse(text: &str) -> Result<TraceLine, EmulatorError> {
+        let mut words = text.split_whitespace();
+        let mut field = |name: &str| words.next().ok_or_else(|| invalid(format!("no {}", name)));
+
+        let cycle = field("cycle")?;
+        let cycle = cycle
+            .parse()
+            .map_err(|_| invalid(format!("invalid cycle {}", cycle)))?;
+        let pc = hex(field("pc")?)?;
+        let opcode = field("opcode")?;
+        // get rather than slicing, the middle of the word needn't be a char
+        // boundary
+        let (opcode, long) = match (opcode.len(), opcode.get(..4), opcode.get(4..)) {
+            (8, Some(high), Some(low)) => (hex(high)?, hex(low)?),
+            _ => (hex(opcode)?, 0),
+        };
+
+        // the instruction text is skipped over, the registers are the words
+        // with an =
+        let mut registers = Registers {
+            pc,
+            ..Registers::default()
+        };
+        let mut seen = [false; REGISTERS.len()];
+        for (name, value) in words.filter_map(|word| word.split_once('=')) {
+            let n = REGISTERS
+                .iter()
+                .position(|register| register.eq_ignore_ascii_case(name))
+                .ok_or_else(|| invalid(format!("there is no register {}", name)))?;
+            let value = hex(value)?;
+            let byte = u8::try_from(value)
+                .map_err(|_| invalid(format!("{} is too big for {}", value, name)));
+
+            match REGISTERS[n] {
+                "I" => registers.i = value,
+                "SP" => registers.sp = byte?,
+                "DT" => registers.dt = byte?,
+                "ST" => registers.st = byte?,
+                _ => registers.v[n] = byte?,
+            }
+            seen[n] = true;
+        }
+
+        if let Some(n) = seen.iter().position(|seen| !seen) {
+            return Err(invalid(format!("no value for {}", REGISTERS[n])));
+        }
+
+        Ok(TraceLine {
+            cycle,
+            opcode,
+            long,
+            registers,
+        })
+    }
+
+    fn to_bytes(self) -> [u8; RECORD_LEN] {
+        let r = &self.registers;
+        let mut bytes = [0; RECORD_LEN];
+        bytes[0..8].copy_from_slice(&self.cycle.to_be_bytes());
+        bytes[8..10].copy_from_slice(&r.pc.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.opcode.to_be_bytes());
+        bytes[12..14].copy_from_slice(&self.long.to_be_bytes());
+        bytes[14..30].copy_from_slice(&r.v);
+        bytes[30..32].copy_from_slice(&r.i.to_be_bytes());
+        bytes[32..35].copy_from_slice(&[r.sp, r.dt, r.st]);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> TraceLine {
+        let word = |at: usize| u16::from_be_bytes([bytes[at], bytes[at + 1]]);
+        let mut cycle = [0; 8];
+        cycle.copy_from_slice(&bytes[0..8]);
+        let mut v = [0; 16];
+        v.copy_from_slice(&bytes[14..30]);
+
+        TraceLine {
+            cycle: u64::from_be_bytes(cycle),
+            opcode: word(10),
+            long: word(12),
+            registers: Registers {
+                v,
+                i: word(30),
+                pc: word(8),
+                sp: bytes[32],
+                dt: bytes[33],
+                st: bytes[34],
+            },
+        }
+    }
+}
+
+// the text format, without a newline
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let r = &self.registers;
+        let (opcode, text) = match disassemble_instruction(self.opcode, self.long, Syntax::Cowgod) {
+            Some(text) if self.opcode == 0xF000 => {
+                (format!("{:04X}{:04X}", 0xF000, self.long), text)
+            }
+            Some(text) => (format!("{:04X}", self.opcode), text),
+            None => (format!("{:04X}", self.opcode), "??".to_string()),
+        };
+
+        write!(
+            f,
+            "{:10} {:04X} {:<8} {:<22}",
+            self.cycle, r.pc, opcode, text
+        )?;
+        for (x, v) in r.v.iter().enumerate() {
+            write!(f, "V{:X}={:02X} ", x, v)?;
+        }
+        write!(
+            f,
+            "I={:04X} SP={:X} DT={:02X} ST={:02X}",
+            r.i, r.sp, r.dt, r.st
+        )
+    }
+}
+
+// a hex number, with or without 0x in front
+fn hex(text: &str) -> Result<u16, EmulatorError> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| invalid(format!("invalid number {}", text)))
+}
+
+fn invalid(reason: String) -> EmulatorError {
+    EmulatorError::InvalidTrace(reason)
+}
+
+// read a trace written in either format
+pub fn read_trace(data: &[u8]) -> Result<Vec<TraceLine>, EmulatorError> {
+    if data.starts_with(MAGIC) {
+        let version = data
+            .get(4..HEADER_LEN)
+            .map(|version| u16::from_be_bytes([version[0], version[1]]));
+        if version != Some(TRACE_VERSION) {
+            return Err(invalid("the version isn't supported".to_string()));
+        }
+
+        let records = &data[HEADER_LEN..];
+        if !records.len().is_multiple_of(RECORD_LEN) {
+            return Err(invalid("the last record is cut short".to_string()));
+        }
+        return Ok(records
+            .chunks(RECORD_LEN)
+            .map(TraceLine::from_bytes)
+            .collect());
+    }
+
+    let text = std::str::from_utf8(data)
+        .map_err(|_| invalid("neither text nor a binary trace".to_string()))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(n, line)| {
+            TraceLine::parse(line).map_err(|e| match e {
+                EmulatorError::InvalidTrace(reason) => {
+                    invalid(format!("line {}: {}", n + 1, reason))
+                }
+                e => e,
+            })
+        })
+        .collect()
+}
+
+// the index of the first line where two traces differ, None if they are the
+// same. A trace that ends first differs from the other where it ends.
+pub fn first_difference(a: &[TraceLine], b: &[TraceLine]) -> Option<usize> {
+    match a.iter().zip(b).position(|(a, b)| !a.same(b)) {
+        Some(n) => Some(n),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
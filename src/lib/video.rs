@@ -0,0 +1,36 @@
+use crate::{EmulatorError, Keypad};
+
+// Requests from the user to the host running the emulator, rather than to the
+// Chip-8 program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    // close the emulator
+    Quit,
+    // save the machine to the numbered save state slot
+    SaveState(u8),
+    // restore the machine from the numbered save state slot
+    LoadState(u8),
+    // go back in time, sent for as long as the user holds the rewind key
+    Rewind,
+}
+
+// Video output used by the System. The System only talks to the screen and the
+// keyboard through this trait, so any backend (an SDL window, an in-memory
+// framebuffer, ...) can be plugged in.
+pub trait Video {
+    // clear the output to the background colour
+    fn clear(&mut self);
+
+    // draw the contents of vram, one byte per pixel in rows of width pixels. The
+    // screen is 64x32 in lo-res and 128x64 in hi-res. Each pixel has bit 0 set
+    // when it is on in plane 1 and bit 1 when it is on in XO-CHIP's plane 2, so
+    // plain Chip-8 pixels are either 0 (off) or 1 (on).
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize);
+
+    // show everything cleared or drawn since the last present
+    fn present(&mut self);
+
+    // poll the backend for user input since the last call. Key presses and
+    // releases are applied to keypad, anything meant for the host is returned.
+    fn user_event(&mut self, keypad: &mut Keypad) -> Result<Option<Command>, EmulatorError>;
+}
@@ -1,9 +1,162 @@
-use emulator::{Display, System};
+use emulator::{assemble_file, disassemble, Config, EmulatorError, Syntax};
 
-fn main() -> Result<(), String> {
-    let display = Display::create("Chip-8".to_string(), 10);
-    let mut system = System::create(display);
+mod dap;
+mod debug;
+mod gdb;
+mod json;
+#[cfg(feature = "sdl")]
+mod run;
+mod trace;
 
-    system.load_rom("roms/IBM_Logo.ch8")?;
-    system.run()
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "usage: chip8 [options] [rom]
+       chip8 disasm [--octo] <rom>
+       chip8 asm [-o <rom>] <source>
+       chip8 debug [--headless] [--history <n>] [--seed <n>]
+                   [--rng <xorshift|vip>] <rom>
+       chip8 gdb [--port <n> | --socket <file>] [--headless] [--history <n>]
+                 [--seed <n>] [--rng <xorshift|vip>] <rom>
+       chip8 dap
+       chip8 trace [-o <file>] [--binary] [--frames <n>] [--pc <from>-<to>]
+                   [--cycles <from>-<to>] [--seed <n>] [--rng <xorshift|vip>] <rom>
+       chip8 tracediff <trace> <trace>
+
+A rom ending in .8o is Octo source, compiled before it runs. Octo programs run
+as XO-CHIP, other roms as Chip-8.
+
+options:
+  --keymap <qwerty|hex|file>  keyboard layout, defaults to <rom>.keys if it
+                              exists and qwerty otherwise
+  --seed <n>                  seed for the random number generator, picked
+                              from the clock if not given
+  --rng <xorshift|vip>        random number generator, defaults to xorshift
+  --record <file>             record the keypad to a movie file until the
+                              emulator is closed
+  --play <file>               play a movie file back
+  --trace <file>              write a line to file for every instruction run
+
+disasm prints the rom as Cowgod style assembly, or as Octo with --octo
+asm assembles Cowgod style source into a rom, written next to the source with
+a .ch8 extension unless -o is given
+debug runs the rom paused under a debugger that reads commands from stdin, in
+a window or with --headless without one. Type help for the commands.
+gdb waits for a gdb remote protocol client on 127.0.0.1, port 1234 unless
+--port is given, or on a unix socket with --socket. The rom starts stopped at
+its first instruction.
+debug and gdb keep the last 100000 instructions run so the program can be
+stepped back through, --history sets how many and 0 turns it off.
+dap is a debug adapter protocol server on stdin and stdout for editors, the
+rom or source to debug is given by the launch request.
+trace runs the rom without a window for 600 frames, or --frames, and writes a
+line for every instruction to stdout or the -o file, as fixed size records with
+--binary. --pc and --cycles only trace the addresses (hex) and instruction
+numbers (decimal) in a range, either end of which can be left out.
+tracediff finds the first instruction where two traces differ, they can come
+from another emulator writing the same text format.";
+
+// instructions the debuggers can step back through unless told otherwise
+const HISTORY: usize = 100_000;
+
+// print a usage error and quit
+fn exit_usage(e: &str) -> ! {
+    eprintln!("{}", e);
+    process::exit(2);
+}
+
+// the config a rom runs with when none is asked for. Octo programs are usually
+// written for XO-CHIP.
+fn config_for(rom: &Path) -> Config {
+    match rom.extension().is_some_and(|ext| ext == "8o") {
+        true => Config::xochip(),
+        false => Config::chip8(),
+    }
+}
+
+// a different seed every run when none is given
+fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+// chip8 disasm [--octo] <rom>
+fn disasm(args: &[String]) -> Result<(), EmulatorError> {
+    let mut syntax = Syntax::Cowgod;
+    let mut rom = None;
+
+    for arg in args {
+        match arg.as_str() {
+            "--octo" => syntax = Syntax::Octo,
+            "-h" | "--help" => exit_usage(USAGE),
+            _ if arg.starts_with('-') => exit_usage(&format!("unknown option {}\n{}", arg, USAGE)),
+            _ => rom = Some(arg),
+        }
+    }
+
+    let rom = rom.unwrap_or_else(|| exit_usage("disasm needs a rom"));
+    print!("{}", disassemble(&fs::read(rom)?, syntax));
+    Ok(())
+}
+
+// chip8 asm [-o <rom>] <source>
+fn asm(args: &[String]) -> Result<(), EmulatorError> {
+    let mut output = None;
+    let mut source = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => exit_usage("-o needs a file name"),
+            },
+            "-h" | "--help" => exit_usage(USAGE),
+            _ if arg.starts_with('-') => exit_usage(&format!("unknown option {}\n{}", arg, USAGE)),
+            _ => source = Some(arg),
+        }
+    }
+
+    let source = Path::new(source.unwrap_or_else(|| exit_usage("asm needs a source file")));
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+
+    fs::write(output, assemble_file(source)?.rom)?;
+    Ok(())
+}
+
+#[cfg(feature = "sdl")]
+fn run(args: &[String]) -> Result<(), EmulatorError> {
+    run::run(args)
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run(_args: &[String]) -> Result<(), EmulatorError> {
+    exit_usage("chip8 was built without the sdl feature, it can't open a window")
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("disasm") => disasm(&args[1..]),
+        Some("asm") => asm(&args[1..]),
+        Some("debug") => debug::debug(&args[1..]),
+        Some("gdb") => gdb::gdb(&args[1..]),
+        Some("dap") => dap::dap(),
+        Some("trace") => trace::trace(&args[1..]),
+        Some("tracediff") => trace::tracediff(&args[1..]),
+        _ => run(&args),
+    };
+
+    // errors are printed the way compilers print them, so editors can jump to
+    // the line of a mistake in assembly or Octo source
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
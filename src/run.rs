@@ -0,0 +1,143 @@
+use crate::{clock_seed, config_for, exit_usage, USAGE};
+use emulator::{
+    Display, EmulatorError, KeyMap, Movie, RngKind, Stopped, System, Trace, TraceFormat,
+};
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+// command line options for running a rom
+struct Options {
+    rom: PathBuf,
+    keymap: Option<String>,
+    seed: Option<u64>,
+    rng: RngKind,
+    record: Option<PathBuf>,
+    play: Option<PathBuf>,
+    trace: Option<PathBuf>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom: PathBuf::from("roms/IBM_Logo.ch8"),
+        keymap: None,
+        seed: None,
+        rng: RngKind::Xorshift,
+        record: None,
+        play: None,
+        trace: None,
+    };
+
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keymap" => options.keymap = Some(args.next().ok_or("--keymap needs a value")?),
+            "--seed" => {
+                let seed = args.next().ok_or("--seed needs a value")?;
+                options.seed = Some(seed.parse().map_err(|_| format!("invalid seed {}", seed))?);
+            }
+            "--rng" => {
+                options.rng = match args.next().as_deref() {
+                    Some("xorshift") => RngKind::Xorshift,
+                    Some("vip") => RngKind::CosmacVip,
+                    _ => return Err("--rng needs to be xorshift or vip".to_string()),
+                }
+            }
+            "--record" => options.record = Some(args.next().ok_or("--record needs a file")?.into()),
+            "--play" => options.play = Some(args.next().ok_or("--play needs a file")?.into()),
+            "--trace" => options.trace = Some(args.next().ok_or("--trace needs a file")?.into()),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => options.rom = PathBuf::from(arg),
+        }
+    }
+
+    Ok(options)
+}
+
+// the key map named on the command line, or the one saved next to the rom
+fn keymap(options: &Options) -> Result<KeyMap, EmulatorError> {
+    match options.keymap.as_deref() {
+        Some("qwerty") => Ok(KeyMap::qwerty()),
+        Some("hex") => Ok(KeyMap::hex()),
+        Some(path) => KeyMap::load(path),
+        None => {
+            let per_rom = rom_file(&options.rom, "keys");
+            match per_rom.exists() {
+                true => KeyMap::load(per_rom),
+                false => Ok(KeyMap::default()),
+            }
+        }
+    }
+}
+
+// a file stored alongside the rom, e.g. roms/Tetris.ch8.keys
+fn rom_file(rom: &Path, extension: &str) -> PathBuf {
+    let mut name = rom.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+// run the system until the user quits or the program exits, saying how saving
+// and loading states went
+fn run_until_quit(system: &mut System<Display>) -> Result<(), EmulatorError> {
+    loop {
+        match system.run()? {
+            Stopped::Quit => return Ok(()),
+            Stopped::SavedState(slot, Ok(())) => println!("saved state {}", slot),
+            Stopped::SavedState(slot, Err(e)) => eprintln!("couldn't save state {}: {}", slot, e),
+            Stopped::LoadedState(slot, Ok(())) => println!("loaded state {}", slot),
+            Stopped::LoadedState(slot, Err(e)) => eprintln!("couldn't load state {}: {}", slot, e),
+        }
+    }
+}
+
+// run a rom in an SDL window
+pub(crate) fn run(args: &[String]) -> Result<(), EmulatorError> {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => exit_usage(&e),
+    };
+
+    let mut display = Display::create("Chip-8".to_string(), 10);
+    display.set_keymap(keymap(&options)?);
+
+    // a movie is played back with the config it was recorded with
+    let movie = options.play.as_ref().map(Movie::load).transpose()?;
+    let config = match &movie {
+        Some(movie) => movie.config(),
+        None => {
+            let mut config = config_for(&options.rom);
+            config.rng = options.rng;
+            config.seed = options.seed.unwrap_or_else(clock_seed);
+            config
+        }
+    };
+
+    let mut system = System::create(display, config);
+    system.load_rom(&options.rom)?;
+
+    if let Some(movie) = movie {
+        system.play(movie)?;
+    }
+    if options.record.is_some() {
+        system.record();
+    }
+    if let Some(path) = &options.trace {
+        let file = BufWriter::new(File::create(path)?);
+        system.trace(Trace::new(TraceFormat::Text, file))?;
+    }
+
+    let result = run_until_quit(&mut system);
+    system.stop_trace()?;
+    result?;
+
+    if let (Some(path), Some(movie)) = (&options.record, system.stop_recording()) {
+        movie.save(path)?;
+        println!("recorded {} frames to {}", movie.len(), path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use crate::{clock_seed, config_for, exit_usage, USAGE};
+use emulator::{
+    first_difference, read_trace, EmulatorError, Framebuffer, RngKind, System, Trace, TraceFormat,
+    TraceLine,
+};
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use std::process;
+
+// frames traced unless --frames is given, ten seconds
+const FRAMES: u64 = 600;
+
+// command line options for tracing a rom
+struct Options {
+    rom: PathBuf,
+    output: Option<PathBuf>,
+    format: TraceFormat,
+    frames: u64,
+    addresses: (u16, u16),
+    cycles: (u64, u64),
+    seed: Option<u64>,
+    rng: RngKind,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom: PathBuf::new(),
+        output: None,
+        format: TraceFormat::Text,
+        frames: FRAMES,
+        addresses: (0, u16::MAX),
+        cycles: (0, u64::MAX),
+        seed: None,
+        rng: RngKind::Xorshift,
+    };
+
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => options.output = Some(args.next().ok_or("-o needs a file name")?.into()),
+            "--binary" => options.format = TraceFormat::Binary,
+            "--frames" => {
+                let frames = args.next().ok_or("--frames needs a value")?;
+                options.frames = frames
+                    .parse()
+                    .map_err(|_| format!("invalid frame count {}", frames))?;
+            }
+            "--pc" => {
+                let range = args.next().ok_or("--pc needs a range")?;
+                let (from, to) = parse_range(&range, 16)
+                    .filter(|(from, _)| *from <= u16::MAX as u64)
+                    .ok_or_else(|| format!("invalid address range {}", range))?;
+                options.addresses = (from as u16, to.min(u16::MAX as u64) as u16);
+            }
+            "--cycles" => {
+                let range = args.next().ok_or("--cycles needs a range")?;
+                options.cycles = parse_range(&range, 10)
+                    .ok_or_else(|| format!("invalid cycle range {}", range))?;
+            }
+            "--seed" => {
+                let seed = args.next().ok_or("--seed needs a value")?;
+                options.seed = Some(seed.parse().map_err(|_| format!("invalid seed {}", seed))?);
+            }
+            "--rng" => {
+                options.rng = match args.next().as_deref() {
+                    Some("xorshift") => RngKind::Xorshift,
+                    Some("vip") => RngKind::CosmacVip,
+                    _ => return Err("--rng needs to be xorshift or vip".to_string()),
+                }
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => options.rom = PathBuf::from(arg),
+        }
+    }
+
+    match options.rom.as_os_str().is_empty() {
+        true => Err("trace needs a rom".to_string()),
+        false => Ok(options),
+    }
+}
+
+// <from>-<to>, both included. Either end can be left out to go from the start
+// or to the end.
+fn parse_range(text: &str, radix: u32) -> Option<(u64, u64)> {
+    let (from, to) = text.split_once('-')?;
+    let number = |text: &str, default| match text {
+        "" => Some(default),
+        _ => u64::from_str_radix(text.trim_start_matches("0x"), radix).ok(),
+    };
+    Some((number(from, 0)?, number(to, u64::MAX)?))
+}
+
+// chip8 trace [-o <file>] [--binary] [--frames <n>] [--pc <from>-<to>]
+//             [--cycles <from>-<to>] [--seed <n>] [--rng <xorshift|vip>] <rom>
+pub(crate) fn trace(args: &[String]) -> Result<(), EmulatorError> {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => exit_usage(&e),
+    };
+
+    let mut config = config_for(&options.rom);
+    config.rng = options.rng;
+    config.seed = options.seed.unwrap_or_else(clock_seed);
+
+    let mut system = System::create(Framebuffer::create(), config);
+    system.load_rom(&options.rom)?;
+
+    let mut trace = match &options.output {
+        Some(path) => Trace::new(options.format, BufWriter::new(File::create(path)?)),
+        None => Trace::new(options.format, BufWriter::new(io::stdout())),
+    };
+    trace.set_addresses(options.addresses.0..=options.addresses.1);
+    trace.set_cycles(options.cycles.0..=options.cycles.1);
+    system.trace(trace)?;
+
+    // what was traced up to an error is still written out
+    let result = run(&mut system, &options);
+    system.stop_trace()?;
+    result
+}
+
+// run the frames asked for, stopping early if the program exits or the cycles
+// being traced are over
+fn run(system: &mut System<Framebuffer>, options: &Options) -> Result<(), EmulatorError> {
+    for _ in 0..options.frames {
+        if system.run_frame()?.exited || system.cycles() > options.cycles.1 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// chip8 tracediff <trace> <trace>
+pub(crate) fn tracediff(args: &[String]) -> Result<(), EmulatorError> {
+    let (a, b) = match args {
+        [a, b] => (a, b),
+        _ => exit_usage("tracediff needs two traces"),
+    };
+    let (ours, theirs) = (read_trace(&fs::read(a)?)?, read_trace(&fs::read(b)?)?);
+
+    let n = match first_difference(&ours, &theirs) {
+        Some(n) => n,
+        None => {
+            println!("the traces are the same for {} instructions", ours.len());
+            return Ok(());
+        }
+    };
+
+    // the instruction before is usually the one that went wrong
+    println!("the traces differ at instruction {}", n + 1);
+    if n > 0 {
+        println!("after\n  {}", ours[n - 1]);
+    }
+    for (name, trace) in [(a, &ours), (b, &theirs)] {
+        match trace.get(n) {
+            Some(line) => println!("{}\n  {}", name, line),
+            None => println!("{}\n  ends", name),
+        }
+    }
+    if let (Some(x), Some(y)) = (ours.get(n), theirs.get(n)) {
+        println!("{}", differences(x, y).join(", "));
+    }
+
+    // like diff, 1 when the traces differ
+    process::exit(1);
+}
+
+// what differs between two lines, e.g. `V3 00 / 01`
+fn differences(a: &TraceLine, b: &TraceLine) -> Vec<String> {
+    let (x, y) = (&a.registers, &b.registers);
+    let mut fields = vec![
+        ("PC".to_string(), x.pc, y.pc),
+        ("opcode".to_string(), a.opcode, b.opcode),
+        ("long".to_string(), a.long, b.long),
+    ];
+    for n in 0..16 {
+        fields.push((format!("V{:X}", n), x.v[n] as u16, y.v[n] as u16));
+    }
+    fields.push(("I".to_string(), x.i, y.i));
+    fields.push(("SP".to_string(), x.sp as u16, y.sp as u16));
+    fields.push(("DT".to_string(), x.dt as u16, y.dt as u16));
+    fields.push(("ST".to_string(), x.st as u16, y.st as u16));
+
+    fields
+        .into_iter()
+        .filter(|(_, a, b)| a != b)
+        .map(|(name, a, b)| format!("{} {:02X} / {:02X}", name, a, b))
+        .collect()
+}
@@ -0,0 +1,184 @@
+use emulator::{assemble, assemble_file, disassemble, EmulatorError, Syntax};
+
+use std::path::PathBuf;
+
+// the line, column and message of a source that doesn't assemble
+fn error(source: &str) -> (usize, usize, String) {
+    match assemble(source) {
+        Err(EmulatorError::Assemble {
+            line,
+            column,
+            message,
+            ..
+        }) => (line, column, message),
+        other => panic!("{:?} from {}", other, source),
+    }
+}
+
+#[test]
+fn instructions_and_data() {
+    let rom = assemble(
+        "CLS
+         LD V3, 0x1F
+         DRW V0, V1, 5
+         LD I, LONG 0x1234
+         SHR V2
+         db 1, 0b10, \"AB\"
+         dw 0xBEEF",
+    )
+    .unwrap()
+    .rom;
+    assert_eq!(
+        rom,
+        [
+            0x00, 0xE0, 0x63, 0x1F, 0xD0, 0x15, 0xF0, 0x00, 0x12, 0x34, 0x82, 0x26, 0x01, 0x02,
+            0x41, 0x42, 0xBE, 0xEF
+        ]
+    );
+}
+
+#[test]
+fn constants_and_expressions() {
+    let rom = assemble(
+        "speed = 3
+         fast equ speed * 2 + 1
+         LD V0, speed
+         LD V1, fast
+         LD V2, (1 << 4) | 0b11 ^ 1
+         LD V3, -1 & 0xFF
+         LD V4, ~0 & 7
+         LD V5, 100 / 7",
+    )
+    .unwrap()
+    .rom;
+    assert_eq!(
+        rom,
+        [0x60, 3, 0x61, 7, 0x62, 0x12, 0x63, 0xFF, 0x64, 7, 0x65, 14]
+    );
+}
+
+// labels can be used before they are defined, and every statement records the
+// line it came from
+#[test]
+fn labels_are_fixed_up() {
+    let program = assemble(
+        "start:
+             JP end
+             CALL sprite
+         sprite: db 0xF0, 0x90
+         end:
+             LD I, sprite
+             JP start",
+    )
+    .unwrap();
+    assert_eq!(
+        program.rom,
+        [0x12, 0x06, 0x22, 0x04, 0xF0, 0x90, 0xA2, 0x04, 0x12, 0x00]
+    );
+
+    let lines: Vec<(u16, usize)> = program.lines.iter().map(|l| (l.addr, l.line)).collect();
+    assert_eq!(
+        lines,
+        [(0x200, 2), (0x202, 3), (0x204, 4), (0x206, 6), (0x208, 7)]
+    );
+    assert_eq!(program.line_of(0x205).unwrap().line, 4);
+    assert_eq!(program.addr_of(&PathBuf::from("<source>"), 6), Some(0x206));
+}
+
+#[test]
+fn errors_point_at_the_problem() {
+    assert_eq!(
+        error("CLS\n  LD V0, nowhere"),
+        (2, 10, "nowhere isn't defined".to_string())
+    );
+    assert_eq!(error("LD V0, 256").0, 1);
+    assert!(error("LD V0, 256").2.contains("out of range"));
+    assert_eq!(
+        error("a = b\nb = a\nLD V0, a"),
+        (2, 5, "a is defined in terms of itself".to_string())
+    );
+    assert_eq!(error("x = 1\nx = 2").0, 2);
+    assert_eq!(error("LD V0 V1"), (1, 7, "expected a comma".to_string()));
+    assert_eq!(error("FOO V0").0, 1);
+    assert_eq!(error("db").2, "expected a value");
+    assert_eq!(error("LD V0, (1 + 2").2, "missing )");
+
+    // dividing by zero is an error at the /, not 0
+    assert_eq!(
+        error("LD V0, 4 / (2 - 2)"),
+        (1, 10, "division by zero".to_string())
+    );
+    assert_eq!(
+        error("zero = 0\nLD V0, 1 / zero"),
+        (2, 10, "division by zero".to_string())
+    );
+}
+
+#[test]
+fn includes() {
+    let dir = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(
+        dir.join("main.asm"),
+        "JP start\ninclude \"lib/font.asm\"\nstart: LD I, digit",
+    )
+    .unwrap();
+    std::fs::write(dir.join("lib/font.asm"), "digit:\n  db 0xF0, 0x90, 0xF0").unwrap();
+    std::fs::write(dir.join("self.asm"), "include \"self.asm\"").unwrap();
+    std::fs::write(dir.join("missing.asm"), "CLS\ninclude \"nope.asm\"").unwrap();
+
+    let program = assemble_file(dir.join("main.asm")).unwrap();
+    assert_eq!(program.rom, [0x12, 0x05, 0xF0, 0x90, 0xF0, 0xA2, 0x02]);
+    let font = program.line_of(0x202).unwrap();
+    assert_eq!(
+        (font.file.clone(), font.line),
+        (dir.join("lib/font.asm"), 2)
+    );
+
+    match assemble_file(dir.join("self.asm")) {
+        Err(EmulatorError::Assemble { message, .. }) => {
+            assert_eq!(message, "includes are nested too deeply")
+        }
+        other => panic!("{:?}", other),
+    }
+    match assemble_file(dir.join("missing.asm")) {
+        Err(EmulatorError::Assemble { file, line, .. }) => {
+            assert_eq!(
+                (file, line),
+                (dir.join("missing.asm").display().to_string(), 2)
+            )
+        }
+        other => panic!("{:?}", other),
+    }
+
+    std::fs::remove_dir_all(dir).ok();
+}
+
+// disassembling a rom and assembling the listing gives back the same rom
+#[test]
+fn round_trip_with_the_disassembler() {
+    let rom = assemble(
+        "start:
+             LD V0, 0
+             LD I, sprite
+         loop:
+             DRW V0, V1, 3
+             ADD V0, 8
+             SE V0, 64
+             JP loop
+             CALL sub
+             LD I, LONG sprite
+             JP start
+         sub:
+             SCD 4
+             PLANE 3
+             RET
+         sprite:
+             db 0xF0, 0x90, 0xF0, 0x12",
+    )
+    .unwrap()
+    .rom;
+
+    let listing = disassemble(&rom, Syntax::Cowgod);
+    assert_eq!(assemble(&listing).unwrap().rom, rom, "{}", listing);
+}
@@ -0,0 +1,414 @@
+use emulator::{assemble, Config, EmulatorError, Framebuffer, System};
+
+fn system(config: Config, source: &str) -> System<Framebuffer> {
+    let mut system = System::create(Framebuffer::create(), config);
+    system.load_rom_data(assemble(source).unwrap().rom).unwrap();
+    system
+}
+
+fn xochip(source: &str) -> System<Framebuffer> {
+    system(Config::xochip(), source)
+}
+
+// source run for n instructions
+fn run(config: Config, source: &str, n: usize) -> System<Framebuffer> {
+    let mut system = system(config, source);
+    for _ in 0..n {
+        system.step().unwrap();
+    }
+    system
+}
+
+// I and the PC wrap around the top of XO-CHIP's 64K of memory instead of
+// overflowing
+#[test]
+fn i_and_pc_wrap_at_the_top_of_memory() {
+    let mut system = xochip(
+        "LD I, LONG 0xFFFF
+         LD V0, 2
+         ADD I, V0",
+    );
+    for _ in 0..3 {
+        system.step().unwrap();
+    }
+    assert_eq!(system.registers().i, 1);
+
+    // SE V0, 2 in the last word but one skips past the end, back to 0
+    system.write_memory(0xFFFC, &[0x30, 0x02]).unwrap();
+    let mut r = system.registers();
+    r.pc = 0xFFFC;
+    system.set_registers(r);
+    system.step().unwrap();
+    assert_eq!(system.registers().pc, 0);
+}
+
+// the flag is written after the result, so VF as the destination ends up
+// holding the flag
+#[test]
+fn vf_as_the_destination_holds_the_flag() {
+    let vf = |source: &str| run(Config::chip8(), source, 3).registers().v[0xF];
+
+    assert_eq!(vf("LD VF, 200\nLD V1, 100\nADD VF, V1"), 1);
+    assert_eq!(vf("LD VF, 100\nLD V1, 100\nADD VF, V1"), 0);
+    assert_eq!(vf("LD VF, 200\nLD V1, 100\nSUB VF, V1"), 1);
+    assert_eq!(vf("LD VF, 100\nLD V1, 200\nSUB VF, V1"), 0);
+    assert_eq!(vf("LD VF, 100\nLD V1, 200\nSUBN VF, V1"), 1);
+    assert_eq!(vf("LD VF, 200\nLD V1, 100\nSUBN VF, V1"), 0);
+    assert_eq!(vf("LD VF, 0x81\nLD V1, 0\nSHR VF, VF"), 1);
+    assert_eq!(vf("LD VF, 0x40\nLD V1, 0\nSHL VF, VF"), 0);
+
+    // equal values don't borrow
+    assert_eq!(vf("LD VF, 7\nLD V1, 7\nSUB VF, V1"), 1);
+    assert_eq!(vf("LD VF, 7\nLD V1, 7\nSUBN VF, V1"), 1);
+
+    // and as the source the result is still worked out from it
+    let r = run(Config::chip8(), "LD V0, 10\nLD VF, 250\nADD V0, VF", 3).registers();
+    assert_eq!((r.v[0], r.v[0xF]), (4, 1));
+}
+
+#[test]
+fn shift_quirk() {
+    let source = "LD V1, 0x81\nLD V2, 0x06\nSHR V1, V2\nLD V3, 0x81\nSHL V3, V2";
+
+    // the VIP shifts Vy into Vx
+    let r = run(Config::chip8(), source, 5).registers();
+    assert_eq!((r.v[1], r.v[3], r.v[0xF]), (0x03, 0x0C, 0));
+
+    // SUPER-CHIP shifts Vx in place
+    let r = run(Config::superchip(), source, 5).registers();
+    assert_eq!((r.v[1], r.v[3], r.v[0xF]), (0x40, 0x02, 1));
+}
+
+#[test]
+fn load_store_quirk() {
+    let source = "LD I, 0x300
+                  LD V0, 1
+                  LD V1, 2
+                  LD V2, 3
+                  LD [I], V2
+                  LD I, 0x300
+                  LD V2, [I]";
+
+    // the VIP leaves I past the last register
+    let mut system = run(Config::chip8(), source, 5);
+    assert_eq!(system.registers().i, 0x303);
+    system.step().unwrap();
+    system.step().unwrap();
+    assert_eq!(system.registers().i, 0x303);
+    assert_eq!(system.memory()[0x300..0x304], [1, 2, 3, 0]);
+
+    // SUPER-CHIP leaves it alone
+    let mut system = run(Config::superchip(), source, 5);
+    assert_eq!(system.registers().i, 0x300);
+    system.step().unwrap();
+    system.step().unwrap();
+    assert_eq!(system.registers().i, 0x300);
+    assert_eq!(system.memory()[0x300..0x304], [1, 2, 3, 0]);
+}
+
+#[test]
+fn jump_quirk() {
+    let source = "LD V0, 4\nLD V2, 8\nJP V0, 0x220";
+
+    assert_eq!(run(Config::chip8(), source, 3).registers().pc, 0x224);
+    // Bxnn adds V2, the high nibble of 0x220
+    assert_eq!(run(Config::superchip(), source, 3).registers().pc, 0x228);
+}
+
+#[test]
+fn vf_reset_quirk() {
+    for op in ["OR", "AND", "XOR"] {
+        let source = format!("LD VF, 5\nLD V0, 3\n{} V0, V1", op);
+
+        assert_eq!(run(Config::chip8(), &source, 3).registers().v[0xF], 0);
+        assert_eq!(run(Config::superchip(), &source, 3).registers().v[0xF], 5);
+    }
+}
+
+#[test]
+fn clipping_quirk() {
+    // the top row of the 0 glyph is four pixels wide, drawn from the last
+    // column but one
+    let source = "LD V0, 62\nLD V1, 31\nLD V2, 0\nLD F, V2\nDRW V0, V1, 5";
+
+    // the VIP cuts it off at the right and bottom edges
+    let system = run(Config::chip8(), source, 5);
+    let (width, _) = system.resolution();
+    let vram = system.vram();
+    assert!(vram[31 * width + 62] != 0 && vram[31 * width + 63] != 0);
+    assert!(vram.iter().take(width * 31).all(|pixel| *pixel == 0));
+    assert_eq!(vram[31 * width], 0);
+
+    // Octo wraps it around to the left and top
+    let system = run(Config::xochip(), source, 5);
+    let (width, _) = system.resolution();
+    let vram = system.vram();
+    assert!(vram[31 * width + 62] != 0 && vram[31 * width] != 0 && vram[31 * width + 1] != 0);
+    assert!(vram[1] != 0 && vram[3 * width] != 0);
+}
+
+#[test]
+fn display_wait_quirk() {
+    let source = "DRW V0, V0, 1\nDRW V0, V0, 1\nLD V5, 1";
+
+    // the VIP draws once a frame, the second sprite waits for the next one
+    let mut system = run(Config::chip8(), source, 3);
+    assert_eq!(system.registers().pc, 0x202);
+    system.run_frame().unwrap();
+    system.step().unwrap();
+    system.step().unwrap();
+    assert_eq!(system.registers().v[5], 1);
+
+    // SUPER-CHIP draws straight away
+    let system = run(Config::superchip(), source, 3);
+    assert_eq!((system.registers().pc, system.registers().v[5]), (0x206, 1));
+}
+
+// the lit pixels, as (x, y)
+fn pixels(system: &System<Framebuffer>) -> Vec<(usize, usize)> {
+    let (width, _) = system.resolution();
+    let vram = system.vram();
+    (0..vram.len())
+        .filter(|i| vram[*i] != 0)
+        .map(|i| (i % width, i / width))
+        .collect()
+}
+
+// the top row of the small 0 glyph, four pixels from (x, y)
+fn row(x: usize, y: usize) -> Vec<(usize, usize)> {
+    (x..x + 4).map(|x| (x, y)).collect()
+}
+
+#[test]
+fn superchip_scrolling() {
+    let mut system = run(
+        Config::superchip(),
+        "HIGH
+         LD V0, 10
+         LD V1, 20
+         LD F, V2
+         DRW V0, V1, 1
+         SCD 3
+         SCR
+         SCL
+         SCL
+         SCL
+         SCL",
+        5,
+    );
+    assert_eq!(pixels(&system), row(10, 20));
+
+    let step = |system: &mut System<Framebuffer>| {
+        system.step().unwrap();
+        pixels(system)
+    };
+    assert_eq!(step(&mut system), row(10, 23));
+    assert_eq!(step(&mut system), row(14, 23));
+    assert_eq!(step(&mut system), row(10, 23));
+    assert_eq!(step(&mut system), row(6, 23));
+    assert_eq!(step(&mut system), row(2, 23));
+
+    // and off the edge, blank columns come in behind
+    assert_eq!(step(&mut system), [(0, 23), (1, 23)]);
+}
+
+#[test]
+fn superchip_resolution() {
+    let mut system = run(
+        Config::superchip(),
+        "LD F, V0
+         DRW V0, V0, 1
+         HIGH
+         DRW V0, V0, 1
+         LOW",
+        2,
+    );
+    assert_eq!(system.resolution(), (64, 32));
+    assert_eq!(pixels(&system), row(0, 0));
+
+    // switching clears the screen
+    system.step().unwrap();
+    assert_eq!(system.resolution(), (128, 64));
+    assert_eq!(system.vram().len(), 128 * 64);
+    assert!(pixels(&system).is_empty());
+    system.step().unwrap();
+    assert_eq!(pixels(&system), row(0, 0));
+
+    system.step().unwrap();
+    assert_eq!(system.resolution(), (64, 32));
+    assert!(pixels(&system).is_empty());
+
+    // Chip-8 doesn't have them
+    let mut system = run(Config::chip8(), "HIGH", 0);
+    assert!(matches!(
+        system.step(),
+        Err(EmulatorError::InvalidOpcode { opcode: 0x00FF, .. })
+    ));
+}
+
+#[test]
+fn superchip_big_font() {
+    let system = run(
+        Config::superchip(),
+        "HIGH
+         LD V0, 8
+         LD HF, V0
+         DRW V1, V1, 10",
+        4,
+    );
+    let i = system.registers().i as usize;
+    let eight = [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF];
+    assert_eq!(system.memory()[i..i + 10], eight);
+
+    // drawn eight pixels wide and ten high
+    let (width, _) = system.resolution();
+    for (y, bits) in eight.iter().enumerate() {
+        for x in 0..8 {
+            let lit = system.vram()[y * width + x] != 0;
+            assert_eq!(lit, bits & (0x80 >> x) != 0, "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn superchip_rpl_flags() {
+    let mut system = run(
+        Config::superchip(),
+        "LD V0, 1
+         LD V1, 2
+         LD V2, 3
+         LD V3, 4
+         LD R, V3
+         LD V0, 0
+         LD V1, 0
+         LD V2, 0
+         LD V3, 0
+         LD V2, R
+         LD R, V8",
+        10,
+    );
+    assert_eq!(system.registers().v[..4], [1, 2, 3, 0]);
+
+    // SUPER-CHIP only has 8 flags, XO-CHIP has 16
+    assert!(matches!(
+        system.step(),
+        Err(EmulatorError::InvalidOpcode { opcode: 0xF875, .. })
+    ));
+    let system = run(
+        Config::xochip(),
+        "LD VF, 9\nLD R, VF\nLD VF, 0\nLD VF, R",
+        4,
+    );
+    assert_eq!(system.registers().v[0xF], 9);
+}
+
+#[test]
+fn xochip_planes() {
+    let mut system = run(
+        Config::xochip(),
+        "LD F, V0
+         PLANE 2
+         DRW V0, V0, 1
+         PLANE 3
+         LD V1, 2
+         DRW V1, V0, 1
+         PLANE 1
+         CLS
+         PLANE 0
+         DRW V0, V0, 1",
+        3,
+    );
+    // each pixel is a bitmask of the planes it is lit in
+    let (width, _) = system.resolution();
+    assert_eq!(system.vram()[..4], [2, 2, 2, 2]);
+
+    // drawing to both planes at once takes a row for each plane, the glyph's
+    // second row 0x90 goes to plane 2 and toggles what is already there
+    for _ in 0..3 {
+        system.step().unwrap();
+    }
+    assert_eq!(system.vram()[..6], [2, 2, 1, 3, 1, 3]);
+
+    // clear only clears the selected planes, and no planes draws nothing
+    for _ in 0..2 {
+        system.step().unwrap();
+    }
+    assert_eq!(system.vram()[..6], [2, 2, 0, 2, 0, 2]);
+    for _ in 0..2 {
+        system.step().unwrap();
+    }
+    assert_eq!(system.vram()[..6], [2, 2, 0, 2, 0, 2]);
+    assert!(system.vram()[width..].iter().all(|pixel| *pixel == 0));
+}
+
+#[test]
+fn xochip_long_i() {
+    let system = run(Config::xochip(), "LD I, LONG 0x1234\nLD V0, 1", 2);
+    assert_eq!(system.registers().i, 0x1234);
+    assert_eq!(system.registers().pc, 0x206);
+
+    // skipping over it skips all four bytes
+    let system = run(
+        Config::xochip(),
+        "SE V0, 0
+         LD I, LONG 0x1234
+         LD V0, 1",
+        2,
+    );
+    assert_eq!(system.registers().i, 0);
+    assert_eq!(system.registers().v[0], 1);
+}
+
+#[test]
+fn xochip_save_and_load_ranges() {
+    let mut system = run(
+        Config::xochip(),
+        "LD V1, 1
+         LD V2, 2
+         LD V3, 3
+         LD I, 0x300
+         SAVE V1, V3
+         LD I, 0x310
+         SAVE V3, V1
+         LD I, 0x300
+         LOAD V6, V4",
+        7,
+    );
+    // I is left where it was, a range can run backwards
+    assert_eq!(system.registers().i, 0x310);
+    assert_eq!(system.memory()[0x300..0x304], [1, 2, 3, 0]);
+    assert_eq!(system.memory()[0x310..0x314], [3, 2, 1, 0]);
+
+    system.step().unwrap();
+    system.step().unwrap();
+    let r = system.registers();
+    assert_eq!((r.v[6], r.v[5], r.v[4], r.i), (1, 2, 3, 0x300));
+
+    // Chip-8 doesn't have them
+    let mut system = run(Config::chip8(), "SAVE V1, V3", 0);
+    assert!(matches!(
+        system.step(),
+        Err(EmulatorError::InvalidOpcode { opcode: 0x5132, .. })
+    ));
+}
+
+#[test]
+fn xochip_audio_pattern() {
+    let system = run(
+        Config::xochip(),
+        "LD I, pattern
+         AUDIO
+         LD V0, 112
+         LD PITCH, V0
+         JP pattern
+     pattern:
+         db 0xFF, 0x00, 0xFF, 0x00, 0xF0, 0x0F, 0xAA, 0x55
+         db 1, 2, 3, 4, 5, 6, 7, 8",
+        4,
+    );
+    assert_eq!(
+        *system.audio_pattern(),
+        [0xFF, 0x00, 0xFF, 0x00, 0xF0, 0x0F, 0xAA, 0x55, 1, 2, 3, 4, 5, 6, 7, 8]
+    );
+    assert_eq!(system.pitch(), 112);
+}
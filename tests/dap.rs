@@ -0,0 +1,532 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+// the line and address of each instruction are in its comment
+const ASM: &str = "start:
+    LD V0, 5        ; 2 200
+    CALL sub        ; 3 202
+    ADD V0, 1       ; 4 204
+    LD I, 0x300     ; 5 206
+    JP start        ; 6 208
+sub:
+    LD V1, 7        ; 8 20A
+    ADD V1, 1       ; 9 20C
+    RET             ; 10 20E
+";
+
+// the same program in Octo, after a jump to main at 200
+const OCTO: &str = ": sub
+    v1 := 7         # 2 202
+    v1 += 1         # 3 204
+;                   # 4 206
+: main
+    v0 := 5         # 6 208
+    sub             # 7 20A
+    v0 += 1         # 8 20C
+    jump main       # 9 20E
+";
+
+// a debug adapter protocol client talking to `chip8 dap` over its stdin and
+// stdout, the way an editor would. Messages are checked as the text the server
+// sends, which has its fields in a fixed order.
+struct Client {
+    server: Child,
+    input: ChildStdin,
+    messages: Receiver<String>,
+    // events that came while waiting for a response
+    events: VecDeque<String>,
+    seq: usize,
+    dir: PathBuf,
+}
+
+impl Client {
+    // start the server with files to launch from in a directory of their own
+    fn start(name: &str, files: &[(&str, &str)]) -> Client {
+        let dir = std::env::temp_dir().join(format!("chip8-dap-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            std::fs::write(dir.join(file), text).unwrap();
+        }
+
+        let mut server = Command::new(env!("CARGO_BIN_EXE_chip8"))
+            .arg("dap")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let input = server.stdin.take().unwrap();
+        let mut output = BufReader::new(server.stdout.take().unwrap());
+
+        // read messages on their own thread so a server that goes quiet fails
+        // the test instead of hanging it
+        let (send, messages) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut len = 0;
+            loop {
+                let mut line = String::new();
+                if output.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                match line.trim_end().strip_prefix("Content-Length: ") {
+                    Some(value) => len = value.parse().unwrap(),
+                    None if line.trim_end().is_empty() => break,
+                    None => panic!("unexpected header {}", line),
+                }
+            }
+
+            let mut body = vec![0; len];
+            output.read_exact(&mut body).unwrap();
+            if send.send(String::from_utf8(body).unwrap()).is_err() {
+                return;
+            }
+        });
+
+        Client {
+            server,
+            input,
+            messages,
+            events: VecDeque::new(),
+            seq: 0,
+            dir,
+        }
+    }
+
+    fn path(&self, file: &str) -> String {
+        let path = std::fs::canonicalize(self.dir.join(file)).unwrap();
+        json_string(&path.to_string_lossy())
+    }
+
+    fn send_raw(&mut self, body: &[u8]) {
+        write!(self.input, "Content-Length: {}\r\n\r\n", body.len()).unwrap();
+        self.input.write_all(body).unwrap();
+        self.input.flush().unwrap();
+    }
+
+    fn next(&mut self) -> String {
+        self.messages
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no message from the server")
+    }
+
+    // send a request and return its response
+    fn request(&mut self, command: &str, arguments: &str) -> String {
+        self.seq += 1;
+        let body = format!(
+            r#"{{"seq":{},"type":"request","command":"{}","arguments":{}}}"#,
+            self.seq, command, arguments
+        );
+        self.send_raw(body.as_bytes());
+
+        loop {
+            let message = self.next();
+            if message.contains(r#""type":"event""#) {
+                self.events.push_back(message);
+                continue;
+            }
+
+            let start = format!(r#""type":"response","request_seq":{},"#, self.seq);
+            assert!(message.contains(&start), "{} for {}", message, body);
+            return message;
+        }
+    }
+
+    // the next event, which has to be the one named
+    fn event(&mut self, event: &str) -> String {
+        let message = match self.events.pop_front() {
+            Some(message) => message,
+            None => self.next(),
+        };
+        assert!(
+            message.contains(&format!(r#""type":"event","event":"{}""#, event)),
+            "{} instead of {}",
+            message,
+            event
+        );
+        message
+    }
+
+    // the stopped event after a step or continue, returning why it stopped
+    fn stopped(&mut self) -> String {
+        let event = self.event("stopped");
+        let reason = event.split(r#""reason":""#).nth(1).unwrap();
+        reason[..reason.find('"').unwrap()].to_string()
+    }
+
+    // the source line the program is stopped at
+    fn line(&mut self) -> usize {
+        let trace = self.request("stackTrace", r#"{"threadId":1}"#);
+        let line = trace.split(r#""line":"#).nth(1).unwrap();
+        line[..line.find(',').unwrap()].parse().unwrap()
+    }
+
+    // disconnect and return what the server wrote to stderr
+    fn disconnect(mut self) -> String {
+        let response = self.request("disconnect", "{}");
+        assert!(response.contains(r#""success":true"#), "{}", response);
+        assert!(self.server.wait().unwrap().success());
+
+        let mut errors = String::new();
+        let mut stderr = self.server.stderr.take().unwrap();
+        stderr.read_to_string(&mut errors).unwrap();
+        errors
+    }
+}
+
+// the server isn't left running and the files go, whether the test passed or
+// not
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.server.kill().ok();
+        self.server.wait().ok();
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+fn json_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// initialize and launch file stopped on entry
+fn launch(name: &str, file: &str, text: &str) -> Client {
+    let mut dap = Client::start(name, &[(file, text)]);
+
+    let response = dap.request("initialize", r#"{"adapterID":"chip8"}"#);
+    assert!(
+        response.contains(r#""supportsStepBack":true"#),
+        "{}",
+        response
+    );
+
+    let arguments = format!(
+        r#"{{"program":{},"headless":true,"stopOnEntry":true}}"#,
+        dap.path(file)
+    );
+    let response = dap.request("launch", &arguments);
+    assert!(response.contains(r#""success":true"#), "{}", response);
+    dap.event("initialized");
+    dap
+}
+
+fn set_breakpoints(dap: &mut Client, lines: &[usize]) -> String {
+    let lines: Vec<String> = lines
+        .iter()
+        .map(|line| format!(r#"{{"line":{}}}"#, line))
+        .collect();
+    let arguments = format!(
+        r#"{{"source":{{"path":{}}},"breakpoints":[{}]}}"#,
+        dap.path("prog.asm"),
+        lines.join(",")
+    );
+    dap.request("setBreakpoints", &arguments)
+}
+
+#[test]
+fn source_breakpoints_and_stepping() {
+    let mut dap = launch("stepping", "prog.asm", ASM);
+
+    let response = set_breakpoints(&mut dap, &[9, 1, 100]);
+    assert!(
+        response.contains(
+            r#""breakpoints":[{"verified":true,"line":9,"instructionReference":"0x20C"},{"verified":false,"line":1,"message":"no code on this line"},{"verified":false,"line":100,"message":"no code on this line"}]"#
+        ),
+        "{}",
+        response
+    );
+    dap.request("configurationDone", "{}");
+    assert_eq!(dap.stopped(), "entry");
+    assert_eq!(dap.line(), 2);
+
+    // a step over the call stops at the breakpoint inside it
+    dap.request("next", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 3));
+    dap.request("next", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("breakpoint".to_string(), 9));
+
+    dap.request("stepOut", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 4));
+
+    // back into the subroutine, a line and then an instruction at a time
+    dap.request("stepBack", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 10));
+    let instruction = r#"{"threadId":1,"granularity":"instruction"}"#;
+    dap.request("stepBack", instruction);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 9));
+    dap.request("stepIn", instruction);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 10));
+
+    // around the loop and back to the breakpoint
+    let response = dap.request("continue", r#"{"threadId":1}"#);
+    assert!(
+        response.contains(r#""allThreadsContinued":true"#),
+        "{}",
+        response
+    );
+    assert_eq!((dap.stopped(), dap.line()), ("breakpoint".to_string(), 9));
+
+    // without breakpoints stepping over the call runs all of it
+    set_breakpoints(&mut dap, &[]);
+    dap.request("stepOut", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 4));
+    for line in [5, 6, 2, 3, 4] {
+        dap.request("next", r#"{"threadId":1}"#);
+        assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), line));
+    }
+
+    dap.disconnect();
+}
+
+#[test]
+fn instruction_breakpoints() {
+    let mut dap = launch("instructions", "prog.asm", ASM);
+
+    let response = dap.request(
+        "setInstructionBreakpoints",
+        r#"{"breakpoints":[
+            {"instructionReference":"0x206"},
+            {"instructionReference":"0x200","offset":10,"condition":"v0 == 5"},
+            {"instructionReference":"nowhere"},
+            {"instructionReference":"0x20C","condition":"v0 =="}
+        ]}"#,
+    );
+    assert!(
+        response.contains(r#""breakpoints":[{"verified":true,"instructionReference":"0x206"},{"verified":true,"instructionReference":"0x20A"},{"verified":false,"message":"not an address"},{"verified":false,"message":"#),
+        "{}",
+        response
+    );
+    dap.request("configurationDone", "{}");
+    assert_eq!(dap.stopped(), "entry");
+
+    // the condition holds the first time the subroutine is called
+    dap.request("continue", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("breakpoint".to_string(), 8));
+    dap.request("continue", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("breakpoint".to_string(), 5));
+    // and again each time round the loop
+    dap.request("continue", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("breakpoint".to_string(), 8));
+
+    dap.disconnect();
+}
+
+#[test]
+fn variables() {
+    let mut dap = launch("variables", "prog.asm", ASM);
+    dap.request("configurationDone", "{}");
+    dap.stopped();
+    for _ in 0..2 {
+        dap.request("stepIn", r#"{"threadId":1}"#);
+        dap.stopped();
+    }
+
+    let response = dap.request("scopes", r#"{"frameId":0}"#);
+    assert!(
+        response.contains(r#""scopes":[{"name":"Registers","variablesReference":1,"expensive":false},{"name":"Timers","variablesReference":2,"expensive":false},{"name":"Stack","variablesReference":3,"expensive":false}]"#),
+        "{}",
+        response
+    );
+
+    let registers = dap.request("variables", r#"{"variablesReference":1}"#);
+    for variable in [
+        r#"{"name":"V0","value":"0x05","variablesReference":0}"#,
+        r#"{"name":"VF","value":"0x00","variablesReference":0}"#,
+        r#"{"name":"PC","value":"0x20A","variablesReference":0,"memoryReference":"0x20A"}"#,
+        r#"{"name":"SP","value":"1","variablesReference":0}"#,
+    ] {
+        assert!(
+            registers.contains(variable),
+            "{} in {}",
+            variable,
+            registers
+        );
+    }
+    let timers = dap.request("variables", r#"{"variablesReference":2}"#);
+    assert!(
+        timers.contains(r#"{"name":"DT","value":"60","variablesReference":0}"#),
+        "{}",
+        timers
+    );
+    let stack = dap.request("variables", r#"{"variablesReference":3}"#);
+    assert!(
+        stack.contains(r##""variables":[{"name":"#1","value":"0x204","variablesReference":0,"memoryReference":"0x204"}]"##),
+        "{}",
+        stack
+    );
+
+    let set = |dap: &mut Client, reference: i64, name: &str, value: &str| {
+        let arguments = format!(
+            r#"{{"variablesReference":{},"name":"{}","value":"{}"}}"#,
+            reference, name, value
+        );
+        dap.request("setVariable", &arguments)
+    };
+    assert!(set(&mut dap, 1, "V3", "0x42")
+        .contains(r#""success":true,"command":"setVariable","body":{"value":"0x42"}"#));
+    assert!(set(&mut dap, 1, "I", "768").contains(r#""body":{"value":"0x300"}"#));
+    assert!(set(&mut dap, 2, "ST", "0x10").contains(r#""body":{"value":"16"}"#));
+    let registers = dap.request("variables", r#"{"variablesReference":1}"#);
+    assert!(
+        registers.contains(r#"{"name":"V3","value":"0x42","variablesReference":0}"#),
+        "{}",
+        registers
+    );
+    assert!(
+        registers.contains(r#"{"name":"I","value":"0x300""#),
+        "{}",
+        registers
+    );
+
+    for (reference, name, value, message) in [
+        (1, "V0", "256", "256 doesn't fit in a byte"),
+        (1, "PC", "0x10000", "0x10000 doesn't fit in 16 bits"),
+        (1, "V0", "five", "five isn't a number"),
+        (1, "VG", "1", "there is no register VG"),
+        (1, "DT", "1", "there is no register DT"),
+        (3, "#1", "0x206", "#1 can't be changed"),
+    ] {
+        let response = set(&mut dap, reference, name, value);
+        let failed = format!(
+            r#""success":false,"command":"setVariable","message":"{}""#,
+            message
+        );
+        assert!(response.contains(&failed), "{}", response);
+    }
+
+    dap.disconnect();
+}
+
+#[test]
+fn read_memory() {
+    let mut dap = launch("memory", "prog.asm", ASM);
+
+    let read = |dap: &mut Client, arguments: &str| {
+        let response = dap.request("readMemory", arguments);
+        let body = response.split(r#""body":"#).nth(1).unwrap_or(&response);
+        body.to_string()
+    };
+    assert_eq!(
+        read(&mut dap, r#"{"memoryReference":"0x200","count":4}"#),
+        r#"{"address":"0x200","data":"YAUiCg==","unreadableBytes":0}}"#
+    );
+    assert_eq!(
+        read(
+            &mut dap,
+            r#"{"memoryReference":"0x200","offset":-2,"count":4}"#
+        ),
+        r#"{"address":"0x1FE","data":"AABgBQ==","unreadableBytes":0}}"#
+    );
+
+    // past either end of memory there is nothing to read
+    assert_eq!(
+        read(&mut dap, r#"{"memoryReference":"0xFFE","count":4}"#),
+        r#"{"address":"0xFFE","data":"AAA=","unreadableBytes":2}}"#
+    );
+    assert_eq!(
+        read(
+            &mut dap,
+            r#"{"memoryReference":"0x200","offset":100000,"count":3}"#
+        ),
+        r#"{"address":"0x188A0","data":"","unreadableBytes":3}}"#
+    );
+    assert_eq!(
+        read(
+            &mut dap,
+            r#"{"memoryReference":"0","offset":-10,"count":4}"#
+        ),
+        r#"{"address":"0x000","data":"","unreadableBytes":4}}"#
+    );
+    assert_eq!(
+        read(&mut dap, r#"{"memoryReference":"0x200","count":-5}"#),
+        r#"{"address":"0x200","data":"","unreadableBytes":0}}"#
+    );
+    let huge = read(
+        &mut dap,
+        r#"{"memoryReference":"0xFFF","count":9007199254740991}"#,
+    );
+    assert!(
+        huge.contains(r#""data":"AA==","unreadableBytes":9007199254740990"#),
+        "{}",
+        huge
+    );
+
+    let response = dap.request("readMemory", r#"{"count":4}"#);
+    assert!(response.contains(r#""success":false,"command":"readMemory","message":"readMemory needs a memory reference""#), "{}", response);
+
+    dap.disconnect();
+}
+
+#[test]
+fn octo_source() {
+    let mut dap = launch("octo", "prog.8o", OCTO);
+    let arguments = format!(
+        r#"{{"source":{{"path":{}}},"breakpoints":[{{"line":8}}]}}"#,
+        dap.path("prog.8o")
+    );
+    let response = dap.request("setBreakpoints", &arguments);
+    assert!(
+        response.contains(r#"{"verified":true,"line":8,"instructionReference":"0x20C"}"#),
+        "{}",
+        response
+    );
+    dap.request("configurationDone", "{}");
+    assert_eq!(dap.stopped(), "entry");
+
+    dap.request("next", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 6));
+    dap.request("next", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 7));
+    dap.request("stepIn", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 2));
+    // the step ends on the line with the breakpoint before it is hit
+    dap.request("stepOut", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("step".to_string(), 8));
+    dap.request("continue", r#"{"threadId":1}"#);
+    assert_eq!((dap.stopped(), dap.line()), ("breakpoint".to_string(), 8));
+
+    dap.disconnect();
+}
+
+// messages that aren't JSON are reported on stderr and skipped, the server
+// carries on with the next one
+#[test]
+fn malformed_messages() {
+    let mut dap = Client::start("malformed", &[]);
+
+    dap.send_raw(br#"{"seq":1,"type":"request""#);
+    dap.send_raw(b"not json");
+    dap.send_raw(br#"{"seq":2,"command":"a\q"}"#);
+    dap.send_raw(br#"{"seq":3,"command":"\u12"}"#);
+    dap.send_raw(br#"{"seq":4,"command":[1,2}"#);
+    dap.send_raw(br#"{"seq":5,"command":"unterminated}"#);
+    dap.send_raw(b"{\"seq\":6,\"command\":\"\xFF\"}");
+    dap.send_raw(br#"{"seq":7} {}"#);
+    dap.send_raw(b"");
+
+    // strings are echoed back in the response, surrogate pairs make one
+    // character and half a pair a replacement character
+    let response = dap.request(r"\ud83d\ude00 é \ud83d \ude00 \ud83d\u0041", "{}");
+    assert!(
+        response.contains("\"command\":\"\u{1F600} \u{E9} \u{FFFD} \u{FFFD} \u{FFFD}A\",\"message\":\"launch a program first\""),
+        "{}",
+        response
+    );
+
+    let errors = dap.disconnect();
+    let expected = [
+        "bad message: expected } at 25",
+        "bad message: unexpected text at 0",
+        "bad message: invalid escape at 21",
+        "bad message: invalid \\u escape",
+        "bad message: expected ] at 23",
+        "bad message: unterminated string",
+        "bad message: a message isn't utf-8",
+        "bad message: unexpected text at 10",
+        "bad message: expected a value at 0",
+    ];
+    assert_eq!(errors.lines().collect::<Vec<_>>(), expected, "{}", errors);
+}
@@ -0,0 +1,284 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// LD V0, 0x7B; LD I, 0x300; LD B, V0; LD V2, [I]; ADD V0, 1; JP 0x200
+const ROM: [u8; 12] = [
+    0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33, 0xF2, 0x65, 0x70, 0x01, 0x12, 0x00,
+];
+
+// run `chip8 debug` on ROM with commands as its input, and return what it
+// printed for each of them. The first reply is the PC shown at the start.
+fn debug(name: &str, commands: &[&str]) -> Vec<String> {
+    let rom = std::env::temp_dir().join(format!("chip8-debug-{}-{}.ch8", name, std::process::id()));
+    std::fs::write(&rom, ROM).unwrap();
+
+    let mut debugger = Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .args(["debug", "--headless"])
+        .arg(&rom)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the debugger quits at the end of its input
+    let mut input = debugger.stdin.take().unwrap();
+    input.write_all(commands.join("\n").as_bytes()).unwrap();
+    drop(input);
+    let output = debugger.wait_with_output().unwrap();
+    std::fs::remove_file(rom).ok();
+
+    assert!(output.status.success());
+    // every reply is followed by a prompt, unless it quit
+    let output = String::from_utf8(output.stdout).unwrap();
+    output
+        .strip_suffix("(chip8) ")
+        .unwrap_or(&output)
+        .split("(chip8) ")
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn breakpoints_and_conditions() {
+    let replies = debug(
+        "breakpoints",
+        &[
+            "break 204",
+            "b 204",
+            "break 208 if v0 == 0x7B",
+            "break if [0x300] > 0",
+            "break",
+            "delete 204",
+            "d 204",
+            "delete #1",
+            "delete #1",
+            "delete #x",
+            "break",
+        ],
+    );
+    assert_eq!(replies[0], "=> 200  607B       LD V0, 0x7B\n");
+    assert_eq!(
+        replies[1..],
+        [
+            "breakpoint at 204\n",
+            "there already is a breakpoint at 204\n",
+            // an address goes into the condition
+            "#1 if pc == 0x208 && (v0 == 0x7B)\n",
+            "#2 if [0x300] > 0\n",
+            "breakpoint at 204\n#1 if pc == 0x208 && (v0 == 0x7B)\n#2 if [0x300] > 0\n",
+            "",
+            "no breakpoint at 204\n",
+            "",
+            "no watchpoint or condition #1\n",
+            "invalid number #x\n",
+            "#2 if [0x300] > 0\n",
+        ]
+    );
+}
+
+#[test]
+fn watchpoints() {
+    let replies = debug(
+        "watch",
+        &[
+            "watch 300",
+            "watch 300 3",
+            "watch read 0x302",
+            "watch access 1FF 2",
+            "watch access",
+            "watch 300 1 2",
+            "watch 300 0",
+            "watch 300 65536",
+            "watch 30g",
+            "break",
+            "delete #2",
+        ],
+    );
+    assert_eq!(
+        replies[1..],
+        [
+            // write is the default
+            "#1 watch write 300\n",
+            "#2 watch write 300-302\n",
+            "#3 watch read 302\n",
+            "#4 watch access 1FF-200\n",
+            "watch needs an address and maybe a length\n",
+            "watch needs an address and maybe a length\n",
+            "a watchpoint needs 1 to FFFF bytes\n",
+            "a watchpoint needs 1 to FFFF bytes\n",
+            "invalid hex number 30g\n",
+            "#1 watch write 300\n#2 watch write 300-302\n#3 watch read 302\n\
+             #4 watch access 1FF-200\n",
+            "",
+        ]
+    );
+}
+
+#[test]
+fn memory_and_disassembly() {
+    let replies = debug(
+        "memory",
+        &[
+            "x 200 4",
+            "x 200 17",
+            "x FFE",
+            "x 1000",
+            "x zz",
+            "x 200 many",
+            "dis 202 2",
+            "dis",
+            "dis 200 x",
+            "dis FFE 5",
+        ],
+    );
+    assert_eq!(
+        replies[1..],
+        [
+            "200  60 7B A3 00\n",
+            "200  60 7B A3 00 F0 33 F2 65 70 01 12 00 00 00 00 00\n210  00\n",
+            // lengths stop at the end of memory
+            "FFE  00 00\n",
+            "",
+            "invalid hex number zz\n",
+            "invalid count many\n",
+            "   202  A300       LD I, 0x300\n   204  F033       LD B, V0\n",
+            // a few instructions back from the PC
+            "   1F8  0000       not an instruction\n   1FA  0000       not an instruction\n\
+             \x20  1FC  0000       not an instruction\n   1FE  0000       not an instruction\n\
+             => 200  607B       LD V0, 0x7B\n   202  A300       LD I, 0x300\n\
+             \x20  204  F033       LD B, V0\n   206  F265       LD V2, [I]\n\
+             \x20  208  7001       ADD V0, 0x01\n   20A  1200       JP 0x200\n",
+            "invalid count x\n",
+            "   FFE  0000       not an instruction\n",
+        ]
+    );
+}
+
+#[test]
+fn set_and_poke() {
+    let replies = debug(
+        "set",
+        &[
+            "set v3 42",
+            "set VF 0xff",
+            "set i 0x123",
+            "set pc 202",
+            "set dt 0",
+            "set st 0",
+            "regs",
+            "set vg 1",
+            "set v10 1",
+            "set v0 100",
+            "set sp zz",
+            "set i",
+            "poke 300 1 2 FF",
+            "x 300 3",
+            "poke 300 100",
+            "poke FFF 1 2",
+            "poke 300",
+            "x 300 3",
+        ],
+    );
+    assert_eq!(replies[1..7], ["", "", "", "", "", ""]);
+    assert_eq!(
+        replies[7..],
+        [
+            "V0 00  V1 00  V2 00  V3 42  V4 00  V5 00  V6 00  V7 00\n\
+             V8 00  V9 00  VA 00  VB 00  VC 00  VD 00  VE 00  VF FF\n\
+             I 0123  PC 202  SP 0  DT 00  ST 00\n",
+            "there is no register vg\n",
+            "there is no register v10\n",
+            "100 doesn't fit in a byte\n",
+            "invalid hex number zz\n",
+            "don't know how to set i, type help for the commands\n",
+            "",
+            "300  01 02 FF\n",
+            // nothing is written when any of it is refused
+            "100 doesn't fit in a byte\n",
+            "memory access out of bounds: 2 byte(s) at address 0xfff\n",
+            "don't know how to poke 300, type help for the commands\n",
+            "300  01 02 FF\n",
+        ]
+    );
+}
+
+#[test]
+fn keys() {
+    let replies = debug(
+        "keys",
+        &[
+            // LD V5, K at 0x310
+            "poke 310 F5 0A",
+            "set pc 310",
+            "step",
+            "regs",
+            "press 7",
+            "step",
+            "release 7",
+            "step",
+            "regs",
+            "press 10",
+            "release x",
+            "press",
+        ],
+    );
+    assert!(
+        replies[4].ends_with("waiting for a key to store in V5\n"),
+        "{}",
+        replies[4]
+    );
+    assert!(
+        replies[9].starts_with("V0 00  V1 00  V2 00  V3 00  V4 00  V5 07"),
+        "{}",
+        replies[9]
+    );
+    assert!(!replies[9].contains("waiting"), "{}", replies[9]);
+    assert_eq!(
+        replies[10..],
+        [
+            "there is no key 10\n",
+            "invalid hex number x\n",
+            "don't know how to press, type help for the commands\n",
+        ]
+    );
+}
+
+#[test]
+fn bad_commands() {
+    let replies = debug(
+        "errors",
+        &[
+            "frobnicate now",
+            "",
+            "step 2x",
+            "back many",
+            "break 2g0",
+            "break 2g0 if v0 == 1",
+            "break if v0 ==",
+            "break 200 if",
+            "regs now",
+            "help",
+            "quit",
+            "regs",
+        ],
+    );
+    assert_eq!(
+        replies[1..9],
+        [
+            "don't know how to frobnicate now, type help for the commands\n",
+            "",
+            "invalid count 2x\n",
+            "invalid count many\n",
+            "invalid hex number 2g0\n",
+            "invalid hex number 2g0\n",
+            "invalid condition: expected a number, register or [ at column 6\n",
+            "if needs a condition\n",
+        ]
+    );
+    assert_eq!(
+        replies[9],
+        "don't know how to regs now, type help for the commands\n"
+    );
+    assert!(replies[10].starts_with("commands:\n"));
+    // nothing is read after quit
+    assert_eq!(replies.len(), 11);
+}
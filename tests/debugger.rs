@@ -0,0 +1,296 @@
+use emulator::{
+    assemble, Access, Condition, Config, Debugger, EmulatorError, Framebuffer, MemoryWrite, Speed,
+    Stop, System, Watch, Watchpoint,
+};
+
+// count up in V0, write the count to 0x300 and read it back with the byte
+// after it. XO-CHIP moves I past what it stores, so it is set each time.
+const ROM: &str = "
+loop:
+    LD I, 0x300
+    ADD V0, 1
+    LD [I], V0
+    LD I, 0x300
+    LD V1, [I]
+    JP loop";
+
+fn system() -> System<Framebuffer> {
+    let config = Config {
+        speed: Speed::InstructionsPerFrame(100),
+        ..Config::xochip()
+    };
+    let mut system = System::create(Framebuffer::create(), config);
+    system.load_rom_data(assemble(ROM).unwrap().rom).unwrap();
+    system
+}
+
+#[test]
+fn conditions_are_worked_out_like_c() {
+    let mut system = system();
+    let mut r = system.registers();
+    r.pc = 0x2A4;
+    r.i = 0x300;
+    r.v[3] = 11;
+    r.v[0xA] = 2;
+    r.v[0xF] = 3;
+    system.set_registers(r);
+    system.write_memory(0x300, &[0xFF]).unwrap();
+
+    let value = |text: &str| Condition::parse(text).unwrap().value(&system);
+    assert_eq!(value("pc == 0x2A4 && V3 > 10 && [I] == 0xFF"), 1);
+    assert_eq!(value("pc == 0x2A4 && V3 > 11"), 0);
+    assert_eq!(value("va + VF * 2"), 8);
+    assert_eq!(value("(1 + 2) * 3"), 9);
+    assert_eq!(value("1 | 2 == 2"), 1);
+    assert_eq!(value("1 << 4 >> 2"), 4);
+    assert_eq!(value("5 > 3 == 1"), 1);
+    assert_eq!(value("0 || 0b10"), 1);
+    assert_eq!(value("3 && 0"), 0);
+    assert_eq!(value("-5 % 3"), -2);
+    assert_eq!(value("!0 + ~0"), 0);
+    assert_eq!(value("i - 1"), 0x2FF);
+
+    // dividing by zero and memory past the end are 0
+    assert_eq!(value("7 / 0"), 0);
+    assert_eq!(value("7 % 0"), 0);
+    assert_eq!(value("[0x10000]"), 0);
+
+    assert!(Condition::parse("[i] == 255").unwrap().is_true(&system));
+    assert_eq!(
+        Condition::parse("  V0 == 1 ").unwrap().to_string(),
+        "V0 == 1"
+    );
+}
+
+#[test]
+fn bad_conditions_say_where() {
+    let error = |text: &str| match Condition::parse(text) {
+        Err(EmulatorError::InvalidCondition(reason)) => reason,
+        other => panic!("{:?} from {}", other, text),
+    };
+
+    assert_eq!(
+        error("V0 =="),
+        "expected a number, register or [ at column 6"
+    );
+    assert_eq!(error("vg == 1"), "there is no register vg at column 1");
+    assert_eq!(error("(1"), "expected ) at column 3");
+    assert_eq!(error("[i"), "expected ] at column 3");
+    assert_eq!(error("1 2"), "expected an operator at column 3");
+    assert_eq!(error("0xZZ"), "0xZZ isn't a number or register at column 1");
+}
+
+#[test]
+fn watchpoints_stop_after_the_access() {
+    let mut system = system();
+    let mut debugger = Debugger::new();
+
+    let write = debugger.add_watchpoint(Watchpoint {
+        addr: 0x300,
+        len: 1,
+        watch: Watch::Write,
+    });
+    assert_eq!(
+        debugger.run_frame(&mut system).unwrap(),
+        Some(Stop::Watchpoint {
+            id: write,
+            access: Access {
+                addr: 0x300,
+                len: 1,
+                write: true
+            },
+            pc: 0x204
+        })
+    );
+    assert_eq!(system.registers().pc, 0x206);
+    assert!(debugger.remove_watchpoint(write));
+
+    // LD V1, [I] reads 0x300 and 0x301
+    let read = debugger.add_watchpoint(Watchpoint {
+        addr: 0x301,
+        len: 1,
+        watch: Watch::Read,
+    });
+    assert_eq!(
+        debugger.run_frame(&mut system).unwrap(),
+        Some(Stop::Watchpoint {
+            id: read,
+            access: Access {
+                addr: 0x300,
+                len: 2,
+                write: false
+            },
+            pc: 0x208
+        })
+    );
+    debugger.remove_watchpoint(read);
+
+    // nothing writes past 0x300
+    debugger.add_watchpoint(Watchpoint {
+        addr: 0x301,
+        len: 0x10,
+        watch: Watch::Write,
+    });
+    assert_eq!(debugger.run_frame(&mut system).unwrap(), None);
+
+    let any = debugger.add_watchpoint(Watchpoint {
+        addr: 0x2FF,
+        len: 2,
+        watch: Watch::Access,
+    });
+    assert!(matches!(
+        debugger.run_frame(&mut system).unwrap(),
+        Some(Stop::Watchpoint { id, .. }) if id == any
+    ));
+}
+
+#[test]
+fn conditions_stop_after_the_instruction_making_them_true() {
+    let mut system = system();
+    let mut debugger = Debugger::new();
+
+    let id = debugger.add_condition(Condition::parse("V0 == 5").unwrap());
+    assert_eq!(
+        debugger.run_frame(&mut system).unwrap(),
+        Some(Stop::Condition { id, pc: 0x202 })
+    );
+    assert_eq!(system.registers().v[0], 5);
+    assert_eq!(debugger.condition(id).unwrap().to_string(), "V0 == 5");
+
+    assert!(debugger.remove_condition(id));
+    assert!(debugger.condition(id).is_none());
+    assert_eq!(debugger.run_frame(&mut system).unwrap(), None);
+}
+
+// touches everything an instruction can change: registers, memory, the stack,
+// the screen, the rng and the timers
+const HISTORY_ROM: &str = "
+    LD V3, 30
+    LD DT, V3
+loop:
+    LD I, 0x300
+    RND V2, 0xFF
+    ADD V0, 1
+    LD [I], V0
+    LD F, V0
+    DRW V0, V2, 5
+    CALL sub
+    JP loop
+sub:
+    RET";
+
+fn history_system() -> System<Framebuffer> {
+    let config = Config {
+        speed: Speed::InstructionsPerFrame(7),
+        ..Config::xochip()
+    };
+    let mut system = System::create(Framebuffer::create(), config);
+    system
+        .load_rom_data(assemble(HISTORY_ROM).unwrap().rom)
+        .unwrap();
+    system
+}
+
+#[test]
+fn stepping_back_undoes_the_whole_machine() {
+    let mut system = history_system();
+    let mut debugger = Debugger::new();
+    system.set_history(1000);
+
+    let mut states = Vec::new();
+    for _ in 0..100 {
+        states.push((system.save_state(), system.vram().to_vec()));
+        debugger.step(&mut system).unwrap();
+    }
+    assert_eq!(system.history_len(), 100);
+
+    while let Some((state, vram)) = states.pop() {
+        assert!(debugger.step_back(&mut system));
+        assert!(system.save_state() == state, "{} steps in", states.len());
+        assert_eq!(system.vram(), &vram[..]);
+    }
+    assert!(!debugger.step_back(&mut system));
+
+    // and runs forward the same way again
+    let before = system.save_state();
+    for _ in 0..100 {
+        debugger.step(&mut system).unwrap();
+    }
+    for _ in 0..100 {
+        system.step_back();
+    }
+    assert!(system.save_state() == before);
+}
+
+#[test]
+fn history_keeps_the_last_instructions() {
+    let mut system = history_system();
+
+    // off to start with
+    system.step().unwrap();
+    assert!(!system.step_back());
+    assert_eq!(system.last_pc(), None);
+
+    system.set_history(3);
+    for _ in 0..10 {
+        system.step().unwrap();
+    }
+    assert_eq!(system.history_len(), 3);
+    for _ in 0..3 {
+        assert!(system.step_back());
+    }
+    assert!(!system.step_back());
+}
+
+#[test]
+fn last_write_and_last_pc() {
+    let mut system = history_system();
+    system.set_history(100);
+
+    // LD V3, LD DT, LD I, RND, ADD, LD [I]
+    for _ in 0..6 {
+        system.step().unwrap();
+    }
+    assert_eq!(system.last_pc(), Some(0x20A));
+    assert_eq!(
+        system.last_write(0x300),
+        Some(MemoryWrite {
+            cycle: 5,
+            pc: 0x20A,
+            old: 0,
+            new: 1
+        })
+    );
+    assert_eq!(system.last_write(0x301), None);
+    assert_eq!(system.last_write(0xFFFF), None);
+}
+
+#[test]
+fn reverse_continue_stops_at_breakpoints() {
+    let mut system = history_system();
+    let mut debugger = Debugger::new();
+    system.set_history(1000);
+    for _ in 0..50 {
+        debugger.step(&mut system).unwrap();
+    }
+
+    // back to the instruction at the breakpoint, undone
+    debugger.add_breakpoint(0x20C);
+    assert_eq!(
+        debugger.reverse_continue(&mut system),
+        Stop::Breakpoint(0x20C)
+    );
+    assert_eq!(system.registers().pc, 0x20C);
+
+    debugger.remove_breakpoint(0x20C);
+    let id = debugger.add_condition(Condition::parse("V0 == 2").unwrap());
+    assert!(matches!(
+        debugger.reverse_continue(&mut system),
+        Stop::Condition { id: stopped, .. } if stopped == id
+    ));
+    assert_eq!(system.registers().v[0], 2);
+
+    debugger.remove_condition(id);
+    assert_eq!(debugger.reverse_continue(&mut system), Stop::HistoryStart);
+    assert_eq!(system.registers().pc, 0x200);
+}
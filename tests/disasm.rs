@@ -0,0 +1,75 @@
+use emulator::{disassemble, Syntax};
+
+// draws a sprite that sits after the code, behind a jump to itself:
+//
+//     LD I, 0x210
+//     CALL 0x20E
+//     SE V0, 0
+//     LD I, LONG 0x210
+//     DRW V0, V1, 5
+//     JP 0x20C
+//     RET
+//     db 0xF0, 0x90, 0xF0, 0x90, 0xF0
+const ROM: [u8; 21] = [
+    0xA2, 0x10, 0x22, 0x0E, 0x30, 0x00, 0xF0, 0x00, 0x02, 0x10, 0xD0, 0x15, 0x12, 0x0C, 0x00, 0xEE,
+    0xF0, 0x90, 0xF0, 0x90, 0xF0,
+];
+
+// the flow pass finds the code, so the sprite after the jump is listed as bytes
+// and everything jumped to, called or pointed at by I gets a label
+#[test]
+fn code_and_data_are_told_apart() {
+    let listing = disassemble(&ROM, Syntax::Cowgod);
+    let expected = "\
+main:
+    LD I, data_210              ; 200  A210
+    CALL sub_20E                ; 202  220E
+    SE V0, 0x00                 ; 204  3000
+    LD I, LONG data_210         ; 206  F000 0210
+    DRW V0, V1, 5               ; 20A  D015
+label_20C:
+    JP label_20C                ; 20C  120C
+sub_20E:
+    RET                         ; 20E  00EE
+data_210:
+    db 0xF0, 0x90, 0xF0, 0x90, 0xF0 ; 210
+";
+    assert_eq!(listing, expected);
+}
+
+#[test]
+fn octo_listing() {
+    let listing = disassemble(&ROM, Syntax::Octo);
+    let expected = "\
+: main
+    i := data_210               # 200  A210
+    sub_20E                     # 202  220E
+    if v0 != 0x00 then          # 204  3000
+    i := long data_210          # 206  F000 0210
+    sprite v0 v1 5              # 20A  D015
+: label_20C
+    jump label_20C              # 20C  120C
+: sub_20E
+    return                      # 20E  00EE
+: data_210
+    0xF0 0x90 0xF0 0x90 0xF0    # 210
+";
+    assert_eq!(listing, expected);
+}
+
+// bytes that would make an instruction are still data when nothing runs them,
+// and a word that isn't an instruction stops the flow
+#[test]
+fn unreachable_words_are_data() {
+    // JP 0x206; LD V0, 1; CLS; 0xFFFF; LD V1, 2
+    let rom = [0x12, 0x06, 0x60, 0x01, 0x00, 0xE0, 0xFF, 0xFF, 0x61, 0x02];
+    let listing = disassemble(&rom, Syntax::Cowgod);
+    let expected = "\
+main:
+    JP label_206                ; 200  1206
+    db 0x60, 0x01, 0x00, 0xE0   ; 202
+label_206:
+    db 0xFF, 0xFF, 0x61, 0x02   ; 206
+";
+    assert_eq!(listing, expected);
+}
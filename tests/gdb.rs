@@ -0,0 +1,221 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+// LD V0, 0x7B; LD I, 0x300; LD B, V0; LD V2, [I]; ADD V0, 1; JP 0x200
+const ROM: [u8; 12] = [
+    0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33, 0xF2, 0x65, 0x70, 0x01, 0x12, 0x00,
+];
+
+// a gdb remote protocol client talking to `chip8 gdb`, the way gdb would
+struct Client {
+    server: Child,
+    stream: TcpStream,
+    rom: PathBuf,
+}
+
+impl Client {
+    fn start(name: &str) -> Client {
+        let rom =
+            std::env::temp_dir().join(format!("chip8-gdb-{}-{}.ch8", name, std::process::id()));
+        std::fs::write(&rom, ROM).unwrap();
+
+        // a port nothing else is using
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut server = Command::new(env!("CARGO_BIN_EXE_chip8"))
+            .args(["gdb", "--headless", "--port", &port.to_string()])
+            .arg(&rom)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+                return Client {
+                    server,
+                    stream,
+                    rom,
+                };
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        server.kill().ok();
+        server.wait().ok();
+        std::fs::remove_file(rom).ok();
+        panic!("the server never started listening");
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        self.stream.write_all(bytes).unwrap();
+    }
+
+    // send a packet, check it was acked and return the reply
+    fn packet(&mut self, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.send_raw(format!("${}#{:02x}", data, checksum).as_bytes());
+        assert_eq!(self.byte(), b'+', "ack for {}", data);
+        self.reply()
+    }
+
+    // the next packet from the server, checking its checksum and acking it
+    fn reply(&mut self) -> String {
+        assert_eq!(self.byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+
+        let checksum = [self.byte(), self.byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(
+            sum,
+            checksum,
+            "checksum of {:?}",
+            String::from_utf8_lossy(&data)
+        );
+
+        self.send_raw(b"+");
+        String::from_utf8(data).unwrap()
+    }
+
+    // the PC, register 0x11
+    fn pc(&mut self) -> String {
+        self.packet("p11")
+    }
+
+    fn detach(mut self) {
+        assert_eq!(self.packet("D"), "OK");
+        assert!(self.server.wait().unwrap().success());
+    }
+}
+
+// a test that fails part way doesn't leave the server running, and the rom
+// doesn't outlive the test
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.server.kill().ok();
+        self.server.wait().ok();
+        std::fs::remove_file(&self.rom).ok();
+    }
+}
+
+#[test]
+fn registers() {
+    let mut gdb = Client::start("registers");
+
+    assert_eq!(gdb.packet("?"), "S05");
+    // V0-VF, I, PC, SP, DT and ST, 16 bit registers big-endian
+    let regs = format!("{}0000020000{:02x}{:02x}", "00".repeat(16), 0x3C, 0x3C);
+    assert_eq!(gdb.packet("g"), regs);
+
+    let mut changed = String::new();
+    for v in 0..16 {
+        changed.push_str(&format!("{:02x}", v * 3));
+    }
+    changed.push_str("01230206000506");
+    assert_eq!(gdb.packet(&format!("G{}", changed)), "OK");
+    assert_eq!(gdb.packet("g"), changed);
+    assert_eq!(gdb.pc(), "0206");
+
+    gdb.detach();
+}
+
+#[test]
+fn memory() {
+    let mut gdb = Client::start("memory");
+
+    assert_eq!(gdb.packet("m200,c"), "607ba300f033f26570011200");
+    assert_eq!(gdb.packet("M300,3:abcdef"), "OK");
+    assert_eq!(gdb.packet("m300,3"), "abcdef");
+    assert_eq!(gdb.packet("X302,2:xy"), "OK");
+    assert_eq!(gdb.packet("m300,4"), "abcd7879");
+
+    // writes past the end of memory, with the wrong length or digits that
+    // aren't hex are refused
+    assert_eq!(gdb.packet("Mfff,2:abcd"), "E01");
+    assert_eq!(gdb.packet("M300,2:abc"), "E01");
+    assert_eq!(gdb.packet("M300,2:a\u{e9}a"), "E01");
+    assert_eq!(gdb.packet("m300,1"), "ab");
+
+    // a length running past the end of memory reads up to the end
+    let rest = gdb.packet("m200,ffffffffffffffff");
+    assert_eq!(rest.len(), (0x1000 - 0x200) * 2);
+    assert_eq!(gdb.packet("m1000,1"), "E01");
+
+    gdb.detach();
+}
+
+#[test]
+fn breakpoints_and_stepping() {
+    let mut gdb = Client::start("breakpoints");
+
+    assert_eq!(gdb.packet("s"), "S05");
+    assert_eq!(gdb.pc(), "0202");
+
+    assert_eq!(gdb.packet("Z0,206,2"), "OK");
+    assert_eq!(gdb.packet("c"), "S05");
+    assert_eq!(gdb.pc(), "0206");
+
+    // around the loop and back to the breakpoint
+    assert_eq!(gdb.packet("c"), "S05");
+    assert_eq!(gdb.pc(), "0206");
+    assert_eq!(gdb.packet("m300,3"), "010203");
+
+    assert_eq!(gdb.packet("z0,206,2"), "OK");
+    assert_eq!(gdb.packet("Z0,20a,2"), "OK");
+    assert_eq!(gdb.packet("c"), "S05");
+    assert_eq!(gdb.pc(), "020a");
+
+    gdb.detach();
+}
+
+#[test]
+fn target_description() {
+    let mut gdb = Client::start("xfer");
+
+    let supported = gdb.packet("qSupported:multiprocess+");
+    assert!(supported.contains("qXfer:features:read+"), "{}", supported);
+
+    let xml = gdb.packet("qXfer:features:read:target.xml:0,ffffffffffffffff");
+    assert!(xml.starts_with("l<?xml"), "{}", xml);
+    assert!(xml.contains("<reg name=\"pc\""), "{}", xml);
+
+    // read in pieces, m while there is more
+    let first = gdb.packet("qXfer:features:read:target.xml:0,10");
+    assert_eq!(first, format!("m{}", &xml[1..0x11]));
+
+    gdb.detach();
+}
+
+#[test]
+fn bad_checksum_is_nacked() {
+    let mut gdb = Client::start("checksum");
+
+    gdb.send_raw(b"$g#00");
+    assert_eq!(gdb.byte(), b'-');
+
+    // and the connection carries on
+    assert_eq!(gdb.packet("?"), "S05");
+    gdb.detach();
+}
@@ -0,0 +1,73 @@
+use emulator::{EmulatorError, KeyMap};
+
+#[test]
+fn built_in_layouts() {
+    let qwerty = KeyMap::qwerty();
+    assert_eq!(qwerty, KeyMap::default());
+    assert_eq!(qwerty.keypad_key("4"), Some(0xC));
+    assert_eq!(qwerty.keypad_key("x"), Some(0x0));
+    assert_eq!(qwerty.keypad_key("Space"), None);
+
+    let hex = KeyMap::hex();
+    assert_eq!(hex.keypad_key("4"), Some(0x4));
+    assert_eq!(hex.keypad_key("f"), Some(0xF));
+    assert_eq!(hex.keypad_key("Q"), None);
+}
+
+#[test]
+fn parse_key_maps() {
+    let map = KeyMap::parse(
+        "# movement
+         Up = 5
+         left=0x7   # with a prefix
+
+         Right = a
+         LEFT = 8",
+    )
+    .unwrap();
+
+    // names are looked up without regard to case and a later line wins
+    assert_eq!(map.keypad_key("up"), Some(0x5));
+    assert_eq!(map.keypad_key("Left"), Some(0x8));
+    assert_eq!(map.keypad_key("RIGHT"), Some(0xA));
+    assert_eq!(map.keypad_key("Down"), None);
+    assert_eq!(map.keypad_key("1"), None);
+
+    assert_eq!(KeyMap::parse("").unwrap().keypad_key("1"), None);
+    assert_eq!(
+        KeyMap::parse("# nothing\n\n").unwrap(),
+        KeyMap::parse("").unwrap()
+    );
+}
+
+#[test]
+fn set_replaces_a_key() {
+    let mut map = KeyMap::qwerty();
+    map.set("q", 0xB);
+    map.set("Space", 0x15);
+    assert_eq!(map.keypad_key("Q"), Some(0xB));
+    assert_eq!(map.keypad_key("space"), Some(0x5));
+}
+
+// the line number and the entry of the first bad line
+fn invalid(text: &str) -> (usize, String) {
+    match KeyMap::parse(text) {
+        Err(EmulatorError::InvalidKeyMap { line, text }) => (line, text),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn bad_key_maps_say_where() {
+    let bad = |line: usize, text: &str| (line, text.to_string());
+
+    assert_eq!(invalid("Up = 5\nDown 6"), bad(2, "Down 6"));
+    assert_eq!(invalid("# keys\n\n  Up = G  # no"), bad(3, "Up = G"));
+    assert_eq!(invalid("Up = 10"), bad(1, "Up = 10"));
+    assert_eq!(invalid("Up ="), bad(1, "Up ="));
+    assert_eq!(invalid(" = 5"), bad(1, "= 5"));
+    assert_eq!(invalid("Up = -1"), bad(1, "Up = -1"));
+
+    let error = KeyMap::parse("\nDown 6").unwrap_err();
+    assert_eq!(error.to_string(), "invalid key map entry on line 2: Down 6");
+}
@@ -0,0 +1,122 @@
+use emulator::{assemble, Config, Framebuffer, Keypad, Quirks, System};
+
+fn system(config: Config, source: &str) -> System<Framebuffer> {
+    let mut system = System::create(Framebuffer::create(), config);
+    system.load_rom_data(assemble(source).unwrap().rom).unwrap();
+    system
+}
+
+#[test]
+fn keys_are_held_independently() {
+    let mut keypad = Keypad::new();
+    keypad.press(0x3);
+    keypad.press(0xA);
+    keypad.press(0x1F);
+    assert!(keypad.is_pressed(0x3) && keypad.is_pressed(0xA) && keypad.is_pressed(0xF));
+    assert_eq!(keypad.first_pressed(), Some(0x3));
+    assert_eq!(keypad.bits(), 0b1000_0100_0000_1000);
+    assert_eq!(Keypad::from_bits(keypad.bits()), keypad);
+
+    keypad.release(0x3);
+    assert!(!keypad.is_pressed(0x3) && keypad.is_pressed(0xA));
+    assert_eq!(keypad.first_pressed(), Some(0xA));
+
+    keypad.clear();
+    assert_eq!((keypad.bits(), keypad.first_pressed()), (0, None));
+}
+
+// Ex9E and ExA1 see every key that is down, not just the last one pressed
+#[test]
+fn skips_see_every_key_held() {
+    let mut system = system(
+        Config::chip8(),
+        "LD V0, 3
+         LD V1, 10
+         LD V2, 5
+         LD V3, 0x13
+         SKP V0
+         LD V4, 1
+         SKP V1
+         LD V5, 1
+         SKP V2
+         LD V6, 1
+         SKNP V2
+         LD V7, 1
+         SKNP V0
+         LD V8, 1
+         SKP V3
+         LD V9, 1
+     end:
+         JP end",
+    );
+    system.keypad_mut().press(3);
+    system.keypad_mut().press(10);
+    system.run_cycles(30).unwrap();
+
+    // V4, V5, V7 and V9 are skipped over. Only the low nibble of V3 is the key.
+    let v = system.registers().v;
+    assert_eq!(v[4..10], [0, 0, 1, 0, 1, 0]);
+}
+
+const WAIT: &str = "LD V0, K
+                    LD V1, 1
+                end:
+                    JP end";
+
+fn waiting(system: &mut System<Framebuffer>) -> bool {
+    system.step().unwrap().waiting
+}
+
+// Fx0A takes the key when it is let go, like the VIP
+#[test]
+fn key_wait_completes_on_release() {
+    let mut system = system(Config::chip8(), WAIT);
+    assert!(waiting(&mut system));
+
+    system.keypad_mut().press(0xB);
+    assert!(waiting(&mut system));
+    assert!(waiting(&mut system));
+    // a second key pressed meanwhile doesn't replace the first
+    system.keypad_mut().press(0x2);
+    system.keypad_mut().release(0xB);
+    assert!(!waiting(&mut system));
+    assert_eq!(system.registers().v[0], 0xB);
+    system.step().unwrap();
+    assert_eq!(system.registers().v[1], 1);
+}
+
+// a key already down when the wait starts has to be let go and pressed again
+#[test]
+fn key_wait_ignores_keys_held_before_it() {
+    let mut system = system(Config::chip8(), WAIT);
+    system.keypad_mut().press(0x5);
+    assert!(waiting(&mut system));
+    assert!(waiting(&mut system));
+
+    system.keypad_mut().release(0x5);
+    assert!(waiting(&mut system));
+    system.keypad_mut().press(0x5);
+    assert!(waiting(&mut system));
+    system.keypad_mut().release(0x5);
+    assert!(!waiting(&mut system));
+    assert_eq!(system.registers().v[0], 0x5);
+}
+
+// with the key_press quirk the key is taken as soon as it goes down
+#[test]
+fn key_press_quirk_completes_on_press() {
+    let config = Config {
+        quirks: Quirks {
+            key_press: true,
+            ..Quirks::cosmac_vip()
+        },
+        ..Config::chip8()
+    };
+    let mut system = system(config, WAIT);
+    // the key held from before still doesn't count
+    system.keypad_mut().press(0x9);
+    assert!(waiting(&mut system));
+    system.keypad_mut().press(0xE);
+    assert!(!waiting(&mut system));
+    assert_eq!(system.registers().v[0], 0xE);
+}
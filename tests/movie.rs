@@ -0,0 +1,130 @@
+use emulator::{Config, EmulatorError, Framebuffer, Movie, Speed, System};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// count the frames key 5 is held down in V2, and roll a random number every
+// frame so playback has to restore the rng too:
+//     LD V1, 5
+// loop:
+//     RND V3, 0xFF
+//     SKNP V1
+//     ADD V2, 1
+//     JP loop
+const ROM: [u8; 10] = [0x61, 0x05, 0xC3, 0xFF, 0xE1, 0xA1, 0x72, 0x01, 0x12, 0x02];
+
+// a system running rom from a file of its own, the tests run at the same time
+fn load(config: Config, rom: &[u8]) -> System<Framebuffer> {
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        "chip8-movie-{}-{}.ch8",
+        std::process::id(),
+        FILES.fetch_add(1, Ordering::Relaxed)
+    );
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, rom).unwrap();
+
+    let mut system = System::create(Framebuffer::create(), config);
+    system.load_rom(&path).unwrap();
+    std::fs::remove_file(path).ok();
+    system
+}
+
+fn system() -> System<Framebuffer> {
+    let config = Config {
+        speed: Speed::InstructionsPerFrame(4),
+        ..Config::xochip()
+    };
+    load(config, &ROM)
+}
+
+// record holding key 5 on every third frame
+fn record() -> (Movie, System<Framebuffer>) {
+    let mut system = system();
+    system.run_frame().unwrap();
+    system.record();
+    for frame in 0..30 {
+        match frame % 3 {
+            0 => system.keypad_mut().press(5),
+            _ => system.keypad_mut().release(5),
+        }
+        system.run_frame().unwrap();
+    }
+    (system.stop_recording().unwrap(), system)
+}
+
+// the CRC-32 the movie container checks its chunks with
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[test]
+fn round_trip_and_playback() {
+    let (movie, recorded) = record();
+    assert_eq!(movie.len(), 30);
+    assert!(movie.keypad(0).unwrap().is_pressed(5));
+    assert!(!movie.keypad(1).unwrap().is_pressed(5));
+    assert!(movie.keypad(30).is_none());
+
+    let bytes = movie.to_bytes();
+    let movie = Movie::from_bytes(&bytes).unwrap();
+    assert_eq!(movie.to_bytes(), bytes);
+
+    // played back from a different point it gets to the same place
+    let mut system = system();
+    for _ in 0..7 {
+        system.run_frame().unwrap();
+    }
+    system.play(movie).unwrap();
+    for _ in 0..30 {
+        assert!(system.playing());
+        system.run_frame().unwrap();
+    }
+    assert_eq!(system.save_state(), recorded.save_state());
+
+    system.run_frame().unwrap();
+    assert!(!system.playing());
+}
+
+#[test]
+fn corrupt_movies_are_refused() {
+    let bytes = record().0.to_bytes();
+
+    let invalid = |bytes: &[u8]| match Movie::from_bytes(bytes) {
+        Err(EmulatorError::InvalidMovie(_)) => {}
+        other => panic!("{:?}", other),
+    };
+    invalid(&bytes[..bytes.len() - 1]);
+    invalid(b"C8MV");
+    invalid(b"C8ST\x00\x01");
+
+    let mut flipped = bytes.clone();
+    flipped[20] ^= 1;
+    invalid(&flipped);
+
+    // KEYS is the last chunk and ends with the length of the last run. A run
+    // of 4 billion frames is refused, even with a good checksum.
+    let mut long = bytes.clone();
+    let end = long.len();
+    long[end - 4..].copy_from_slice(&u32::MAX.to_be_bytes());
+    let crc = crc32(&long[14..]);
+    long[10..14].copy_from_slice(&crc.to_be_bytes());
+    invalid(&long);
+}
+
+#[test]
+fn movies_only_play_on_their_rom() {
+    let (movie, _) = record();
+
+    let mut other = load(Config::xochip(), &[0x12, 0x00]);
+    assert!(matches!(
+        other.play(movie),
+        Err(EmulatorError::InvalidMovie(_))
+    ));
+}
@@ -0,0 +1,182 @@
+use emulator::{compile_octo, Config, EmulatorError, Framebuffer, Mode, System};
+
+// run an Octo program for a while and return the system to look at
+fn run(source: &str) -> System<Framebuffer> {
+    let program = compile_octo(source, Mode::XoChip).unwrap();
+    let mut system = System::create(Framebuffer::create(), Config::xochip());
+    system.load_rom_data(program.rom).unwrap();
+    for _ in 0..100 {
+        system.step().unwrap();
+    }
+    system
+}
+
+// every comparison gives the answer it should, against a register and a
+// number, with if .. then and with if .. begin .. else .. end
+#[test]
+fn comparisons() {
+    for op in ["==", "!=", "<", ">", "<=", ">="] {
+        for (a, b) in [(3u8, 5u8), (5, 3), (4, 4), (0, 255), (255, 0)] {
+            let source = format!(
+                ": main
+                    v0 := {a} v1 := {b} v2 := 0 v3 := 0 v4 := 2
+                    if v0 {op} v1 then v2 := 1
+                    if v0 {op} {b} then v3 := 1
+                    if v0 {op} v1 begin v4 := 1 else v4 := 0 end
+                    loop again"
+            );
+            let v = run(&source).registers().v;
+
+            let expected = match op {
+                "==" => a == b,
+                "!=" => a != b,
+                "<" => a < b,
+                ">" => a > b,
+                "<=" => a <= b,
+                _ => a >= b,
+            } as u8;
+            assert_eq!(v[2], expected, "v0 {} v1 with {} and {}", op, a, b);
+            assert_eq!(v[3], expected, "v0 {} {} with v0 {}", op, b, a);
+            assert_eq!(v[4], expected, "begin v0 {} v1 with {} and {}", op, a, b);
+            // the registers compared are left alone
+            assert_eq!((v[0], v[1]), (a, b));
+        }
+    }
+}
+
+// VF ends up holding the flag, not the result, when it is the destination
+#[test]
+fn flag_wins_over_result_in_vf() {
+    let v = run(": main
+            vf := 200 v1 := 100 vf += v1 v2 := vf
+            vf := 5 v1 := 3 vf -= v1 v3 := vf
+            vf := 3 v1 := 3 vf -= v1 v4 := vf
+            vf := 3 v1 := 5 vf =- v1 v5 := vf
+            loop again")
+    .registers()
+    .v;
+
+    assert_eq!(v[2], 1, "carry");
+    assert_eq!(v[3], 1, "no borrow");
+    assert_eq!(v[4], 1, "equal values don't borrow");
+    assert_eq!(v[5], 1, "no borrow from subn");
+}
+
+// the directives, flow control and subroutines all come out the way Octo
+// would run them
+#[test]
+fn language() {
+    let program = compile_octo(
+        ":alias counter v5
+         :const LIMIT 5
+         :calc DOUBLE { LIMIT * 2 }
+         :macro add-twice reg n { reg += n reg += n }
+
+         : data 1 2 3
+         : double-it v6 += v6 ;
+
+         : main
+            counter := 0
+            loop
+                add-twice counter 1
+                while counter != DOUBLE
+            again
+            v6 := LIMIT
+            double-it
+            i := data
+            load v2
+            v7 := v2
+            v8 := v0
+            :unpack 0xA data
+            loop again",
+        Mode::XoChip,
+    )
+    .unwrap();
+    // data comes before main, so a jump to main goes first
+    assert_eq!(program.rom[..5], [0x12, 0x09, 1, 2, 3]);
+
+    let mut system = System::create(Framebuffer::create(), Config::xochip());
+    system.load_rom_data(program.rom).unwrap();
+    for _ in 0..100 {
+        system.step().unwrap();
+    }
+    let v = system.registers().v;
+    assert_eq!(v[5], 10, "loop .. while .. again");
+    assert_eq!(v[6], 10, "subroutine");
+    assert_eq!((v[7], v[8]), (3, 1), "load");
+    assert_eq!((v[0], v[1]), (0xA2, 0x02), ":unpack");
+}
+
+#[test]
+fn errors() {
+    let error = |source: &str, mode: Mode| match compile_octo(source, mode) {
+        Err(EmulatorError::Assemble {
+            line,
+            column,
+            message,
+            ..
+        }) => (line, column, message),
+        other => panic!("{:?} from {}", other, source),
+    };
+
+    assert_eq!(
+        error(": main\n  v0 := nothing", Mode::XoChip),
+        (2, 9, "nothing isn't defined".to_string())
+    );
+    assert_eq!(
+        error(": main plane 1", Mode::SuperChip),
+        (1, 8, "plane needs XO-CHIP".to_string())
+    );
+    assert_eq!(
+        error(": main hires", Mode::Chip8).2,
+        "hires needs SUPER-CHIP"
+    );
+    assert_eq!(
+        error(": main\nif v0 == 1 begin v1 := 2", Mode::XoChip),
+        (2, 1, "if without an end".to_string())
+    );
+    assert_eq!(
+        error(": main loop v0 += 1", Mode::XoChip).2,
+        "loop without an again"
+    );
+    assert_eq!(
+        error(":macro m { m } : main m", Mode::XoChip).2,
+        "m expands forever"
+    );
+    assert_eq!(
+        error(": main v0 := 256", Mode::XoChip).2,
+        "256 is out of range, it needs to be -128 to 255"
+    );
+}
+
+// :unpack takes constants as well as labels, and :next labels the address of
+// i := long rather than the F000 half of it
+#[test]
+fn unpack_and_next() {
+    let program = compile_octo(
+        ": main
+            :unpack 0xB SPOT
+            v2 := v0
+            v3 := v1
+            :next target i := long 0
+            :next byte v4 := 7
+            :unpack long target
+            v5 := v0
+            v6 := v1
+            :unpack 0 byte
+            :const SPOT 0x345",
+        Mode::XoChip,
+    )
+    .unwrap();
+    assert_eq!(program.rom[8..12], [0xF0, 0x00, 0x00, 0x00]);
+
+    let mut system = System::create(Framebuffer::create(), Config::xochip());
+    system.load_rom_data(program.rom).unwrap();
+    for _ in 0..12 {
+        system.step().unwrap();
+    }
+    let v = system.registers().v;
+    assert_eq!((v[2], v[3]), (0xB3, 0x45), "constant");
+    assert_eq!((v[5], v[6]), (0x02, 0x0A), ":next i := long");
+    assert_eq!((v[0], v[1]), (0x02, 0x0D), ":next");
+}
@@ -0,0 +1,36 @@
+use emulator::{assemble, disassemble_instruction, Op, Syntax};
+
+// every word that decodes encodes back to itself, and the op it decodes to
+// survives the trip too
+#[test]
+fn every_word_round_trips() {
+    let mut instructions = 0;
+
+    for word in 0..=u16::MAX {
+        if let Ok(op) = Op::decode(word) {
+            assert_eq!(op.encode(), word, "{:?} from {:04X}", op, word);
+            assert_eq!(Op::decode(op.encode()).ok(), Some(op));
+            instructions += 1;
+        }
+    }
+
+    // 0: 39, 1-4, 6, 7, A-D: 10 * 4096, 5: 3 * 256, 8: 9 * 256, 9: 256,
+    // E: 2 * 16, F: 14 * 16 + 2
+    assert_eq!(instructions, 44_585);
+}
+
+// the opcode table the disassembler and assembler use knows exactly the words
+// Op does, and assembles each of them back to the same word
+#[test]
+fn op_agrees_with_opcode_table() {
+    for word in 0..=u16::MAX {
+        let text = disassemble_instruction(word, 0x1234, Syntax::Cowgod);
+        assert_eq!(Op::decode(word).is_ok(), text.is_some(), "{:04X}", word);
+
+        if let (Ok(op), Some(text)) = (Op::decode(word), text) {
+            let rom = assemble(&text).unwrap().rom;
+            assert_eq!(rom.len() as u16, op.size(), "{}", text);
+            assert_eq!(rom[..2], word.to_be_bytes(), "{}", text);
+        }
+    }
+}
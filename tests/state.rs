@@ -0,0 +1,146 @@
+use emulator::{Command, Config, EmulatorError, Framebuffer, Stopped, System};
+
+use std::path::PathBuf;
+
+// count up in V0 and write the count to memory forever:
+//     LD I, 0x300
+// loop:
+//     ADD V0, 1
+//     LD [I], V0
+//     JP loop
+const ROM: [u8; 8] = [0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x02];
+
+// a system running ROM from a file of its own, so its save state slots don't
+// clash with other tests
+fn system(name: &str) -> (System<Framebuffer>, PathBuf) {
+    let rom = std::env::temp_dir().join(format!("chip8-{}-{}.ch8", name, std::process::id()));
+    std::fs::write(&rom, ROM).unwrap();
+
+    let mut system = System::create(Framebuffer::create(), Config::xochip());
+    system.load_rom(&rom).unwrap();
+    (system, rom)
+}
+
+fn remove(rom: PathBuf, slots: &[u8]) {
+    for slot in slots {
+        let mut state = rom.clone().into_os_string();
+        state.push(format!(".state{}", slot));
+        std::fs::remove_file(state).ok();
+    }
+    std::fs::remove_file(rom).ok();
+}
+
+#[test]
+fn save_state_round_trip() {
+    let (mut system, rom) = system("state");
+    for _ in 0..3 {
+        system.run_frame().unwrap();
+    }
+    let state = system.save_state();
+    let vram = system.vram().to_vec();
+
+    for _ in 0..3 {
+        system.run_frame().unwrap();
+    }
+    assert_ne!(system.save_state(), state);
+
+    system.load_state(&state).unwrap();
+    assert_eq!(system.save_state(), state);
+    assert_eq!(system.vram(), &vram[..]);
+
+    // anything that isn't a whole, unchanged state is refused and leaves the
+    // machine alone
+    let mut corrupt = state.clone();
+    corrupt[20] ^= 1;
+    for bad in [&state[..state.len() - 1], &corrupt, b"C8ST"] {
+        assert!(matches!(
+            system.load_state(bad),
+            Err(EmulatorError::InvalidState(_))
+        ));
+        assert_eq!(system.save_state(), state);
+    }
+
+    remove(rom, &[]);
+}
+
+// run hands the outcome of saving and loading back instead of printing it
+#[test]
+fn run_reports_saving_and_loading() {
+    let (mut system, rom) = system("slots");
+    system.run_frame().unwrap();
+    let state = system.save_state();
+
+    system.video_mut().command(Command::SaveState(1));
+    assert!(matches!(system.run(), Ok(Stopped::SavedState(1, Ok(())))));
+
+    system.run_frame().unwrap();
+    system.video_mut().command(Command::LoadState(1));
+    assert!(matches!(system.run(), Ok(Stopped::LoadedState(1, Ok(())))));
+    assert_eq!(system.save_state(), state);
+
+    // nothing has been saved in slot 2
+    system.video_mut().command(Command::LoadState(2));
+    assert!(matches!(system.run(), Ok(Stopped::LoadedState(2, Err(_)))));
+    assert_eq!(system.save_state(), state);
+
+    system.video_mut().quit();
+    assert!(matches!(system.run(), Ok(Stopped::Quit)));
+
+    remove(rom, &[1]);
+}
+
+// the state at the start of each of n frames, and at the end
+fn run_frames(system: &mut System<Framebuffer>, n: usize) -> Vec<Vec<u8>> {
+    let mut states = Vec::new();
+    for _ in 0..n {
+        states.push(system.save_state());
+        system.run_frame().unwrap();
+    }
+    states.push(system.save_state());
+    states
+}
+
+#[test]
+fn rewind_goes_back_to_a_snapshot() {
+    let (mut system, rom) = system("rewind");
+    system.set_rewind(1, 100);
+    let before = run_frames(&mut system, 10);
+
+    assert_eq!(system.rewind(3).unwrap(), 3);
+    assert_eq!(system.save_state(), before[7]);
+    assert_eq!(system.rewind(0).unwrap(), 0);
+
+    // and runs forward from there the same way again
+    let again = run_frames(&mut system, 3);
+    assert_eq!(again[..], before[7..]);
+
+    // snapshots every 4 frames go back to the last one at or before the frame
+    system.set_rewind(4, 100);
+    let before = run_frames(&mut system, 10);
+    let rewound = system.rewind(1).unwrap() as usize;
+    assert!((1..=4).contains(&rewound), "{}", rewound);
+    assert_eq!(system.save_state(), before[10 - rewound]);
+
+    remove(rom, &[]);
+}
+
+#[test]
+fn rewind_only_goes_back_as_far_as_it_keeps() {
+    let (mut system, rom) = system("rewind-capacity");
+
+    system.set_rewind(1, 5);
+    let before = run_frames(&mut system, 20);
+    let rewound = system.rewind(100).unwrap() as usize;
+    assert!((1..=5).contains(&rewound), "{}", rewound);
+    assert_eq!(system.save_state(), before[20 - rewound]);
+
+    // nothing older is left
+    assert_eq!(system.rewind(100).unwrap(), 0);
+
+    // a capacity of 0 turns it off
+    system.set_rewind(1, 0);
+    run_frames(&mut system, 5);
+    assert_eq!(system.rewind(1).unwrap(), 0);
+
+    remove(rom, &[]);
+}
@@ -0,0 +1,154 @@
+use emulator::{assemble, Config, Events, Framebuffer, Speed, System};
+
+fn system(config: Config, source: &str) -> System<Framebuffer> {
+    let mut system = System::create(Framebuffer::create(), config);
+    system.load_rom_data(assemble(source).unwrap().rom).unwrap();
+    system
+}
+
+#[test]
+fn step_reports_what_the_instruction_did() {
+    let mut system = system(
+        Config::superchip(),
+        "LD V0, 0
+         LD ST, V0
+         LD V0, 5
+         DRW V1, V1, 1
+         CLS
+         LD ST, V0
+         LD V2, K
+         EXIT",
+    );
+    let mut step = || system.step().unwrap();
+
+    // the machine beeps for a second when it starts
+    assert!(step().sound);
+    assert_eq!(step(), Events::default());
+    assert_eq!(step(), Events::default());
+    assert_eq!(
+        step(),
+        Events {
+            drew: true,
+            ..Events::default()
+        }
+    );
+    assert_eq!(
+        step(),
+        Events {
+            cleared: true,
+            ..Events::default()
+        }
+    );
+    // the buzzer stays on while the sound timer runs
+    assert!(step().sound);
+    assert!(step().waiting);
+    assert!(step().waiting);
+    assert_eq!(system.registers().pc, 0x20E);
+
+    system.keypad_mut().press(7);
+    system.step().unwrap();
+    system.keypad_mut().release(7);
+    assert!(!system.step().unwrap().waiting);
+    assert_eq!(system.registers().v[2], 7);
+
+    assert!(system.step().unwrap().exited);
+    // and nothing runs after the program exits
+    let cycles = system.cycles();
+    assert!(system.step().unwrap().exited);
+    assert_eq!(system.cycles(), cycles);
+}
+
+#[test]
+fn run_cycles_stops_early_for_keys_and_exit() {
+    let source = "loop:
+                      ADD V0, 1
+                      SE V0, 10
+                      JP loop
+                      DRW V1, V1, 1
+                      LD V2, K
+                      EXIT";
+    let mut system = system(Config::superchip(), source);
+
+    let events = system.run_cycles(10).unwrap();
+    assert_eq!(system.cycles(), 10);
+    assert!(!events.drew && !events.waiting);
+
+    // the draw is merged into the events of the whole run, which stops at the
+    // key wait
+    let events = system.run_cycles(100).unwrap();
+    assert!(events.drew && events.waiting);
+    assert_eq!(system.registers().pc, 0x20A);
+    let cycles = system.cycles();
+    assert!(system.run_cycles(100).unwrap().waiting);
+    assert_eq!(system.cycles(), cycles);
+
+    system.keypad_mut().press(1);
+    system.run_cycles(1).unwrap();
+    system.keypad_mut().release(1);
+    let events = system.run_cycles(100).unwrap();
+    assert!(events.exited && !events.waiting);
+}
+
+#[test]
+fn run_frame_ticks_the_timers_after_the_frame() {
+    let config = Config {
+        speed: Speed::InstructionsPerFrame(3),
+        ..Config::chip8()
+    };
+    let mut system = system(
+        config,
+        "LD V0, 2
+         LD DT, V0
+         LD ST, V0
+     loop:
+         JP loop",
+    );
+
+    // ST is loaded by the frame's last instruction, then ticks once
+    let events = system.run_frame().unwrap();
+    let r = system.registers();
+    assert_eq!((r.dt, r.st, system.cycles()), (1, 1, 3));
+    assert!(events.sound);
+
+    // the buzzer goes off when the sound timer runs out
+    assert!(!system.run_frame().unwrap().sound);
+    let r = system.registers();
+    assert_eq!((r.dt, r.st, system.cycles()), (0, 0, 6));
+    system.run_frame().unwrap();
+    assert_eq!(system.registers().dt, 0);
+}
+
+// the instructions run in each of n frames at speed
+fn frames(speed: Speed, n: usize) -> Vec<u64> {
+    let config = Config {
+        speed,
+        ..Config::chip8()
+    };
+    let mut system = system(config, "loop: JP loop");
+    (0..n)
+        .map(|_| {
+            let before = system.cycles();
+            system.run_frame().unwrap();
+            system.cycles() - before
+        })
+        .collect()
+}
+
+#[test]
+fn speed_carries_the_remainder_between_frames() {
+    assert_eq!(frames(Speed::InstructionsPerFrame(7), 3), [7, 7, 7]);
+    assert_eq!(frames(Speed::Hz(600), 3), [10, 10, 10]);
+
+    // 90Hz is a frame and a half, 100Hz five instructions every three frames
+    assert_eq!(frames(Speed::Hz(90), 4), [1, 2, 1, 2]);
+    assert_eq!(frames(Speed::Hz(100), 6), [1, 2, 2, 1, 2, 2]);
+
+    // below 60Hz some frames run nothing
+    assert_eq!(frames(Speed::Hz(30), 4), [0, 1, 0, 1]);
+    assert_eq!(frames(Speed::Hz(0), 2), [0, 0]);
+
+    // over a second every rate runs exactly its number of instructions
+    for hz in [1, 59, 61, 500, 1000, 12345] {
+        assert_eq!(frames(Speed::Hz(hz), 60).iter().sum::<u64>(), hz as u64);
+    }
+}
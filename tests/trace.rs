@@ -0,0 +1,190 @@
+use emulator::{
+    assemble, first_difference, read_trace, Config, EmulatorError, Framebuffer, System, Trace,
+    TraceFormat, TraceLine,
+};
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+const ROM: &str = "
+    LD I, LONG 0x300
+loop:
+    ADD V0, 1
+    LD B, V0
+    JP loop";
+
+// a trace written to memory the test can still look at
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// the trace of the first n instructions of ROM
+fn record(n: usize, trace: impl FnOnce(Shared) -> Trace) -> Vec<u8> {
+    let out = Shared::default();
+    let mut system = System::create(Framebuffer::create(), Config::xochip());
+    system.load_rom_data(assemble(ROM).unwrap().rom).unwrap();
+    system.trace(trace(out.clone())).unwrap();
+    for _ in 0..n {
+        system.step().unwrap();
+    }
+    system.stop_trace().unwrap();
+    out.0.take()
+}
+
+fn invalid(result: Result<impl std::fmt::Debug, EmulatorError>) -> String {
+    match result {
+        Err(EmulatorError::InvalidTrace(reason)) => reason,
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn text_and_binary_traces_read_back_the_same() {
+    let text = record(10, |out| Trace::new(TraceFormat::Text, out));
+    let binary = record(10, |out| Trace::new(TraceFormat::Binary, out));
+    assert!(binary.starts_with(b"C8TR"));
+
+    let lines = read_trace(&text).unwrap();
+    assert_eq!(read_trace(&binary).unwrap(), lines);
+    assert_eq!(lines.len(), 10);
+
+    // the registers are the ones from before the instruction ran
+    assert_eq!((lines[0].opcode, lines[0].long), (0xF000, 0x300));
+    assert_eq!(lines[1].registers.i, 0x300);
+    assert_eq!(lines[1].registers.pc, 0x204);
+    assert_eq!((lines[1].opcode, lines[1].registers.v[0]), (0x7001, 0));
+    assert_eq!((lines[4].opcode, lines[4].registers.v[0]), (0x7001, 1));
+    assert_eq!(
+        lines.iter().map(|line| line.cycle).collect::<Vec<_>>(),
+        (0..10).collect::<Vec<_>>()
+    );
+
+    let first = String::from_utf8(text).unwrap();
+    let first = first.lines().next().unwrap();
+    assert!(first.contains("F0000300"), "{}", first);
+    assert!(first.contains("LD I, LONG"), "{}", first);
+}
+
+#[test]
+fn traces_can_be_limited_to_addresses_and_cycles() {
+    let adds = read_trace(&record(20, |out| {
+        let mut trace = Trace::new(TraceFormat::Text, out);
+        trace.set_addresses(0x204..=0x204);
+        trace
+    }))
+    .unwrap();
+    assert!(adds.iter().all(|line| line.opcode == 0x7001));
+    assert_eq!(adds.len(), 7);
+
+    let middle = read_trace(&record(20, |out| {
+        let mut trace = Trace::new(TraceFormat::Binary, out);
+        trace.set_cycles(5..=7);
+        trace
+    }))
+    .unwrap();
+    assert_eq!(
+        middle.iter().map(|line| line.cycle).collect::<Vec<_>>(),
+        [5, 6, 7]
+    );
+}
+
+#[test]
+fn lines_from_other_emulators() {
+    // registers in any order and case, 0x or not, the instruction text is
+    // skipped over
+    let mut line = String::from("12 0x0204 F0000300 anything at all i=0300 sp=1 DT=3B st=0x0");
+    for v in 0..16 {
+        line.push_str(&format!(" v{:X}={:02X}", v, v * 2));
+    }
+    let parsed = TraceLine::parse(&line).unwrap();
+    assert_eq!(parsed.cycle, 12);
+    assert_eq!((parsed.opcode, parsed.long), (0xF000, 0x300));
+    let r = parsed.registers;
+    assert_eq!((r.pc, r.i, r.sp, r.dt, r.st), (0x204, 0x300, 1, 0x3B, 0));
+    assert_eq!(r.v[0xF], 0x1E);
+
+    // and it prints back as the same line
+    assert_eq!(TraceLine::parse(&parsed.to_string()).unwrap(), parsed);
+
+    let all = "V0=0 V1=0 V2=0 V3=0 V4=0 V5=0 V6=0 V7=0 V8=0 V9=0 VA=0 VB=0 VC=0 VD=0 VE=0 \
+               VF=0 I=0 SP=0 DT=0";
+    assert_eq!(invalid(TraceLine::parse("")), "no cycle");
+    assert_eq!(invalid(TraceLine::parse("1")), "no pc");
+    assert_eq!(invalid(TraceLine::parse("x 0200 00E0")), "invalid cycle x");
+    assert_eq!(
+        invalid(TraceLine::parse("1 02g0 00E0")),
+        "invalid number 02g0"
+    );
+    assert_eq!(
+        invalid(TraceLine::parse("1 0200 aéééb V0=0")),
+        "invalid number aéééb"
+    );
+    assert_eq!(
+        invalid(TraceLine::parse("1 0200 00E0 VG=1")),
+        "there is no register VG"
+    );
+    assert_eq!(
+        invalid(TraceLine::parse("1 0200 00E0 V0=100")),
+        "256 is too big for V0"
+    );
+    assert_eq!(
+        invalid(TraceLine::parse(&format!("1 0200 00E0 {}", all))),
+        "no value for ST"
+    );
+}
+
+#[test]
+fn bad_traces_say_where() {
+    let text = record(3, |out| Trace::new(TraceFormat::Text, out));
+    let mut text = String::from_utf8(text).unwrap();
+    text.insert_str(0, "# a comment\n\n");
+    assert_eq!(read_trace(text.as_bytes()).unwrap().len(), 3);
+
+    text.push_str("3 0206 oops");
+    assert_eq!(
+        invalid(read_trace(text.as_bytes())),
+        "line 6: invalid number oops"
+    );
+
+    let binary = record(3, |out| Trace::new(TraceFormat::Binary, out));
+    assert_eq!(
+        invalid(read_trace(&binary[..binary.len() - 1])),
+        "the last record is cut short"
+    );
+    let mut newer = binary.clone();
+    newer[5] = 2;
+    assert_eq!(invalid(read_trace(&newer)), "the version isn't supported");
+    assert_eq!(
+        invalid(read_trace(&[0xFF, 0xFE])),
+        "neither text nor a binary trace"
+    );
+}
+
+#[test]
+fn first_difference_ignores_cycles() {
+    let a = read_trace(&record(10, |out| Trace::new(TraceFormat::Binary, out))).unwrap();
+    assert_eq!(first_difference(&a, &a), None);
+
+    let mut b = a.clone();
+    for line in &mut b {
+        line.cycle += 100;
+    }
+    assert_eq!(first_difference(&a, &b), None);
+
+    b[6].registers.v[3] = 1;
+    assert_eq!(first_difference(&a, &b), Some(6));
+
+    // a trace that stops early differs where it stops
+    assert_eq!(first_difference(&a, &a[..4]), Some(4));
+    assert_eq!(first_difference(&a[..4], &a), Some(4));
+}